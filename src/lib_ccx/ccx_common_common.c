@@ -75,6 +75,8 @@ int add_cc_sub_text(struct cc_subtitle *sub, char *str, LLONG start_time,
 	if (mode)
 		strncpy(sub->mode, mode, 4);
 	sub->got_output = 1;
+	sub->has_position = 0;
+	sub->has_confidence = 0;
 	sub->next = NULL;
 
 	return 0;
@@ -85,6 +85,18 @@ void init_options(struct ccx_s_options *options)
 	options->hardsubx_conf_thresh = 0.0;
 	options->hardsubx_hue = 0.0;
 	options->hardsubx_lum_thresh = 95.0;
+	options->hardsubx_roi_count = 0;
+	options->hardsubx_letterbox_frames = 0;
+	options->hardsubx_debug_dir = NULL;
+	options->hardsubx_change_thresh = 5.0;
+	options->hardsubx_merge_similarity = 80.0;
+	options->hardsubx_tessdata_dir = NULL;
+	options->hardsubx_psm = -1;
+	options->hardsubx_whitelist = NULL;
+	options->hardsubx_cue_position = 0;
+	options->hardsubx_ocr_json = NULL;
+	options->hardsubx_conf_comment = 0;
+	options->hardsubx_review_thresh = 0.0;
 	options->hardsubx_and_common = 0;
 
 	options->transcript_settings = ccx_encoders_default_transcript_settings;
@@ -146,6 +158,22 @@ void init_options(struct ccx_s_options *options)
 	options->settings_dtvcc.print_file_reports = 1;
 	options->settings_dtvcc.no_rollup = 0;
 	options->settings_dtvcc.report = NULL;
+	options->settings_dtvcc.print_json_report = 0;
+	options->settings_dtvcc.sequence_recovery = 0;
+	options->settings_dtvcc.capture_filename = NULL;
+	options->settings_dtvcc.replay_filename = NULL;
+	options->settings_dtvcc.compliance_check = 0;
+	options->settings_dtvcc.rotate_size = 0;
+	options->settings_dtvcc.outputs_count = 0;
+	options->settings_dtvcc.service_languages = NULL;
+	options->settings_dtvcc.time_offset = 0;
+	options->settings_dtvcc.time_scale = 1.0;
+	options->settings_dtvcc.clip_start = -1;
+	options->settings_dtvcc.clip_end = -1;
+	options->settings_dtvcc.min_duration = 0;
+	options->settings_dtvcc.max_duration = 0;
+	options->settings_dtvcc.min_gap = 0;
+	options->settings_dtvcc.merge_identical = 0;
 	memset(
 	    options->settings_dtvcc.services_enabled, 0,
 	    CCX_DTVCC_MAX_SERVICES * sizeof(options->settings_dtvcc.services_enabled[0]));
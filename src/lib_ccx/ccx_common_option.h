@@ -86,6 +86,18 @@ struct encoder_cfg
 	int extract_only_708; 								// 1 if only 708 subs extraction is enabled
 };
 
+#define HARDSUBX_MAX_ROIS 8
+
+struct hardsubx_roi
+{
+	// Rectangle of the frame which is searched for burned-in subtitles
+	float left;
+	float top;
+	float width;
+	float height;
+	int in_pixels; // 1 if the values are pixels, 0 if they are fractions of the frame size
+};
+
 struct ccx_s_options // Options from user parameters
 {
 	int extract;                                               // Extract 1st, 2nd or both fields
@@ -159,6 +171,19 @@ struct ccx_s_options // Options from user parameters
 	float hardsubx_conf_thresh;
 	float hardsubx_hue;
 	float hardsubx_lum_thresh;
+	struct hardsubx_roi hardsubx_rois[HARDSUBX_MAX_ROIS]; // Regions of the frame searched for subtitles
+	int hardsubx_roi_count;                               // 0 to use the default region of the mode
+	int hardsubx_letterbox_frames;                        // Number of frames to detect black bars from, 0 to disable
+	char *hardsubx_debug_dir;                             // Directory to write the images of every stage to, NULL to disable
+	float hardsubx_change_thresh;                         // Changed part of the subtitle region which triggers OCR, in percent
+	float hardsubx_merge_similarity;                      // Similarity of consecutive texts merged into one subtitle, in percent
+	char *hardsubx_tessdata_dir;                          // Directory of the .traineddata files, NULL to search for it
+	int hardsubx_psm;                                     // Tesseract page segmentation mode, -1 for the default
+	char *hardsubx_whitelist;                             // The only characters to recognize, NULL for all
+	int hardsubx_cue_position;                            // 1 to place the subtitles where they are burned in
+	char *hardsubx_ocr_json;                              // File to write the boxes of the recognized words to, NULL to disable
+	int hardsubx_conf_comment;                            // 1 to write the OCR confidence of every subtitle as an SRT comment
+	float hardsubx_review_thresh;                         // Subtitles with a word below this confidence need review, 0 to disable
 
 	ccx_encoders_transcript_format transcript_settings; // Keeps the settings for generating transcript output files.
 	enum ccx_output_date_format date_format;
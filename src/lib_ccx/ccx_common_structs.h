@@ -79,6 +79,17 @@ struct cc_subtitle
 	/** Used for DVB end time in ms */
 	int time_out;
 
+	/** Placement of CC_TEXT in percent of the frame, only used if has_position is set */
+	int has_position;
+	float line_percent;     // Top of the text
+	float position_percent; // Horizontal center of the text
+
+	/** OCR confidence of CC_TEXT in percent, only used if has_confidence is set */
+	int has_confidence;
+	float mean_confidence;
+	float min_confidence;
+	int needs_review; // The least confident word is below the review threshold
+
 	struct cc_subtitle *next;
 	struct cc_subtitle *prev;
 };
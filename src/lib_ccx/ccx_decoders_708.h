@@ -25,6 +25,7 @@
 #define OVERLAPPED_BY_HIGH_PRIORITY 1
 
 #define CCX_DTVCC_FILENAME_TEMPLATE ".p%u.svc%02u"
+#define CCX_DTVCC_MAX_OUTPUTS 8
 
 #define CCX_DTVCC_NO_LAST_SEQUENCE -1
 
@@ -311,7 +312,7 @@ typedef struct dtvcc_tv_screen
 typedef struct ccx_decoder_dtvcc_report
 {
 	int reset_count;
-	unsigned services[CCX_DTVCC_MAX_SERVICES];
+	unsigned services[CCX_DTVCC_MAX_SERVICES + 1]; // Indexed by service number, 0 is unused
 } ccx_decoder_dtvcc_report;
 
 typedef struct dtvcc_service_decoder
@@ -331,6 +332,23 @@ typedef struct ccx_decoder_dtvcc_settings
 	int active_services_count;
 	int services_enabled[CCX_DTVCC_MAX_SERVICES];
 	struct ccx_common_timing_ctx *timing;
+	int print_json_report; // Print decoder statistics as JSON at the end of the run
+	int sequence_recovery; // On lost packets: 0 - continue, 1 - flush and reset decoders, 2 - reset decoders
+	char *capture_filename; // Record all assembled packets to this file, NULL to disable
+	char *replay_filename; // Decode the packets of this capture instead of the input, NULL to disable
+	int compliance_check; // Report violations of CTA-708 instead of writing captions
+	int rotate_size; // Start a new output file after this many KB, 0 to disable
+	char *outputs[CCX_DTVCC_MAX_OUTPUTS]; // Outputs of every service as FORMAT[:TEMPLATE]
+	int outputs_count;
+	char *service_languages; // Languages of the services, e.g. "1=eng,2=spa"
+	LLONG time_offset; // Added to all output times in ms, after scaling
+	double time_scale; // Factor all output times are multiplied with
+	LLONG clip_start; // Captions before this time in ms are trimmed, -1 to disable
+	LLONG clip_end; // Captions after this time in ms are trimmed, -1 to disable
+	int min_duration; // Minimum display time of a screen in ms, 0 to disable
+	int max_duration; // Maximum display time of a screen in ms, 0 to disable
+	int min_gap; // Minimum time between two screens in ms
+	int merge_identical; // Merge adjacent screens with the same text
 } ccx_decoder_dtvcc_settings;
 
 /**
@@ -16,8 +16,12 @@ made to reuse, not duplicate, as many functions as possible */
 #include "ccx_dtvcc.h"
 
 #ifndef DISABLE_RUST
+extern void *ccxr_dtvcc_init(struct ccx_decoder_dtvcc_settings *settings_dtvcc);
+extern void ccxr_dtvcc_free(void *dtvcc_rust);
+extern void ccxr_dtvcc_print_report(void *dtvcc_rust);
 extern int ccxr_process_cc_data(struct lib_cc_decode *dec_ctx, unsigned char *cc_data, int cc_count);
-extern void ccxr_flush_decoder(struct dtvcc_ctx *dtvcc, struct dtvcc_service_decoder *decoder);
+extern int ccxr_dtvcc_replay_capture(void *dtvcc_rust, struct encoder_ctx *encoder);
+extern void ccxr_flush_active_decoders(struct lib_cc_decode *ctx);
 #endif
 
 uint64_t utc_refvalue = UINT64_MAX; /* _UI64_MAX/UINT64_MAX means don't use UNIX, 0 = use current system time as reference, +1 use a specific reference */
@@ -55,6 +59,8 @@ int process_cc_data(struct encoder_ctx *enc_ctx, struct lib_cc_decode *dec_ctx,
 	}
 
 #ifndef DISABLE_RUST
+	// The capture is replayed once, as soon as the encoder is known
+	ccxr_dtvcc_replay_capture(dec_ctx->dtvcc_rust, enc_ctx);
 	ret = ccxr_process_cc_data(dec_ctx, cc_data, cc_count);
 #endif
 
@@ -233,6 +239,11 @@ void dinit_cc_decode(struct lib_cc_decode **ctx)
 {
 	struct lib_cc_decode *lctx = *ctx;
 	dtvcc_free(&lctx->dtvcc);
+#ifndef DISABLE_RUST
+	ccxr_dtvcc_print_report(lctx->dtvcc_rust);
+	ccxr_dtvcc_free(lctx->dtvcc_rust);
+	lctx->dtvcc_rust = NULL;
+#endif
 	dinit_avc(&lctx->avc_ctx);
 	ccx_decoder_608_dinit_library(&lctx->context_cc608_field_1);
 	ccx_decoder_608_dinit_library(&lctx->context_cc608_field_2);
@@ -263,6 +274,9 @@ struct lib_cc_decode *init_cc_decode(struct ccx_decoders_common_settings_t *sett
 
 	ctx->dtvcc = dtvcc_init(setting->settings_dtvcc);
 	ctx->dtvcc->is_active = setting->settings_dtvcc->enabled;
+#ifndef DISABLE_RUST
+	ctx->dtvcc_rust = ccxr_dtvcc_init(setting->settings_dtvcc);
+#endif
 
 	if (setting->codec == CCX_CODEC_ATSC_CC)
 	{
@@ -443,6 +457,11 @@ void flush_cc_decode(struct lib_cc_decode *ctx, struct cc_subtitle *sub)
 	}
 	if (ctx->dtvcc->is_active)
 	{
+#ifndef DISABLE_RUST
+		// Input without any cc_data has not replayed the capture yet
+		ccxr_dtvcc_replay_capture(ctx->dtvcc_rust, ctx->dtvcc->encoder);
+		ccxr_flush_active_decoders(ctx);
+#else
 		for (int i = 0; i < CCX_DTVCC_MAX_SERVICES; i++)
 		{
 			dtvcc_service_decoder *decoder = &ctx->dtvcc->decoders[i];
@@ -451,13 +470,10 @@ void flush_cc_decode(struct lib_cc_decode *ctx, struct cc_subtitle *sub)
 			if (decoder->cc_count > 0)
 			{
 				ctx->current_field = 3;
-#ifndef DISABLE_RUST
-				ccxr_flush_decoder(ctx->dtvcc, decoder);
-#else
 				dtvcc_decoder_flush(ctx->dtvcc, decoder);
-#endif
 			}
 		}
+#endif
 	}
 }
 struct encoder_ctx *copy_encoder_context(struct encoder_ctx *ctx)
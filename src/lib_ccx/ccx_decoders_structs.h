@@ -209,6 +209,9 @@ struct lib_cc_decode
     int false_pict_header;
 
 	dtvcc_ctx *dtvcc;
+#ifndef DISABLE_RUST
+	void *dtvcc_rust; // Rust 708 decoder context, see ccxr_dtvcc_init()
+#endif
 	int current_field;
 	// Analyse/use the picture information
 	int maxtref; // Use to remember the temporal reference number
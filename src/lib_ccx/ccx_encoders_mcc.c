@@ -29,6 +29,10 @@ static void write_string(int fh, char *string);
 static void random_chars(char buffer[], int len);
 static void uuid4(char *buffer);
 
+#ifndef DISABLE_RUST
+extern int ccxr_mcc_encode_cc_data(struct encoder_ctx *enc_ctx, struct lib_cc_decode *dec_ctx, unsigned char *cc_data, int cc_count);
+#endif
+
 static void ms_to_frame(struct encoder_ctx *ctx, struct ccx_mcc_caption_time *caption_time_ptr, int fr_code, int dropframe_flag)
 {
 	int64 actual_time_in_ms = (((caption_time_ptr->hour * 3600) + (caption_time_ptr->minute * 60) +
@@ -99,6 +103,11 @@ boolean mcc_encode_cc_data(struct encoder_ctx *enc_ctx, struct lib_cc_decode *de
 	ASSERT(enc_ctx);
 	ASSERT(dec_ctx);
 
+#ifndef DISABLE_RUST
+	// The Rust decoder writes the MCC output, using the same CDPs as its other outputs
+	return ccxr_mcc_encode_cc_data(enc_ctx, dec_ctx, cc_data, cc_count) == 0;
+#endif
+
 	struct ccx_mcc_caption_time caption_time = convert_to_caption_time(enc_ctx->timing->fts_now + enc_ctx->timing->fts_global);
 
 	if (enc_ctx->header_printed_flag == CCX_FALSE)
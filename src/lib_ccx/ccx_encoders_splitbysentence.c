@@ -613,6 +613,8 @@ struct cc_subtitle *sbs_append_string(unsigned char *str, const LLONG time_from,
 			tmpsub->data = strndup(bp_last_break, tmpsub->nb_data - 1);
 			tmpsub->datatype = CC_DATATYPE_GENERIC;
 			tmpsub->got_output = 1;
+			tmpsub->has_position = 0;
+			tmpsub->has_confidence = 0;
 
 			tmpsub->start_time = alphanum_cur;
 			alphanum_cur = 0;
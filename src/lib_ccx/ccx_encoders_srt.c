@@ -7,8 +7,8 @@
 #include "ccextractor.h"
 
 /* The timing here is not PTS based, but output based, i.e. user delay must be accounted for
-   if there is any */
-int write_stringz_as_srt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+   if there is any. A non empty comment is written as a line of its own before the text */
+static int _write_stringz_as_srt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end, const char *comment)
 {
 	int used;
 	unsigned h1, m1, s1, ms1;
@@ -31,6 +31,12 @@ int write_stringz_as_srt(char *string, struct encoder_ctx *context, LLONG ms_sta
 	dbg_print(CCX_DMT_DECODER_608, "%s", timeline);
 
 	write_wrapped(context->out->fh, context->buffer, used);
+	if (comment[0])
+	{
+		used = encode_line(context, context->buffer, (unsigned char *)comment);
+		write_wrapped(context->out->fh, context->buffer, used);
+		write_wrapped(context->out->fh, context->encoded_crlf, context->encoded_crlf_length);
+	}
 	int len = strlen(string);
 	unsigned char *unescaped = (unsigned char *)malloc(len + 1);
 	unsigned char *el = (unsigned char *)malloc(len * 3 + 1); // Be generous
@@ -78,6 +84,11 @@ int write_stringz_as_srt(char *string, struct encoder_ctx *context, LLONG ms_sta
 	return 0;
 }
 
+int write_stringz_as_srt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+{
+	return _write_stringz_as_srt(string, context, ms_start, ms_end, "");
+}
+
 int write_cc_bitmap_as_srt(struct cc_subtitle *sub, struct encoder_ctx *context)
 {
 	int ret = 0;
@@ -150,7 +161,11 @@ int write_cc_subtitle_as_srt(struct cc_subtitle *sub, struct encoder_ctx *contex
 	{
 		if (sub->type == CC_TEXT)
 		{
-			ret = write_stringz_as_srt(sub->data, context, sub->start_time, sub->end_time);
+			char comment[80] = "";
+			if (sub->has_confidence)
+				snprintf(comment, sizeof(comment), "{OCR confidence: mean %.2f%%, min %.2f%%%s}",
+					 sub->mean_confidence, sub->min_confidence, sub->needs_review ? ", needs review" : "");
+			ret = _write_stringz_as_srt(sub->data, context, sub->start_time, sub->end_time, comment);
 			freep(&sub->data);
 			sub->nb_data = 0;
 			ret = 1;
@@ -5,7 +5,12 @@
 #include "ccx_encoders_helpers.h"
 #include "ocr.h"
 
-int write_stringz_as_ssa(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+// Size of the script the \pos override tags refer to, the default as the header sets no PlayResX/Y
+#define SSA_PLAY_RES_X 384
+#define SSA_PLAY_RES_Y 288
+
+// overrides are put in front of the text, e.g. "{\an8\pos(192,230)}"
+static int _write_stringz_as_ssa(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end, const char *overrides)
 {
 	int used;
 	unsigned h1, m1, s1, ms1;
@@ -18,8 +23,8 @@ int write_stringz_as_ssa(char *string, struct encoder_ctx *context, LLONG ms_sta
 	millis_to_time(ms_start, &h1, &m1, &s1, &ms1);
 	millis_to_time(ms_end - 1, &h2, &m2, &s2, &ms2); // -1 To prevent overlapping with next line.
 
-	sprintf(timeline, "Dialogue: 0,%02u:%02u:%02u.%01u,%02u:%02u:%02u.%02u,Default,,0000,0000,0000,,",
-		h1, m1, s1, ms1 / 10, h2, m2, s2, ms2 / 10);
+	snprintf(timeline, sizeof(timeline), "Dialogue: 0,%02u:%02u:%02u.%01u,%02u:%02u:%02u.%02u,Default,,0000,0000,0000,,%s",
+		 h1, m1, s1, ms1 / 10, h2, m2, s2, ms2 / 10, overrides);
 	used = encode_line(context, context->buffer, (unsigned char *)timeline);
 	dbg_print(CCX_DMT_DECODER_608, "\n- - - ASS/SSA caption - - -\n");
 	dbg_print(CCX_DMT_DECODER_608, "%s", timeline);
@@ -72,6 +77,11 @@ int write_stringz_as_ssa(char *string, struct encoder_ctx *context, LLONG ms_sta
 	return 0;
 }
 
+int write_stringz_as_ssa(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+{
+	return _write_stringz_as_ssa(string, context, ms_start, ms_end, "");
+}
+
 int write_cc_bitmap_as_ssa(struct cc_subtitle *sub, struct encoder_ctx *context)
 {
 	int ret = 0;
@@ -140,7 +150,11 @@ int write_cc_subtitle_as_ssa(struct cc_subtitle *sub, struct encoder_ctx *contex
 	{
 		if (sub->type == CC_TEXT)
 		{
-			ret = write_stringz_as_ssa(sub->data, context, sub->start_time, sub->end_time);
+			char overrides[64] = "";
+			if (sub->has_position)
+				snprintf(overrides, sizeof(overrides), "{\\an8\\pos(%.0f,%.0f)}",
+					 sub->position_percent * SSA_PLAY_RES_X / 100, sub->line_percent * SSA_PLAY_RES_Y / 100);
+			ret = _write_stringz_as_ssa(sub->data, context, sub->start_time, sub->end_time, overrides);
 			freep(&sub->data);
 			sub->nb_data = 0;
 			ret = 1;
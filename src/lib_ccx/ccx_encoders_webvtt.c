@@ -119,8 +119,8 @@ static const char *webvtt_pac_row_percent[] = {"10", "15.33", "20.66", "26", "31
 					       "47.33", "52.66", "58", "63.33", "68.66", "74", "79.33", "84.66"};
 
 /* The timing here is not PTS based, but output based, i.e. user delay must be accounted for
-if there is any */
-int write_stringz_as_webvtt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+if there is any. settings are appended to the timeline, e.g. " line:80%" */
+static int _write_stringz_as_webvtt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end, const char *settings)
 {
 	int used;
 	unsigned h1, m1, s1, ms1;
@@ -131,8 +131,8 @@ int write_stringz_as_webvtt(char *string, struct encoder_ctx *context, LLONG ms_
 	millis_to_time(ms_start, &h1, &m1, &s1, &ms1);
 	millis_to_time(ms_end - 1, &h2, &m2, &s2, &ms2); // -1 To prevent overlapping with next line.
 
-	sprintf(timeline, "%02u:%02u:%02u.%03u --> %02u:%02u:%02u.%03u%s",
-		h1, m1, s1, ms1, h2, m2, s2, ms2, context->encoded_crlf);
+	snprintf(timeline, sizeof(timeline), "%02u:%02u:%02u.%03u --> %02u:%02u:%02u.%03u%s%s",
+		 h1, m1, s1, ms1, h2, m2, s2, ms2, settings, context->encoded_crlf);
 	used = encode_line(context, context->buffer, (unsigned char *)timeline);
 	dbg_print(CCX_DMT_DECODER_608, "\n- - - WEBVTT caption - - -\n");
 	dbg_print(CCX_DMT_DECODER_608, "%s", timeline);
@@ -202,6 +202,11 @@ int write_stringz_as_webvtt(char *string, struct encoder_ctx *context, LLONG ms_
 	return 0;
 }
 
+int write_stringz_as_webvtt(char *string, struct encoder_ctx *context, LLONG ms_start, LLONG ms_end)
+{
+	return _write_stringz_as_webvtt(string, context, ms_start, ms_end, "");
+}
+
 void write_webvtt_header(struct encoder_ctx *context)
 {
 	if (context->wrote_webvtt_header) // Already done
@@ -332,7 +337,11 @@ int write_cc_subtitle_as_webvtt(struct cc_subtitle *sub, struct encoder_ctx *con
 	{
 		if (sub->type == CC_TEXT)
 		{
-			ret = write_stringz_as_webvtt(sub->data, context, sub->start_time, sub->end_time);
+			char settings[64] = "";
+			if (sub->has_position)
+				snprintf(settings, sizeof(settings), " line:%.2f%% position:%.2f%% align:center",
+					 sub->line_percent, sub->position_percent);
+			ret = _write_stringz_as_webvtt(sub->data, context, sub->start_time, sub->end_time, settings);
 			freep(&sub->data);
 			sub->nb_data = 0;
 		}
@@ -179,6 +179,20 @@ void _hardsubx_params_dump(struct ccx_s_options *options, struct lib_hardsubx_ct
 			fatal(EXIT_MALFORMED_PARAMETER, "Invalid OCR Mode");
 	}
 
+	mprint("OCR Language : %s\n", options->ocrlang ? options->ocrlang : "eng");
+	if (options->hardsubx_tessdata_dir)
+	{
+		mprint("Tesseract Data Directory : %s\n", options->hardsubx_tessdata_dir);
+	}
+	if (options->hardsubx_psm >= 0)
+	{
+		mprint("Page Segmentation Mode : %d\n", options->hardsubx_psm);
+	}
+	if (options->hardsubx_whitelist)
+	{
+		mprint("OCR Character Whitelist : %s\n", options->hardsubx_whitelist);
+	}
+
 	if (ctx->conf_thresh > 0)
 	{
 		mprint("OCR Confidence Threshold : %.2f\n", ctx->conf_thresh);
@@ -218,6 +232,57 @@ void _hardsubx_params_dump(struct ccx_s_options *options, struct lib_hardsubx_ct
 		mprint("Minimum subtitle duration : %0.2f seconds\n", ctx->min_sub_duration);
 	}
 
+	if (ctx->roi_count == 0)
+	{
+		mprint("Subtitle Region : %s (Default)\n", ctx->tickertext ? "Bottom 8%" : "Bottom quarter");
+	}
+	for (int i = 0; i < ctx->roi_count; i++)
+	{
+		struct hardsubx_roi *roi = &ctx->rois[i];
+		if (roi->in_pixels)
+			mprint("Subtitle Region : %.0f,%.0f %.0fx%.0f pixels\n", roi->left, roi->top, roi->width, roi->height);
+		else
+			mprint("Subtitle Region : %.3f,%.3f %.3fx%.3f of the frame\n", roi->left, roi->top, roi->width, roi->height);
+	}
+
+	if (ctx->letterbox_frames > 0)
+	{
+		mprint("Black Bar Detection : First %d frames\n", ctx->letterbox_frames);
+	}
+
+	if (ctx->change_thresh > 0)
+	{
+		mprint("OCR Change Threshold : %.2f%%\n", ctx->change_thresh);
+	}
+	else
+	{
+		mprint("OCR Change Threshold : Off, every frame is recognized\n");
+	}
+
+	mprint("Subtitle Merge Similarity : %.2f%%\n", ctx->merge_similarity);
+
+	if (ctx->cue_position)
+	{
+		mprint("Subtitle Position : Where burned in\n");
+	}
+	if (ctx->ocr_json)
+	{
+		mprint("Word Boxes : %s\n", ctx->ocr_json);
+	}
+	if (ctx->conf_comment)
+	{
+		mprint("Confidence Comments : On\n");
+	}
+	if (ctx->review_thresh > 0)
+	{
+		mprint("Review Threshold : %.2f\n", ctx->review_thresh);
+	}
+
+	if (ctx->debug_dir)
+	{
+		mprint("Debug Images : %s\n", ctx->debug_dir);
+	}
+
 	mprint("FFMpeg Media Information:-\n");
 }
 
@@ -229,21 +294,30 @@ struct lib_hardsubx_ctx *_init_hardsubx(struct ccx_s_options *options)
 		fatal(EXIT_NOT_ENOUGH_MEMORY, "Not enough memory for HardsubX data structures.");
 	memset(ctx, 0, sizeof(struct lib_hardsubx_ctx));
 
-	ctx->tess_handle = TessBaseAPICreate();
-	char *pars_vec = strdup("debug_file");
-	char *pars_values = strdup("/dev/null");
+#ifndef DISABLE_RUST
+	if (hardsubx_init_ocr(ctx, options->ocrlang, options->hardsubx_tessdata_dir, options->ocr_oem,
+			      options->hardsubx_psm, options->hardsubx_whitelist) != 0)
+	{
+		free(ctx);
+		return NULL;
+	}
+#else
 	char *tessdata_path = NULL;
 
 	char *lang = (char *)options->ocrlang;
 	if (!lang)
 		lang = "eng"; // English is default language
 
-	tessdata_path = probe_tessdata_location(lang);
+	if (options->hardsubx_tessdata_dir)
+		tessdata_path = options->hardsubx_tessdata_dir;
+	else
+		tessdata_path = probe_tessdata_location(lang);
 	if (!tessdata_path)
 	{
 		if (strcmp(lang, "eng") == 0)
 		{
 			mprint("eng.traineddata not found! No Switching Possible\n");
+			free(ctx);
 			return NULL;
 		}
 		mprint("%s.traineddata not found! Switching to English\n", lang);
@@ -252,10 +326,14 @@ struct lib_hardsubx_ctx *_init_hardsubx(struct ccx_s_options *options)
 		if (!tessdata_path)
 		{
 			mprint("eng.traineddata not found! No Switching Possible\n");
+			free(ctx);
 			return NULL;
 		}
 	}
 
+	ctx->tess_handle = TessBaseAPICreate();
+	char *pars_vec = strdup("debug_file");
+	char *pars_values = strdup("/dev/null");
 	int ret = -1;
 
 	if (!strncmp("4.", TessVersion(), 2) || !strncmp("5.", TessVersion(), 2))
@@ -281,6 +359,11 @@ struct lib_hardsubx_ctx *_init_hardsubx(struct ccx_s_options *options)
 	{
 		fatal(EXIT_NOT_ENOUGH_MEMORY, "Not enough memory to initialize Tesseract");
 	}
+	if (options->hardsubx_psm >= 0)
+		TessBaseAPISetPageSegMode(ctx->tess_handle, options->hardsubx_psm);
+	if (options->hardsubx_whitelist)
+		TessBaseAPISetVariable(ctx->tess_handle, "tessedit_char_whitelist", options->hardsubx_whitelist);
+#endif
 
 	// Initialize attributes common to lib_ccx context
 	ctx->basefilename = get_basename(options->output_filename); // TODO: Check validity, add stdin, network
@@ -304,6 +387,16 @@ struct lib_hardsubx_ctx *_init_hardsubx(struct ccx_s_options *options)
 	ctx->hue = options->hardsubx_hue;
 	ctx->lum_thresh = options->hardsubx_lum_thresh;
 	ctx->hardsubx_and_common = options->hardsubx_and_common;
+	memcpy(ctx->rois, options->hardsubx_rois, sizeof(ctx->rois));
+	ctx->roi_count = options->hardsubx_roi_count;
+	ctx->letterbox_frames = options->hardsubx_letterbox_frames;
+	ctx->debug_dir = options->hardsubx_debug_dir;
+	ctx->change_thresh = options->hardsubx_change_thresh;
+	ctx->merge_similarity = options->hardsubx_merge_similarity;
+	ctx->cue_position = options->hardsubx_cue_position;
+	ctx->ocr_json = options->hardsubx_ocr_json;
+	ctx->conf_comment = options->hardsubx_conf_comment;
+	ctx->review_thresh = options->hardsubx_review_thresh;
 
 	// Initialize subtitle structure memory
 	ctx->dec_sub = (struct cc_subtitle *)malloc(sizeof(struct cc_subtitle));
@@ -318,8 +411,14 @@ void _dinit_hardsubx(struct lib_hardsubx_ctx **ctx)
 	// Free all memory allocated to everything in the context
 
 	// Free OCR
+#ifndef DISABLE_RUST
+	hardsubx_free_ocr(lctx);
+	hardsubx_free_change_gate(lctx);
+	hardsubx_free_layout_state(lctx);
+#else
 	TessBaseAPIEnd(lctx->tess_handle);
 	TessBaseAPIDelete(lctx->tess_handle);
+#endif
 
 	// Free subtitle
 	freep(lctx->dec_sub);
@@ -334,6 +433,11 @@ void hardsubx(struct ccx_s_options *options, struct lib_ccx_ctx *ctx_normal)
 	// Initialize HardsubX data structures
 	struct lib_hardsubx_ctx *ctx;
 	ctx = _init_hardsubx(options);
+	if (!ctx)
+	{
+		mprint("HardsubX could not be initialized, no burned-in subtitles are extracted\n");
+		return;
+	}
 
 	// Dump parameters (Not using params_dump since completely different parameters)
 	_hardsubx_params_dump(options, ctx);
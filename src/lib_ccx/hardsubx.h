@@ -62,6 +62,7 @@ struct lib_hardsubx_ctx
 	// Leptonica Image and Tesseract Context
 	PIX *im;
 	TessBaseAPI *tess_handle;
+	void *tess_api; // Owner of tess_handle when it was created by Rust
 
 	// Classifier parameters
 	float cur_conf;
@@ -78,12 +79,52 @@ struct lib_hardsubx_ctx
 	float conf_thresh;
 	float hue;
 	float lum_thresh;
+
+	// Region of the frame searched for subtitles
+	struct hardsubx_roi rois[HARDSUBX_MAX_ROIS];
+	int roi_count;
+
+	// Active picture area without black bars, detected from the first frames
+	int letterbox_frames;      // Number of frames to detect it from, 0 to disable
+	int letterbox_frames_seen; // Frames with a picture looked at so far
+	int active_left;
+	int active_top;
+	int active_right; // 0 until a picture was found
+	int active_bottom;
+
+	// Directory to write the intermediate images of every frame to, NULL to disable
+	char *debug_dir;
+
+	// OCR is skipped while less than change_thresh percent of the subtitle region changed
+	float change_thresh;
+	void *change_gate; // Feature image and text of the last OCR'd frame, owned by Rust
+
+	// Consecutive texts at least merge_similarity percent alike become one subtitle
+	float merge_similarity;
+
+	// Boxes of the recognized words
+	int cue_position;  // 1 to place the subtitles where they are burned in
+	char *ocr_json;	   // File to write the subtitles with their words to, NULL to disable
+	void *word_layout; // Words of the last recognized frame and the open ocr_json, owned by Rust
+
+	// Confidence of the subtitles
+	int conf_comment;    // 1 to write the confidence of every subtitle as an SRT comment
+	float review_thresh; // Subtitles with a word below it need review, 0 to disable
 };
 
 struct lib_hardsubx_ctx *_init_hardsubx(struct ccx_s_options *options);
 void _hardsubx_params_dump(struct ccx_s_options *options, struct lib_hardsubx_ctx *ctx);
 void hardsubx(struct ccx_s_options *options, struct lib_ccx_ctx *ctx_normal);
 void _dinit_hardsubx(struct lib_hardsubx_ctx **ctx);
+void hardsubx_free_change_gate(struct lib_hardsubx_ctx *ctx);
+int hardsubx_init_ocr(struct lib_hardsubx_ctx *ctx, const char *languages, const char *tessdata_dir, int oem, int psm, const char *whitelist);
+void hardsubx_free_ocr(struct lib_hardsubx_ctx *ctx);
+void *hardsubx_copy_layout(struct lib_hardsubx_ctx *ctx);
+void hardsubx_free_layout(void *layout);
+int hardsubx_layout_position(const void *layout, float *line_percent, float *position_percent);
+int hardsubx_layout_confidence(const void *layout, float *mean_confidence, float *min_confidence);
+void hardsubx_write_layout(struct lib_hardsubx_ctx *ctx, const void *layout, const char *text, int64_t start_ms, int64_t end_ms);
+void hardsubx_free_layout_state(struct lib_hardsubx_ctx *ctx);
 int hardsubx_process_data(struct lib_hardsubx_ctx *ctx, struct lib_ccx_ctx *ctx_normal);
 
 // hardsubx_decoder.c
@@ -110,6 +151,7 @@ char *get_ocr_text_letterwise_threshold(struct lib_hardsubx_ctx *ctx, PIX *image
 
 // hardsubx_utility.c
 int edit_distance(char *word1, char *word2, int len1, int len2);
+float text_similarity(const char *word1, const char *word2, float min_similarity);
 int64_t convert_pts_to_ms(int64_t pts, AVRational time_base);
 int64_t convert_pts_to_ns(int64_t pts, AVRational time_base);
 int64_t convert_pts_to_s(int64_t pts, AVRational time_base);
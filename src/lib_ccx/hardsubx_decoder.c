@@ -12,6 +12,23 @@
 #include "hardsubx.h"
 
 #ifdef DISABLE_RUST
+// Returns 1 if the pixel is in one of the configured regions, or in the rows from default_top if none is set
+static int in_roi(struct lib_hardsubx_ctx *ctx, int x, int y, int width, int height, int default_top)
+{
+	if (ctx->roi_count == 0)
+		return y >= default_top;
+	for (int k = 0; k < ctx->roi_count; k++)
+	{
+		struct hardsubx_roi *roi = &ctx->rois[k];
+		float scale_x = roi->in_pixels ? 1 : width;
+		float scale_y = roi->in_pixels ? 1 : height;
+		if (x >= (int)floorf(roi->left * scale_x) && x < (int)ceilf((roi->left + roi->width) * scale_x) &&
+		    y >= (int)floorf(roi->top * scale_y) && y < (int)ceilf((roi->top + roi->height) * scale_y))
+			return 1;
+	}
+	return 0;
+}
+
 char *_process_frame_white_basic(struct lib_hardsubx_ctx *ctx, AVFrame *frame, int width, int height, int index)
 {
 	// printf("frame : %04d\n", index);
@@ -29,10 +46,12 @@ char *_process_frame_white_basic(struct lib_hardsubx_ctx *ctx, AVFrame *frame, i
 	lum_im = pixCreate(width, height, 32);
 	feat_im = pixCreate(width, height, 32);
 
-	for (int i = (3 * height) / 4; i < height; i++)
+	for (int i = ctx->roi_count ? 0 : (3 * height) / 4; i < height; i++)
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, (3 * height) / 4))
+				continue;
 			int p = j * 3 + i * frame->linesize[0];
 			int r = frame->data[0][p];
 			int g = frame->data[0][p + 1];
@@ -53,10 +72,12 @@ char *_process_frame_white_basic(struct lib_hardsubx_ctx *ctx, AVFrame *frame, i
 	dilate_gray_im = pixDilateGray(sobel_edge_im, 21, 11);
 	edge_im = pixThresholdToBinary(dilate_gray_im, 50);
 
-	for (int i = 3 * (height / 4); i < height; i++)
+	for (int i = ctx->roi_count ? 0 : 3 * (height / 4); i < height; i++)
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, 3 * (height / 4)))
+				continue;
 			unsigned int p1, p2, p3;
 			pixGetPixel(edge_im, j, i, &p1);
 			// pixGetPixel(pixd,j,i,&p2);
@@ -131,6 +152,8 @@ char *_process_frame_color_basic(struct lib_hardsubx_ctx *ctx, AVFrame *frame, i
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, 0))
+				continue;
 			int p = j * 3 + i * frame->linesize[0];
 			int r = frame->data[0][p];
 			int g = frame->data[0][p + 1];
@@ -156,10 +179,12 @@ char *_process_frame_color_basic(struct lib_hardsubx_ctx *ctx, AVFrame *frame, i
 	pixSauvolaBinarize(gray_im_2, 15, 0.3, 1, NULL, NULL, NULL, &pixd);
 
 	feat_im = pixCreate(width, height, 32);
-	for (int i = 3 * (height / 4); i < height; i++)
+	for (int i = ctx->roi_count ? 0 : 3 * (height / 4); i < height; i++)
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, 3 * (height / 4)))
+				continue;
 			unsigned int p1, p2, p3, p4;
 			pixGetPixel(edge_im, j, i, &p1);
 			pixGetPixel(pixd, j, i, &p2);
@@ -313,10 +338,12 @@ char *_process_frame_tickertext(struct lib_hardsubx_ctx *ctx, AVFrame *frame, in
 	lum_im = pixCreate(width, height, 32);
 	feat_im = pixCreate(width, height, 32);
 
-	for (int i = (92 * height) / 100; i < height; i++)
+	for (int i = ctx->roi_count ? 0 : (92 * height) / 100; i < height; i++)
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, (92 * height) / 100))
+				continue;
 			int p = j * 3 + i * frame->linesize[0];
 			int r = frame->data[0][p];
 			int g = frame->data[0][p + 1];
@@ -337,10 +364,12 @@ char *_process_frame_tickertext(struct lib_hardsubx_ctx *ctx, AVFrame *frame, in
 	dilate_gray_im = pixDilateGray(sobel_edge_im, 21, 11);
 	edge_im = pixThresholdToBinary(dilate_gray_im, 50);
 
-	for (int i = 92 * (height / 100); i < height; i++)
+	for (int i = ctx->roi_count ? 0 : 92 * (height / 100); i < height; i++)
 	{
 		for (int j = 0; j < width; j++)
 		{
+			if (!in_roi(ctx, j, i, width, height, 92 * (height / 100)))
+				continue;
 			unsigned int p1, p2, p3;
 			pixGetPixel(edge_im, j, i, &p1);
 			// pixGetPixel(pixd,j,i,&p2);
@@ -354,11 +383,6 @@ char *_process_frame_tickertext(struct lib_hardsubx_ctx *ctx, AVFrame *frame, in
 
 	// Tesseract OCR for the ticker text here
 	subtitle_text = get_ocr_text_simple(ctx, lum_im);
-	char write_path[100];
-	sprintf(write_path, "./lum_im%04d.jpg", index);
-	pixWrite(write_path, lum_im, IFF_JFIF_JPEG);
-	sprintf(write_path, "./im%04d.jpg", index);
-	pixWrite(write_path, im, IFF_JFIF_JPEG);
 
 	pixDestroy(&im);
 	pixDestroy(&gray_im);
@@ -401,8 +425,13 @@ int hardsubx_process_frames_tickertext(struct lib_hardsubx_ctx *ctx, struct enco
 				ticker_text = _process_frame_tickertext(ctx, ctx->rgb_frame, ctx->codec_ctx->width, ctx->codec_ctx->height, frame_number);
 				printf("frame_number: %d\n", frame_number);
 
-				if (strlen(ticker_text) > 0)
-					printf("%s\n", ticker_text);
+				// NULL if the frame could not be recognized
+				if (ticker_text)
+				{
+					if (strlen(ticker_text) > 0)
+						printf("%s\n", ticker_text);
+					TessDeleteText(ticker_text);
+				}
 
 				cur_sec = (int)convert_pts_to_s(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base);
 				total_sec = (int)convert_pts_to_s(ctx->format_ctx->duration, AV_TIME_BASE_Q);
@@ -416,18 +445,95 @@ int hardsubx_process_frames_tickertext(struct lib_hardsubx_ctx *ctx, struct enco
 	return 0;
 }
 
+// A subtitle line that keeps being extended while consecutive frames show a similar text
+struct hardsubx_cue
+{
+	char *text; // Variant recognized with the highest confidence, NULL if no line is open
+	float conf;
+	int64_t begin_time;
+	int64_t end_time;	 // Time of the last frame showing the line
+	int64_t last_frame_time; // Time of the last processed frame
+	void *layout;		 // Words of text with their boxes, owned by Rust
+};
+
+static void _flush_cue(struct lib_hardsubx_ctx *ctx, struct encoder_ctx *enc_ctx, struct hardsubx_cue *cue)
+{
+	if (!cue->text)
+		return;
+
+	add_cc_sub_text(ctx->dec_sub, cue->text, cue->begin_time, cue->end_time, "", "BURN", CCX_ENC_UTF_8);
+#ifndef DISABLE_RUST
+	struct cc_subtitle *sub = ctx->dec_sub;
+	while (sub->next)
+		sub = sub->next;
+	if (ctx->cue_position)
+		sub->has_position = hardsubx_layout_position(cue->layout, &sub->line_percent, &sub->position_percent);
+	if (ctx->conf_comment && hardsubx_layout_confidence(cue->layout, &sub->mean_confidence, &sub->min_confidence))
+	{
+		sub->has_confidence = 1;
+		sub->needs_review = ctx->review_thresh > 0 && sub->min_confidence < ctx->review_thresh;
+	}
+	hardsubx_write_layout(ctx, cue->layout, cue->text, cue->begin_time, cue->end_time);
+	hardsubx_free_layout(cue->layout);
+	cue->layout = NULL;
+#endif
+	encode_sub(enc_ctx, ctx->dec_sub);
+	free(cue->text);
+	cue->text = NULL;
+}
+
+// Keeps the words of the last recognized frame with the text of the line
+static void _set_cue_text(struct lib_hardsubx_ctx *ctx, struct hardsubx_cue *cue, char *text)
+{
+	free(cue->text);
+	cue->text = strdup(text);
+	cue->conf = ctx->cur_conf;
+#ifndef DISABLE_RUST
+	hardsubx_free_layout(cue->layout);
+	cue->layout = hardsubx_copy_layout(ctx);
+#endif
+}
+
+// Folds the text of a frame into the open line if they are similar enough, otherwise
+// encodes the open line and starts a new one. ctx->cur_conf is the confidence of the text.
+static void _merge_cue(struct lib_hardsubx_ctx *ctx, struct encoder_ctx *enc_ctx, struct hardsubx_cue *cue, char *text, int64_t frame_time)
+{
+	if (text)
+	{
+		char *double_enter = strstr(text, "\n\n");
+		if (double_enter != NULL)
+			*(double_enter) = '\0';
+	}
+
+	int has_text = text && strlen(text);
+	if (cue->text && has_text && text_similarity(text, cue->text, ctx->merge_similarity / 100) >= 0)
+	{
+		cue->end_time = frame_time;
+		if (ctx->cur_conf > cue->conf)
+			_set_cue_text(ctx, cue, text);
+	}
+	else
+	{
+		_flush_cue(ctx, enc_ctx, cue);
+		if (has_text)
+		{
+			_set_cue_text(ctx, cue, text);
+			cue->begin_time = cue->last_frame_time + 1;
+			cue->end_time = frame_time;
+		}
+	}
+	cue->last_frame_time = frame_time;
+}
+
 void hardsubx_process_frames_linear(struct lib_hardsubx_ctx *ctx, struct encoder_ctx *enc_ctx)
 {
 	// Do an exhaustive linear search over the video
 
-	int prev_sub_encoded = 1; // Previous seen subtitle encoded or not
-	int dist = 0;
+	struct hardsubx_cue cue = {0};
 	int cur_sec = 0, total_sec, progress;
 	int frame_number = 0;
-	int64_t prev_begin_time = 0, prev_end_time = 0; // Begin and end time of previous seen subtitle
 	int64_t prev_packet_pts = 0;
-	char *subtitle_text = NULL;	 // Subtitle text of current frame
-	char *prev_subtitle_text = NULL; // Previously seen subtitle text
+	char *subtitle_text = NULL; // Subtitle text of current frame
 
 	while (av_read_frame(ctx->format_ctx, &ctx->packet) >= 0)
 	{
@@ -469,79 +575,14 @@ void hardsubx_process_frames_linear(struct lib_hardsubx_ctx *ctx, struct encoder
 				progress = (cur_sec * 100) / total_sec;
 				activity_progress(progress, cur_sec / 60, cur_sec % 60);
 
-				if ((!subtitle_text && !prev_subtitle_text) || (subtitle_text && !strlen(subtitle_text) && !prev_subtitle_text))
-				{
-					prev_end_time = convert_pts_to_ms(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base);
-				}
-
-				if (subtitle_text)
-				{
-					char *double_enter = strstr(subtitle_text, "\n\n");
-					if (double_enter != NULL)
-						*(double_enter) = '\0';
-				}
-
-				if (!prev_sub_encoded && prev_subtitle_text)
-				{
-					if (subtitle_text)
-					{
-						dist = edit_distance(subtitle_text, prev_subtitle_text, (int)strlen(subtitle_text), (int)strlen(prev_subtitle_text));
-						if (dist < (0.2 * MIN(strlen(subtitle_text), strlen(prev_subtitle_text))))
-						{
-							dist = -1;
-							subtitle_text = NULL;
-							prev_end_time = convert_pts_to_ms(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base);
-						}
-					}
-					if (dist != -1)
-					{
-						add_cc_sub_text(ctx->dec_sub, prev_subtitle_text, prev_begin_time, prev_end_time, "", "BURN", CCX_ENC_UTF_8);
-						encode_sub(enc_ctx, ctx->dec_sub);
-						prev_begin_time = prev_end_time + 1;
-						prev_subtitle_text = NULL;
-						prev_sub_encoded = 1;
-						prev_end_time = convert_pts_to_ms(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base);
-						if (subtitle_text)
-						{
-							prev_subtitle_text = strdup(subtitle_text);
-							prev_sub_encoded = 0;
-						}
-					}
-					dist = 0;
-				}
-
-				// if(ctx->conf_thresh > 0)
-				// {
-				// 	if(ctx->cur_conf >= ctx->prev_conf)
-				// 	{
-				// 		prev_subtitle_text = strdup(subtitle_text);
-				// 		ctx->prev_conf = ctx->cur_conf;
-				// 	}
-				// }
-				// else
-				// {
-				// 	prev_subtitle_text = strdup(subtitle_text);
-				// }
-
-				if (!prev_subtitle_text && subtitle_text)
-				{
-					prev_begin_time = prev_end_time + 1;
-					prev_end_time = convert_pts_to_ms(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base);
-					prev_subtitle_text = strdup(subtitle_text);
-					prev_sub_encoded = 0;
-				}
+				_merge_cue(ctx, enc_ctx, &cue, subtitle_text, convert_pts_to_ms(ctx->packet.pts, ctx->format_ctx->streams[ctx->video_stream_id]->time_base));
 				prev_packet_pts = ctx->packet.pts;
 			}
 		}
 		av_packet_unref(&ctx->packet);
 	}
 
-	if (!prev_sub_encoded)
-	{
-		add_cc_sub_text(ctx->dec_sub, prev_subtitle_text, prev_begin_time, prev_end_time, "", "BURN", CCX_ENC_UTF_8);
-		encode_sub(enc_ctx, ctx->dec_sub);
-		prev_sub_encoded = 1;
-	}
+	_flush_cue(ctx, enc_ctx, &cue);
 	activity_progress(100, cur_sec / 60, cur_sec % 60);
 }
 
@@ -559,14 +600,11 @@ void process_hardsubx_linear_frames_and_normal_subs(struct lib_hardsubx_ctx *har
 	uint64_t min_pts = UINT64_MAX;
 
 	// variables for burnt-in subtitle extraction
-	int prev_sub_encoded_hard = 1; // Previous seen burnt-in subtitle encoded or not
-	int dist = 0;
+	struct hardsubx_cue cue_hard = {0}; // Burnt-in subtitle line being extended
 	int cur_sec = 0, total_sec, progress;
 	int frame_number = 0;
-	int64_t prev_begin_time_hard = 0, prev_end_time_hard = 0; // Begin and end time of previous seen burnt-in subtitle
 	int64_t prev_packet_pts_hard = 0;
-	char *subtitle_text_hard = NULL;      // Subtitle text of current frame (burnt_in)
-	char *prev_subtitle_text_hard = NULL; // Previously seen burnt-in subtitle text
+	char *subtitle_text_hard = NULL; // Subtitle text of current frame (burnt_in)
 
 	stream_mode = ctx->demux_ctx->get_stream_mode(ctx->demux_ctx);
 
@@ -747,54 +785,7 @@ void process_hardsubx_linear_frames_and_normal_subs(struct lib_hardsubx_ctx *har
 						progress = (cur_sec * 100) / total_sec;
 						activity_progress(progress, cur_sec / 60, cur_sec % 60);
 						// progress on burnt-in extraction
-						if ((!subtitle_text_hard && !prev_subtitle_text_hard) || (subtitle_text_hard && !strlen(subtitle_text_hard) && !prev_subtitle_text_hard))
-						{
-							prev_end_time_hard = convert_pts_to_ms(hard_ctx->packet.pts, hard_ctx->format_ctx->streams[hard_ctx->video_stream_id]->time_base);
-						}
-
-						if (subtitle_text_hard)
-						{
-							char *double_enter = strstr(subtitle_text_hard, "\n\n");
-							if (double_enter != NULL)
-								*(double_enter) = '\0';
-						}
-
-						if (!prev_sub_encoded_hard && prev_subtitle_text_hard)
-						{
-							if (subtitle_text_hard)
-							{
-								dist = edit_distance(subtitle_text_hard, prev_subtitle_text_hard, (int)strlen(subtitle_text_hard), (int)strlen(prev_subtitle_text_hard));
-								if (dist < (0.2 * MIN(strlen(subtitle_text_hard), strlen(prev_subtitle_text_hard))))
-								{
-									dist = -1;
-									subtitle_text_hard = NULL;
-									prev_end_time_hard = convert_pts_to_ms(hard_ctx->packet.pts, hard_ctx->format_ctx->streams[hard_ctx->video_stream_id]->time_base);
-								}
-							}
-							if (dist != -1)
-							{
-								add_cc_sub_text(hard_ctx->dec_sub, prev_subtitle_text_hard, prev_begin_time_hard, prev_end_time_hard, "", "BURN", CCX_ENC_UTF_8);
-								encode_sub(enc_ctx, hard_ctx->dec_sub);
-								prev_begin_time_hard = prev_end_time_hard + 1;
-								prev_subtitle_text_hard = NULL;
-								prev_sub_encoded_hard = 1;
-								prev_end_time_hard = convert_pts_to_ms(hard_ctx->packet.pts, hard_ctx->format_ctx->streams[hard_ctx->video_stream_id]->time_base);
-								if (subtitle_text_hard)
-								{
-									prev_subtitle_text_hard = strdup(subtitle_text_hard);
-									prev_sub_encoded_hard = 0;
-								}
-							}
-							dist = 0;
-						}
-
-						if (!prev_subtitle_text_hard && subtitle_text_hard)
-						{
-							prev_begin_time_hard = prev_end_time_hard + 1;
-							prev_end_time_hard = convert_pts_to_ms(hard_ctx->packet.pts, hard_ctx->format_ctx->streams[hard_ctx->video_stream_id]->time_base);
-							prev_subtitle_text_hard = strdup(subtitle_text_hard);
-							prev_sub_encoded_hard = 0;
-						}
+						_merge_cue(hard_ctx, enc_ctx, &cue_hard, subtitle_text_hard, convert_pts_to_ms(hard_ctx->packet.pts, hard_ctx->format_ctx->streams[hard_ctx->video_stream_id]->time_base));
 						prev_packet_pts_hard = hard_ctx->packet.pts;
 					}
 				}
@@ -802,12 +793,7 @@ void process_hardsubx_linear_frames_and_normal_subs(struct lib_hardsubx_ctx *har
 			av_packet_unref(&hard_ctx->packet);
 		}
 	}
-	if (!prev_sub_encoded_hard)
-	{
-		add_cc_sub_text(hard_ctx->dec_sub, prev_subtitle_text_hard, prev_begin_time_hard, prev_end_time_hard, "", "BURN", CCX_ENC_UTF_8);
-		encode_sub(enc_ctx, hard_ctx->dec_sub);
-		prev_sub_encoded_hard = 1;
-	}
+	_flush_cue(hard_ctx, enc_ctx, &cue_hard);
 	activity_progress(100, cur_sec / 60, cur_sec % 60);
 }
 
@@ -89,6 +89,23 @@ int edit_distance(char *word1, char *word2, int len1, int len2)
 	return ret;
 }
 
+float text_similarity(const char *word1, const char *word2, float min_similarity)
+{
+	if (!word1 || !word2)
+		return -1.0;
+
+	int len1 = strlen(word1);
+	int len2 = strlen(word2);
+	int longest = len1 > len2 ? len1 : len2;
+	if (!longest)
+		return 1.0;
+
+	int dist = edit_distance((char *)word1, (char *)word2, len1, len2);
+	if (dist > (int)floorf((1.0 - min_similarity) * longest + 0.001))
+		return -1.0;
+	return 1.0 - (float)dist / longest;
+}
+
 int is_valid_trailing_char(char c)
 {
 	char *prune_text = ",~`'-_+=‘:;” \n";
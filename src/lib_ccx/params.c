@@ -420,6 +420,55 @@ void print_usage(void)
 	mprint("                       \"all[EUC-KR]\") and it will encode specified charset to\n");
 	mprint("                       UTF-8 using iconv. See iconv documentation to check if\n");
 	mprint("                       required encoding/charset is supported.\n");
+	mprint("         --708-report: Print statistics of the CEA-708 decoder (packets,\n");
+	mprint("                       commands and errors per service) as JSON to stderr\n");
+	mprint("                       at the end of the run.\n");
+	mprint("  --708-recovery MODE: What to do when CEA-708 packets are lost. MODE can be\n");
+	mprint("                       none (default, continue decoding), flush (write out\n");
+	mprint("                       visible windows and reset the decoders) or reset\n");
+	mprint("                       (reset the decoders, dropping their content).\n");
+	mprint("   --708-capture FILE: Record every assembled CEA-708 packet with its time and\n");
+	mprint("                       sequence number to FILE, so decoding can be replayed\n");
+	mprint("                       later without the original input.\n");
+	mprint("    --708-replay FILE: Decode the CEA-708 packets of a capture written with\n");
+	mprint("                       --708-capture instead of the CEA-708 data of the\n");
+	mprint("                       input, writing the captions to the usual outputs.\n");
+	mprint("     --708-compliance: Check the CEA-708 data for violations of CTA-708 (e.g.\n");
+	mprint("                       bandwidth, missing null headers, reserved codes, bad\n");
+	mprint("                       window definitions) and print every finding with its\n");
+	mprint("                       time and severity at the end of the run. No captions\n");
+	mprint("                       are written in this mode.\n");
+	mprint("      --708-rotate KB: Start a new CEA-708 output file once the current one\n");
+	mprint("                       has reached KB kilobytes. Files are only split between\n");
+	mprint("                       captions, later files are named e.g. video_1.srt.\n");
+	mprint("--708-output FORMAT[:TEMPLATE]:\n");
+	mprint("                       Write every CEA-708 service in FORMAT (srt, sami,\n");
+	mprint("                       txt, scc, vtt or json) to a file named after\n");
+	mprint("                       TEMPLATE. Can be given up to 8 times to write several\n");
+	mprint("                       formats at once, instead of the output format set\n");
+	mprint("                       with -out. TEMPLATE can contain {base}, {program},\n");
+	mprint("                       {service}, {lang} and {ext}, the default is\n");
+	mprint("                       {base}.p{program}.svc{service}{ext}\n");
+	mprint("      --708-lang LIST: Languages of the CEA-708 services for {lang} and JSON\n");
+	mprint("                       output, e.g. 1=eng,2=spa\n");
+	mprint("      --708-offset MS: Shift all CEA-708 output times by MS milliseconds, in\n");
+	mprint("                       addition to -delay. Captions which would start before\n");
+	mprint("                       zero are trimmed.\n");
+	mprint("--708-timescale FROM:TO:\n");
+	mprint("                       Convert the CEA-708 output times from the frame rate\n");
+	mprint("                       FROM to TO, e.g. 25:23.976. A single number is used as\n");
+	mprint("                       the factor all times are multiplied with.\n");
+	mprint(" --708-clip START-END: Only write the CEA-708 captions between START and END\n");
+	mprint("                       (SS, MM:SS or HH:MM:SS), trimming the ones crossing\n");
+	mprint("                       the boundaries. Either time can be left out.\n");
+	mprint("--708-min-duration MS: Show every CEA-708 caption for at least MS\n");
+	mprint("                       milliseconds, unless the next one starts earlier.\n");
+	mprint("--708-max-duration MS: Show every CEA-708 caption for at most MS\n");
+	mprint("                       milliseconds.\n");
+	mprint("     --708-min-gap MS: Keep at least MS milliseconds between two CEA-708\n");
+	mprint("                       captions, ending the earlier one sooner if needed.\n");
+	mprint("          --708-merge: Merge adjacent CEA-708 captions with the same text\n");
+	mprint("                       into one.\n");
 	mprint("\n");
 	mprint("In general, if you want English subtitles you don't need to use these options\n");
 	mprint("as they are broadcast in field 1, channel 1. If you want the second language\n");
@@ -679,6 +728,8 @@ void print_usage(void)
 	mprint("                       using the Chinese (Traditional) trained data\n");
 	mprint("                       This option is also helpful when the traineddata file\n");
 	mprint("                       has non standard names that don't follow ISO specs\n");
+	mprint("                       With -hardsubx several files can be joined with +,\n");
+	mprint("                       e.g. '-ocrlang eng+hin' for English and Hindi.\n");
 	mprint("          -quant mode: How to quantize the bitmap before passing it to tesseract\n");
 	mprint("                       for OCR'ing.\n");
 	mprint("                       0: Don't quantize at all.\n");
@@ -984,6 +1035,66 @@ void print_usage(void)
 	mprint("                     Recommended values are in the range 80 to 100.\n");
 	mprint("                     The default value is 95\n");
 	mprint("\n");
+	mprint("              -roi : Specify a rectangle of the frame which is searched\n");
+	mprint("                     for subtitles, as LEFT,TOP,WIDTH,HEIGHT fractions of\n");
+	mprint("                     the frame size, or in pixels with a 'px' suffix.\n");
+	mprint("                     Can be given up to %d times to search several regions.\n", HARDSUBX_MAX_ROIS);
+	mprint("                     The default is the bottom quarter of the frame, or the\n");
+	mprint("                     bottom 8%% with -tickertext.\n");
+	mprint("                     e.g. -roi 0,0,1,0.2 (top fifth) or -roi 0,60,1920,200px\n");
+	mprint("\n");
+	mprint("        -letterbox : Detect black bars around the picture from the first\n");
+	mprint("                     N frames, e.g. -letterbox 50.\n");
+	mprint("                     The default region then covers the bottom quarter of\n");
+	mprint("                     the picture and the bar below it, and the edges of the\n");
+	mprint("                     bars are not taken as text. Fractions of -roi are of\n");
+	mprint("                     the picture, edges at 0 or 1 include the bar beyond.\n");
+	mprint("                     Rectangles in pixels are not adapted.\n");
+	mprint("\n");
+	mprint("        -debug_dir : Write the images of every processing stage of each\n");
+	mprint("                     frame as PNG files into the given directory, to tune\n");
+	mprint("                     the thresholds. The file names hold the frame number\n");
+	mprint("                     and time, e.g. frame000250_10000ms_feat.png\n");
+	mprint("\n");
+	mprint("    -change_thresh : Specify which part of the subtitle region, in percent\n");
+	mprint("                     of the text pixels, must change for a frame to be\n");
+	mprint("                     recognized again. The text of unchanged frames is\n");
+	mprint("                     taken from the last recognized frame.\n");
+	mprint("                     The default value is 5, 0 recognizes every frame.\n");
+	mprint("\n");
+	mprint(" -merge_similarity : Specify how similar, in percent of the characters,\n");
+	mprint("                     the texts of consecutive frames must be to become\n");
+	mprint("                     one subtitle. Of the merged texts the one recognized\n");
+	mprint("                     with the highest confidence is kept.\n");
+	mprint("                     The default value is 80, 100 only merges equal texts.\n");
+	mprint("\n");
+	mprint("     -tessdata_dir : Specify the tessdata directory with the .traineddata\n");
+	mprint("                     files of -ocrlang, or the directory containing it.\n");
+	mprint("                     By default it is searched for like for other OCR.\n");
+	mprint("\n");
+	mprint("              -psm : Specify the page segmentation mode of Tesseract,\n");
+	mprint("                     e.g. -psm 7 to treat the subtitle as a single line.\n");
+	mprint("                     Valid values are 0 to 13, see tesseract --help-psm.\n");
+	mprint("\n");
+	mprint("        -whitelist : Specify the only characters to recognize,\n");
+	mprint("                     e.g. -whitelist 0123456789 for numbers only.\n");
+	mprint("\n");
+	mprint("     -cue_position : Place the subtitles where they are burned in, with\n");
+	mprint("                     the line and position settings of WebVTT cues and\n");
+	mprint("                     \\pos tags of SSA/ASS dialogues.\n");
+	mprint("\n");
+	mprint("         -ocr_json : Write every subtitle with the recognized words, their\n");
+	mprint("                     confidence and bounding box in frame pixels to the\n");
+	mprint("                     given file, as a JSON array.\n");
+	mprint("\n");
+	mprint("     -conf_comment : Write the mean and minimum OCR confidence of every\n");
+	mprint("                     subtitle as a {comment} line in SRT output.\n");
+	mprint("\n");
+	mprint("    -review_thresh : Specify the confidence, 0 to 100, below which a\n");
+	mprint("                     recognized word marks its subtitle as needing\n");
+	mprint("                     review in -conf_comment and -ocr_json output.\n");
+	mprint("                     The default value is 0, which marks no subtitle.\n");
+	mprint("\n");
 	mprint("		-hcc	   : This option will be used if the file should have both\n");
 	mprint("					 closed captions and burned in subtitles\n");
 	mprint("            An example command for burned-in subtitle extraction is as follows:\n");
@@ -1441,6 +1552,202 @@ int parse_parameters(struct ccx_s_options *opt, int argc, char *argv[])
 					fatal(EXIT_MALFORMED_PARAMETER, "-whiteness_thresh has no argument.\n");
 				}
 			}
+			if (strcmp(argv[i], "-roi") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					struct hardsubx_roi roi;
+					int len = 0;
+					if (opt->hardsubx_roi_count >= HARDSUBX_MAX_ROIS)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-roi can be given at most %d times.\n", HARDSUBX_MAX_ROIS);
+					}
+					if (sscanf(argv[i], "%f,%f,%f,%f%n", &roi.left, &roi.top, &roi.width, &roi.height, &len) != 4)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-roi has an invalid value.\nExpected LEFT,TOP,WIDTH,HEIGHT e.g. 0,0.75,1,0.25 or 0,810,1920,270px\n");
+					}
+					roi.in_pixels = strcmp(argv[i] + len, "px") == 0;
+					if (!roi.in_pixels && argv[i][len] != '\0')
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-roi has an invalid suffix, only 'px' is allowed.\n");
+					}
+					if (roi.left < 0 || roi.top < 0 || roi.width <= 0 || roi.height <= 0 ||
+					    (!roi.in_pixels && (roi.left > 1.0 || roi.top > 1.0 || roi.width > 1.0 || roi.height > 1.0)))
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-roi has an invalid rectangle.\nFractions must be in [0,1] and the width and height must be positive\n");
+					}
+					opt->hardsubx_rois[opt->hardsubx_roi_count++] = roi;
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-roi has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-letterbox") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					opt->hardsubx_letterbox_frames = atoi(argv[i]);
+					if (opt->hardsubx_letterbox_frames <= 0)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-letterbox has either 0 or an invalid value supplied\n");
+					}
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-letterbox has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-debug_dir") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+					opt->hardsubx_debug_dir = argv[i];
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-debug_dir has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-change_thresh") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					char *end;
+					opt->hardsubx_change_thresh = strtof(argv[i], &end);
+					if (*end != '\0' || opt->hardsubx_change_thresh < 0.0 || opt->hardsubx_change_thresh > 100.0)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-change_thresh has an invalid value supplied\nValid values are in [0.0,100.0]\n");
+					}
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-change_thresh has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-merge_similarity") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					char *end;
+					opt->hardsubx_merge_similarity = strtof(argv[i], &end);
+					if (*end != '\0' || opt->hardsubx_merge_similarity < 0.0 || opt->hardsubx_merge_similarity > 100.0)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-merge_similarity has an invalid value supplied\nValid values are in [0.0,100.0]\n");
+					}
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-merge_similarity has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-tessdata_dir") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+					opt->hardsubx_tessdata_dir = argv[i];
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-tessdata_dir has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-psm") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					char *end;
+					opt->hardsubx_psm = (int)strtol(argv[i], &end, 10);
+					if (*end != '\0' || opt->hardsubx_psm < 0 || opt->hardsubx_psm > 13)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-psm must be between 0 and 13\n");
+					}
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-psm has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-whitelist") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+					opt->hardsubx_whitelist = argv[i];
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-whitelist has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-cue_position") == 0)
+			{
+				opt->hardsubx_cue_position = 1;
+				continue;
+			}
+			if (strcmp(argv[i], "-ocr_json") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+					opt->hardsubx_ocr_json = argv[i];
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-ocr_json has no argument.\n");
+				}
+			}
+			if (strcmp(argv[i], "-conf_comment") == 0)
+			{
+				opt->hardsubx_conf_comment = 1;
+				continue;
+			}
+			if (strcmp(argv[i], "-review_thresh") == 0)
+			{
+				if (i < argc - 1)
+				{
+					i++;
+
+					char *end;
+					opt->hardsubx_review_thresh = strtof(argv[i], &end);
+					if (*end != '\0' || opt->hardsubx_review_thresh < 0.0 || opt->hardsubx_review_thresh > 100.0)
+					{
+						fatal(EXIT_MALFORMED_PARAMETER, "-review_thresh has an invalid value supplied\nValid values are in [0.0,100.0]\n");
+					}
+
+					continue;
+				}
+				else
+				{
+					fatal(EXIT_MALFORMED_PARAMETER, "-review_thresh has no argument.\n");
+				}
+			}
 		}
 #endif // ENABLE_HARDSUBX
 
@@ -2362,6 +2669,213 @@ int parse_parameters(struct ccx_s_options *opt, int argc, char *argv[])
 				fatal(EXIT_MALFORMED_PARAMETER, "--service has no argument.\n");
 			}
 		}
+		if (strcmp(argv[i], "--708-report") == 0)
+		{
+			opt->settings_dtvcc.print_json_report = 1;
+			continue;
+		}
+		if (strcmp(argv[i], "--708-recovery") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				if (strcmp(argv[i], "none") == 0)
+					opt->settings_dtvcc.sequence_recovery = 0;
+				else if (strcmp(argv[i], "flush") == 0)
+					opt->settings_dtvcc.sequence_recovery = 1;
+				else if (strcmp(argv[i], "reset") == 0)
+					opt->settings_dtvcc.sequence_recovery = 2;
+				else
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-recovery only accepts none, flush or reset.\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-recovery has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-capture") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.capture_filename = argv[i];
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-capture has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-replay") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.replay_filename = argv[i];
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-replay has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-compliance") == 0)
+		{
+			opt->settings_dtvcc.compliance_check = 1;
+			continue;
+		}
+		if (strcmp(argv[i], "--708-rotate") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.rotate_size = atoi(argv[i]);
+				if (opt->settings_dtvcc.rotate_size <= 0)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-rotate needs a size in KB greater than 0.\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-rotate has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-output") == 0)
+		{
+			if (i < argc - 1)
+			{
+				if (opt->settings_dtvcc.outputs_count >= CCX_DTVCC_MAX_OUTPUTS)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-output can be given at most %d times.\n", CCX_DTVCC_MAX_OUTPUTS);
+				i++;
+				opt->settings_dtvcc.outputs[opt->settings_dtvcc.outputs_count++] = argv[i];
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-output has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-lang") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.service_languages = argv[i];
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-lang has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-offset") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				char *end;
+				opt->settings_dtvcc.time_offset = strtoll(argv[i], &end, 10);
+				if (*end != '\0')
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-offset only accepts integers (such as -300 or 300)\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-offset has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-timescale") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				double from, to;
+				int count = sscanf(argv[i], "%lf:%lf", &from, &to);
+				if (count == 2 && from > 0 && to > 0)
+					opt->settings_dtvcc.time_scale = from / to;
+				else if (count == 1 && from > 0 && !strchr(argv[i], ':'))
+					opt->settings_dtvcc.time_scale = from;
+				else
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-timescale only accepts FROM:TO (such as 25:23.976) or a factor\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-timescale has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-clip") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				char *separator = strrchr(argv[i], '-');
+				struct ccx_boundary_time start = {0}, end = {0};
+				if (separator == NULL)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-clip needs START-END\n");
+				*separator = '\0';
+				if ((*argv[i] && stringztoms(argv[i], &start) == -1) ||
+				    (separator[1] && stringztoms(separator + 1, &end) == -1))
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-clip only accepts SS, MM:SS or HH:MM:SS for START and END\n");
+				opt->settings_dtvcc.clip_start = start.set ? start.time_in_ms : -1;
+				opt->settings_dtvcc.clip_end = end.set ? end.time_in_ms : -1;
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-clip has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-min-duration") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.min_duration = atoi(argv[i]);
+				if (opt->settings_dtvcc.min_duration < 0)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-min-duration doesn't accept negative times.\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-min-duration has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-max-duration") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.max_duration = atoi(argv[i]);
+				if (opt->settings_dtvcc.max_duration < 0)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-max-duration doesn't accept negative times.\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-max-duration has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-min-gap") == 0)
+		{
+			if (i < argc - 1)
+			{
+				i++;
+				opt->settings_dtvcc.min_gap = atoi(argv[i]);
+				if (opt->settings_dtvcc.min_gap < 0)
+					fatal(EXIT_MALFORMED_PARAMETER, "--708-min-gap doesn't accept negative times.\n");
+				continue;
+			}
+			else
+			{
+				fatal(EXIT_MALFORMED_PARAMETER, "--708-min-gap has no argument.\n");
+			}
+		}
+		if (strcmp(argv[i], "--708-merge") == 0)
+		{
+			opt->settings_dtvcc.merge_identical = 1;
+			continue;
+		}
 		if (strcmp(argv[i], "-datapid") == 0)
 		{
 			if (i < argc - 1)
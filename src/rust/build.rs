@@ -29,7 +29,7 @@ fn main() {
     ]);
 
     #[cfg(feature = "hardsubx_ocr")]
-    allowlist_types.extend_from_slice(&["AVRational", "AVPacket", "AVFrame"]);
+    allowlist_types.extend_from_slice(&["AVRational", "AVPacket", "AVFrame", "hardsubx_roi"]);
 
     let mut builder = bindgen::Builder::default()
         // The input header we would like to generate
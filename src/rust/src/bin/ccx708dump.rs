@@ -0,0 +1,428 @@
+//! Inspect the CEA-708 data of a file
+//!
+//! Reads `cc_data` from a RCWT file (as written by `-out=bin`) or from a raw file of 3 byte
+//! `cc_data` triplets, and prints every assembled DTVCC packet with its service blocks and the
+//! commands in them. Every packet is then decoded by the 708 decoder of the library, and the
+//! state of the windows of its services is printed.
+//!
+//! The decoder runs in compliance mode, so no captions are written and the violations of
+//! CTA-708 it found are printed at the end.
+//!
+//! Usage: `ccx708dump FILE`
+
+#![allow(non_snake_case)]
+
+use std::{env, fs, process};
+
+use ccx_rust::bindings::*;
+use ccx_rust::decoder::commands::{self, C0CodeSet, C0Command, C1CodeSet, C1Command};
+use ccx_rust::decoder::{print_findings, Dtvcc};
+
+const RCWT_HEADER: [u8; 3] = [0xCC, 0xCC, 0xED];
+const RCWT_HEADER_LENGTH: usize = 11;
+const MAX_WINDOWS: usize = 8;
+const EXT1: u8 = 0x10;
+
+/// Returns the ids of the windows set in the bitmap
+fn windows_in_bitmap(bitmap: u8) -> Vec<usize> {
+    (0..MAX_WINDOWS)
+        .filter(|i| bitmap & (1 << i) != 0)
+        .collect()
+}
+
+/// Returns a hh:mm:ss,ms string of time
+fn time_str(time: i64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        time / 3_600_000,
+        time / 60_000 % 60,
+        time / 1000 % 60,
+        time % 1000
+    )
+}
+
+/// Print a C0 command, returns the number of used bytes
+fn dump_C0(block: &[u8]) -> usize {
+    let C0Command { command, length } = C0Command::new(block[0]);
+    let length = length as usize;
+    if length > block.len() {
+        println!(
+            "    C0 {:?}: command is {} bytes long but only {} are left",
+            command,
+            length,
+            block.len()
+        );
+        return block.len();
+    }
+    match command {
+        C0CodeSet::P16 => {
+            let sym = ((block[1] as u16) << 8) | block[2] as u16;
+            println!("    C0 P16 [{:04X}]", sym);
+        }
+        C0CodeSet::RESERVED => println!("    C0 reserved code [{:02X}]", block[0]),
+        _ => println!("    C0 {:?}", command),
+    }
+    length
+}
+
+/// Print a C1 command with its parameters, returns the number of used bytes
+fn dump_C1(block: &[u8]) -> usize {
+    let code = block[0];
+    let C1Command {
+        command,
+        length,
+        name,
+    } = C1Command::new(code);
+    let length = length as usize;
+    if length > block.len() {
+        println!(
+            "    C1 {}: command is {} bytes long but only {} are left",
+            name,
+            length,
+            block.len()
+        );
+        return block.len();
+    }
+    let params = &block[1..length];
+    let details = match command {
+        C1CodeSet::CLW | C1CodeSet::DSW | C1CodeSet::HDW | C1CodeSet::TGW | C1CodeSet::DLW => {
+            format!("windows={:?}", windows_in_bitmap(params[0]))
+        }
+        C1CodeSet::DLY => format!("tenths_of_sec={}", params[0]),
+        C1CodeSet::SPA => format!(
+            "pen_size={} offset={} text_tag={} font_tag={} edge_type={} underline={} italic={}",
+            params[0] & 0x3,
+            (params[0] >> 2) & 0x3,
+            (params[0] >> 4) & 0xf,
+            params[1] & 0x7,
+            (params[1] >> 3) & 0x7,
+            (params[1] >> 6) & 0x1,
+            (params[1] >> 7) & 0x1
+        ),
+        C1CodeSet::SPC => format!(
+            "fg_color={} fg_opacity={} bg_color={} bg_opacity={} edge_color={}",
+            params[0] & 0x3f,
+            (params[0] >> 6) & 0x3,
+            params[1] & 0x3f,
+            (params[1] >> 6) & 0x3,
+            params[2] & 0x3f
+        ),
+        C1CodeSet::SPL => format!("row={} column={}", params[0] & 0x0f, params[1] & 0x3f),
+        C1CodeSet::SWA => format!(
+            "fill_color={} fill_opacity={} border_color={} border_type={} justify={} scroll_dir={} \
+             print_dir={} word_wrap={} display_effect={} effect_dir={} effect_speed={}",
+            params[0] & 0x3f,
+            (params[0] >> 6) & 0x3,
+            params[1] & 0x3f,
+            ((params[2] >> 5) & 0x4) | ((params[1] >> 6) & 0x3),
+            params[2] & 0x3,
+            (params[2] >> 2) & 0x3,
+            (params[2] >> 4) & 0x3,
+            (params[2] >> 6) & 0x1,
+            params[3] & 0x3,
+            (params[3] >> 2) & 0x3,
+            (params[3] >> 4) & 0xf
+        ),
+        C1CodeSet::DF0
+        | C1CodeSet::DF1
+        | C1CodeSet::DF2
+        | C1CodeSet::DF3
+        | C1CodeSet::DF4
+        | C1CodeSet::DF5
+        | C1CodeSet::DF6
+        | C1CodeSet::DF7 => format!(
+            "priority={} col_lock={} row_lock={} visible={} anchor_vertical={} relative_pos={} \
+             anchor_horizontal={} rows={} columns={} anchor_point={} pen_style={} win_style={}",
+            params[0] & 0x7,
+            (params[0] >> 3) & 0x1,
+            (params[0] >> 4) & 0x1,
+            (params[0] >> 5) & 0x1,
+            params[1] & 0x7f,
+            params[1] >> 7,
+            params[2],
+            (params[3] & 0xf) + 1,
+            (params[4] & 0x3f) + 1,
+            params[3] >> 4,
+            params[5] & 0x7,
+            (params[5] >> 3) & 0x7
+        ),
+        C1CodeSet::RESERVED => format!("code={:02X}", code),
+        _ => String::new(),
+    };
+    println!("    C1 {} {}", name, details);
+    length
+}
+
+/// Print the text characters at the start of the block, returns the number of used bytes
+fn dump_text(block: &[u8]) -> usize {
+    let text: String = block
+        .iter()
+        .take_while(|&&c| (0x20..=0x7F).contains(&c) || c >= 0xA0)
+        .map(|&c| if c == 0x7F { '♪' } else { c as char })
+        .collect();
+    println!("    Text \"{}\"", text);
+    text.chars().count()
+}
+
+/// Print an extended code following EXT1, returns the number of used bytes including EXT1
+fn dump_extended(block: &[u8]) -> usize {
+    let code = match block.get(1) {
+        Some(&code) => code,
+        None => {
+            println!("    EXT1 without a code");
+            return 1;
+        }
+    };
+    let used = match code {
+        0..=0x1F => {
+            println!("    C2 reserved code [{:02X}]", code);
+            commands::handle_C2(code) as usize
+        }
+        0x20..=0x7F | 0xA0..=0xFF => {
+            println!(
+                "    {} [{:02X}]",
+                if code < 0x80 { "G2" } else { "G3" },
+                code
+            );
+            1
+        }
+        _ => {
+            println!("    C3 reserved code [{:02X}]", code);
+            commands::handle_C3(code, block.get(2).copied().unwrap_or(0)) as usize
+        }
+    };
+    1 + used
+}
+
+/// Print all commands of a service block
+fn dump_service_block(block: &[u8]) {
+    let mut i = 0;
+    while i < block.len() {
+        let used = match block[i] {
+            EXT1 => dump_extended(&block[i..]),
+            0..=0x1F => dump_C0(&block[i..]),
+            0x80..=0x9F => dump_C1(&block[i..]),
+            _ => dump_text(&block[i..]),
+        };
+        i += used.max(1);
+    }
+}
+
+/// Print the defined windows of a service, as kept by the decoder
+fn print_windows(dtvcc: &Dtvcc, service_number: usize, indent: &str) {
+    let decoder = match &dtvcc.decoders[service_number - 1] {
+        Some(decoder) => decoder,
+        None => return,
+    };
+    for (id, window) in decoder.windows.iter().enumerate() {
+        if window.is_defined == 0 {
+            continue;
+        }
+        println!(
+            "{}W{}{} {} {}x{} pen {}:{}",
+            indent,
+            id,
+            if decoder.current_window == id as i32 {
+                "*"
+            } else {
+                ""
+            },
+            if window.visible != 0 {
+                "visible"
+            } else {
+                "hidden"
+            },
+            window.row_count,
+            window.col_count,
+            window.pen_row,
+            window.pen_column
+        );
+        for row in window.rows.iter().take(window.row_count.max(0) as usize) {
+            let text: String = row
+                .iter()
+                .take(window.col_count.max(0) as usize)
+                .map(|sym| if sym.is_set() { sym.to_char() } else { ' ' })
+                .collect();
+            if !text.trim().is_empty() {
+                println!("{}  |{}|", indent, text.trim_end());
+            }
+        }
+    }
+}
+
+/// Assembles DTVCC packets from cc_data, prints them and decodes them with [Dtvcc]
+struct Inspector<'a> {
+    dtvcc: Dtvcc<'a>,
+    packet: Vec<u8>,
+}
+
+impl<'a> Inspector<'a> {
+    fn new(dtvcc: Dtvcc<'a>) -> Self {
+        Self {
+            dtvcc,
+            packet: Vec::new(),
+        }
+    }
+    fn process_cc_data(&mut self, fts: Option<i64>, cc_data: &[u8]) {
+        let cc_valid = (cc_data[0] & 4) >> 2;
+        let cc_type = cc_data[0] & 3;
+        if cc_valid == 0 || cc_type < 2 {
+            return;
+        }
+        if cc_type == 3 {
+            if !self.packet.is_empty() {
+                println!(
+                    "Incomplete packet of {} bytes dropped, new packet started",
+                    self.packet.len()
+                );
+            }
+            self.packet.clear();
+        } else if self.packet.is_empty() {
+            // Data without a packet start
+            return;
+        }
+        self.packet.extend_from_slice(&cc_data[1..3]);
+
+        let len = match self.packet[0] & 0x3F {
+            0 => 128,
+            size => size as usize * 2,
+        };
+        if self.packet.len() >= len {
+            let packet = std::mem::take(&mut self.packet);
+            self.dump_packet(fts, &packet[..len]);
+        }
+    }
+    fn dump_packet(&mut self, fts: Option<i64>, packet: &[u8]) {
+        let seq = (packet[0] & 0xC0) >> 6;
+        let time = fts
+            .map(time_str)
+            .unwrap_or_else(|| "--:--:--,---".to_owned());
+        // The last sequence is negative before the first packet
+        let last = self.dtvcc.last_sequence;
+        let gap = if last >= 0 && (last + 1) % 4 != seq as i32 {
+            format!(" (unexpected, expected {})", (last + 1) % 4)
+        } else {
+            String::new()
+        };
+        println!(
+            "[{}] Packet sequence {}{}, size {}",
+            time,
+            seq,
+            gap,
+            packet.len()
+        );
+
+        let mut services = Vec::new();
+        let mut pos = 1;
+        while pos < packet.len() {
+            let mut service_number = ((packet[pos] & 0xE0) >> 5) as usize;
+            let block_length = (packet[pos] & 0x1F) as usize;
+            if service_number == 7 && pos + 1 < packet.len() {
+                pos += 1;
+                service_number = (packet[pos] & 0x3F) as usize;
+            }
+            pos += 1;
+            if service_number == 0 {
+                if block_length == 0 {
+                    println!("  Null header");
+                } else {
+                    println!(
+                        "  Block of {} bytes for service 0, skipping rest of packet",
+                        block_length
+                    );
+                }
+                break;
+            }
+            if pos + block_length > packet.len() {
+                println!(
+                    "  Service {}, block size {} exceeds the packet",
+                    service_number, block_length
+                );
+                break;
+            }
+            println!("  Service {}, block size {}", service_number, block_length);
+            dump_service_block(&packet[pos..pos + block_length]);
+            if !services.contains(&service_number) {
+                services.push(service_number);
+            }
+            pos += block_length;
+        }
+
+        if let Some(fts) = fts {
+            self.dtvcc.timing.set_fts(fts);
+        }
+        self.dtvcc.process_packet(packet);
+        for service_number in services {
+            println!("  Service {} windows", service_number);
+            print_windows(&self.dtvcc, service_number, "    ");
+        }
+    }
+}
+
+/// Returns the settings of a decoder for all services, checking compliance instead of
+/// writing captions
+fn dtvcc_settings() -> ccx_decoder_dtvcc_settings {
+    // The C structures are plain data, all zero is a valid empty state
+    let timing: &'static mut ccx_common_timing_ctx =
+        Box::leak(Box::new(unsafe { std::mem::zeroed() }));
+    let report: &'static mut ccx_decoder_dtvcc_report =
+        Box::leak(Box::new(unsafe { std::mem::zeroed() }));
+    let mut settings: ccx_decoder_dtvcc_settings = unsafe { std::mem::zeroed() };
+    settings.enabled = 1;
+    settings.services_enabled.iter_mut().for_each(|x| *x = 1);
+    settings.active_services_count = settings.services_enabled.len() as i32;
+    settings.timing = timing;
+    settings.report = report;
+    settings.compliance_check = 1;
+    settings.time_scale = 1.0;
+    settings.clip_start = -1;
+    settings.clip_end = -1;
+    settings
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: {} FILE", args[0]);
+        eprintln!("FILE is a RCWT file or a raw file of 3 byte cc_data triplets");
+        process::exit(1);
+    }
+    let data = match fs::read(&args[1]) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Unable to read {}: {}", args[1], err);
+            process::exit(1);
+        }
+    };
+
+    // No captions are written in compliance mode, the encoder is only counted in
+    let mut encoder: encoder_ctx = unsafe { std::mem::zeroed() };
+    let settings = dtvcc_settings();
+    let mut dtvcc = Dtvcc::new(&settings);
+    dtvcc.encoder = Some(&mut encoder);
+
+    let mut inspector = Inspector::new(dtvcc);
+    if data.starts_with(&RCWT_HEADER) {
+        let mut pos = RCWT_HEADER_LENGTH;
+        while pos + 10 <= data.len() {
+            let mut fts = [0; 8];
+            fts.copy_from_slice(&data[pos..pos + 8]);
+            let fts = i64::from_le_bytes(fts);
+            let count = u16::from_le_bytes([data[pos + 8], data[pos + 9]]) as usize;
+            pos += 10;
+            let end = (pos + count * 3).min(data.len());
+            for cc_data in data[pos..end].chunks_exact(3) {
+                inspector.process_cc_data(Some(fts), cc_data);
+            }
+            pos = end;
+        }
+    } else {
+        for cc_data in data.chunks_exact(3) {
+            inspector.process_cc_data(None, cc_data);
+        }
+    }
+
+    if let Some(findings) = inspector.dtvcc.compliance_findings() {
+        print_findings(&findings);
+    }
+}
@@ -0,0 +1,93 @@
+//! Capture of assembled DTVCC packets
+//!
+//! Every packet is stored as a single line of text: the FTS in ms, the 2 bit sequence number and the
+//! packet bytes in hex, e.g. `1001 2 C2 21 41 42`. Lines starting with `#` are comments.
+//! A capture can be fed back into a decoder with [Dtvcc::replay_capture][super::Dtvcc::replay_capture()],
+//! so the decoding of a stream can be reproduced without the original input file.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+};
+
+use crate::bindings::*;
+
+const CAPTURE_HEADER: &str = "# CCExtractor CEA-708 packet capture v1";
+
+/// Writes assembled packets to a capture file
+pub struct PacketRecorder {
+    file: BufWriter<File>,
+}
+
+impl PacketRecorder {
+    /// Create the capture file, overwriting an existing one
+    pub fn create(filename: &str) -> Result<Self, String> {
+        let file = File::create(filename)
+            .map_err(|err| format!("Unable to create capture file {}: {}", filename, err))?;
+        let mut recorder = Self {
+            file: BufWriter::new(file),
+        };
+        writeln!(recorder.file, "{}", CAPTURE_HEADER).map_err(|err| err.to_string())?;
+        Ok(recorder)
+    }
+    /// Write a single packet to the capture
+    pub fn record(&mut self, fts: LLONG, sequence: u8, packet: &[u8]) -> Result<(), String> {
+        let data = packet
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(self.file, "{} {} {}", fts, sequence, data).map_err(|err| err.to_string())
+    }
+}
+
+/// A packet read from a capture
+pub struct CapturedPacket {
+    pub fts: LLONG,
+    pub sequence: u8,
+    pub data: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Parse a single line of a capture, returns `None` for comments and empty lines
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let mut fields = line.split_whitespace();
+        let fts = fields
+            .next()
+            .and_then(|fts| fts.parse().ok())
+            .ok_or_else(|| format!("Invalid FTS in capture line: {}", line))?;
+        let sequence = fields
+            .next()
+            .and_then(|seq| seq.parse().ok())
+            .filter(|&seq| seq < 4)
+            .ok_or_else(|| format!("Invalid sequence number in capture line: {}", line))?;
+        let data = fields
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|err| format!("Invalid packet data in capture line: {}: {}", line, err))?;
+        if data.is_empty() || data.len() > 128 {
+            return Err(format!("Invalid packet length in capture line: {}", line));
+        }
+        Ok(Some(Self {
+            fts,
+            sequence,
+            data,
+        }))
+    }
+}
+
+/// Read all packets of a capture
+pub fn read_capture<R: BufRead>(reader: R) -> Result<Vec<CapturedPacket>, String> {
+    let mut packets = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        if let Some(packet) = CapturedPacket::parse(&line)? {
+            packets.push(packet);
+        }
+    }
+    Ok(packets)
+}
@@ -44,6 +44,20 @@ impl C0CodeSet {
             _ => C0CodeSet::RESERVED,
         }
     }
+    /// Returns the byte value of the command, `None` for reserved codes
+    pub fn code(&self) -> Option<u8> {
+        match self {
+            C0CodeSet::NUL => Some(0),
+            C0CodeSet::ETX => Some(0x3),
+            C0CodeSet::BS => Some(0x8),
+            C0CodeSet::FF => Some(0xC),
+            C0CodeSet::CR => Some(0xD),
+            C0CodeSet::HCR => Some(0xE),
+            C0CodeSet::EXT1 => Some(0x10),
+            C0CodeSet::P16 => Some(0x18),
+            C0CodeSet::RESERVED => None,
+        }
+    }
 }
 
 /// C0 command with its length
@@ -103,6 +117,44 @@ pub enum C1CodeSet {
     RESERVED,
 }
 
+impl C1CodeSet {
+    /// Returns the byte value of the command, `None` for reserved codes
+    pub fn code(&self) -> Option<u8> {
+        let code = match self {
+            C1CodeSet::CW0 => 0x80,
+            C1CodeSet::CW1 => 0x81,
+            C1CodeSet::CW2 => 0x82,
+            C1CodeSet::CW3 => 0x83,
+            C1CodeSet::CW4 => 0x84,
+            C1CodeSet::CW5 => 0x85,
+            C1CodeSet::CW6 => 0x86,
+            C1CodeSet::CW7 => 0x87,
+            C1CodeSet::CLW => 0x88,
+            C1CodeSet::DSW => 0x89,
+            C1CodeSet::HDW => 0x8A,
+            C1CodeSet::TGW => 0x8B,
+            C1CodeSet::DLW => 0x8C,
+            C1CodeSet::DLY => 0x8D,
+            C1CodeSet::DLC => 0x8E,
+            C1CodeSet::RST => 0x8F,
+            C1CodeSet::SPA => 0x90,
+            C1CodeSet::SPC => 0x91,
+            C1CodeSet::SPL => 0x92,
+            C1CodeSet::SWA => 0x97,
+            C1CodeSet::DF0 => 0x98,
+            C1CodeSet::DF1 => 0x99,
+            C1CodeSet::DF2 => 0x9A,
+            C1CodeSet::DF3 => 0x9B,
+            C1CodeSet::DF4 => 0x9C,
+            C1CodeSet::DF5 => 0x9D,
+            C1CodeSet::DF6 => 0x9E,
+            C1CodeSet::DF7 => 0x9F,
+            C1CodeSet::RESERVED => return None,
+        };
+        Some(code)
+    }
+}
+
 /// C1 command with its length
 pub struct C1Command {
     pub command: C1CodeSet,
@@ -0,0 +1,158 @@
+//! Compliance checks for CTA-708 data
+//!
+//! In compliance mode the decoder still processes every packet, but instead of writing captions
+//! it collects the violations of the standard it encounters as [Finding]s. Packet level findings
+//! and the service bandwidth are tracked by the [ComplianceChecker] of the [Dtvcc][super::Dtvcc]
+//! context, everything inside a service block is checked by the service decoder.
+
+use std::fmt;
+
+use super::timing::get_time_str;
+use crate::bindings::*;
+
+/// Maximum bit rate of a single caption service, CTA-708-E 6.2.3
+const MAX_SERVICE_BITRATE: u64 = 9600;
+/// Length of the interval the service bandwidth is measured over, in ms
+const BANDWIDTH_INTERVAL: LLONG = 1000;
+
+/// Severity of a finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Allowed by the standard, but likely to cause problems with some decoders
+    Warning,
+    /// Violation of the standard
+    Error,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A single violation of the standard
+#[derive(Debug, Clone)]
+pub struct Finding {
+    /// Time of the packet the violation was found in
+    pub fts: LLONG,
+    pub severity: Severity,
+    /// Service the finding belongs to, 0 for the packet layer
+    pub service: u8,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layer = if self.service == 0 {
+            "packet".to_owned()
+        } else {
+            format!("service {}", self.service)
+        };
+        write!(
+            f,
+            "{} {:<7} {}: {}",
+            get_time_str(self.fts),
+            self.severity.name(),
+            layer,
+            self.message
+        )
+    }
+}
+
+/// Number of bytes a service received in the current interval
+#[derive(Debug, Clone, Copy, Default)]
+struct ServiceBandwidth {
+    interval_start: LLONG,
+    bytes: u64,
+}
+
+/// Collects the packet level findings and measures the bandwidth of the services
+#[derive(Debug)]
+pub struct ComplianceChecker {
+    pub findings: Vec<Finding>,
+    bandwidth: Vec<Option<ServiceBandwidth>>,
+}
+
+impl ComplianceChecker {
+    pub fn new() -> Self {
+        Self {
+            findings: Vec::new(),
+            bandwidth: vec![None; CCX_DTVCC_MAX_SERVICES as usize],
+        }
+    }
+    /// Add a packet level finding
+    pub fn add(&mut self, fts: LLONG, severity: Severity, message: String) {
+        self.findings.push(Finding {
+            fts,
+            severity,
+            service: 0,
+            message,
+        });
+    }
+    /// Count the bytes of a service block, including its header
+    ///
+    /// The bandwidth is checked whenever an interval of [BANDWIDTH_INTERVAL] ms is complete
+    pub fn count_service_bytes(&mut self, fts: LLONG, service_number: u8, bytes: u64) {
+        let index = (service_number - 1) as usize;
+        let mut bandwidth = self.bandwidth[index].unwrap_or(ServiceBandwidth {
+            interval_start: fts,
+            bytes: 0,
+        });
+        if fts - bandwidth.interval_start >= BANDWIDTH_INTERVAL {
+            self.check_bandwidth(service_number, &bandwidth);
+            bandwidth = ServiceBandwidth {
+                interval_start: fts,
+                bytes: 0,
+            };
+        }
+        bandwidth.bytes += bytes;
+        self.bandwidth[index] = Some(bandwidth);
+    }
+    /// Check the bandwidth of the last, possibly incomplete, intervals
+    pub fn finish(&mut self) {
+        for index in 0..self.bandwidth.len() {
+            if let Some(bandwidth) = self.bandwidth[index].take() {
+                self.check_bandwidth(index as u8 + 1, &bandwidth);
+            }
+        }
+    }
+    fn check_bandwidth(&mut self, service_number: u8, bandwidth: &ServiceBandwidth) {
+        let bitrate = bandwidth.bytes * 8 * 1000 / BANDWIDTH_INTERVAL as u64;
+        if bitrate > MAX_SERVICE_BITRATE {
+            self.findings.push(Finding {
+                fts: bandwidth.interval_start,
+                severity: Severity::Error,
+                service: service_number,
+                message: format!(
+                    "Service bandwidth of {} bps exceeds the maximum of {} bps",
+                    bitrate, MAX_SERVICE_BITRATE
+                ),
+            });
+        }
+    }
+}
+
+impl Default for ComplianceChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Print the findings to stderr, followed by a summary
+pub fn print_findings(findings: &[Finding]) {
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    for finding in findings {
+        eprintln!("{}", finding);
+    }
+    eprintln!(
+        "CEA-708 compliance: {} errors, {} warnings",
+        errors,
+        findings.len() - errors
+    );
+}
@@ -0,0 +1,122 @@
+//! Post-processing of the screens before they are written
+//!
+//! Screens can be very short, e.g. 1 ms when a window is replaced right after it was shown, or
+//! stay for minutes when no clear arrives. The [CueQueue] holds back one screen until the next
+//! one is known, so its display time can be limited and kept apart from the next screen, and
+//! screens with the same text can be merged. Times are in the time of the input, before the time
+//! transform of the outputs.
+
+use super::tv_screen::TvScreen;
+use super::CCX_DTVCC_SCREENGRID_ROWS;
+use crate::bindings::*;
+
+use log::debug;
+
+/// Screens which start at most this many ms after the previous one ended are adjacent
+const ADJACENT_GAP: LLONG = 50;
+
+/// Limits of the display time of the screens
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CueRules {
+    /// Minimum time a screen is shown, in ms
+    pub min_duration: Option<LLONG>,
+    /// Maximum time a screen is shown, in ms
+    pub max_duration: Option<LLONG>,
+    /// Minimum time between the end of a screen and the start of the next one, in ms
+    pub min_gap: LLONG,
+    /// Merge adjacent screens with the same text
+    pub merge_identical: bool,
+}
+
+impl CueRules {
+    /// Returns `true` if any rule is set, screens are written directly otherwise
+    pub fn is_active(&self) -> bool {
+        self.min_duration.is_some()
+            || self.max_duration.is_some()
+            || self.min_gap > 0
+            || self.merge_identical
+    }
+    /// Adjust the hide time of a screen, given the show time of the next screen
+    fn apply(&self, screen: &mut TvScreen, next_show: Option<LLONG>) {
+        let show = screen.time_ms_show;
+        let mut hide = screen.time_ms_hide;
+        if let Some(min_duration) = self.min_duration {
+            hide = hide.max(show + min_duration);
+        }
+        if let Some(max_duration) = self.max_duration {
+            hide = hide.min(show + max_duration);
+        }
+        if let Some(next_show) = next_show {
+            hide = hide.min(next_show - self.min_gap).max(show);
+        }
+        if hide != screen.time_ms_hide {
+            debug!(
+                "dtvcc_cue_rules: Screen at {} ms ends at {} instead of {} ms",
+                show, hide, screen.time_ms_hide
+            );
+            screen.time_ms_hide = hide;
+        }
+    }
+}
+
+/// Holds back the last screen until its times are final
+pub struct CueQueue {
+    pub rules: CueRules,
+    pending: Option<TvScreen>,
+}
+
+impl CueQueue {
+    pub fn new(rules: CueRules) -> Self {
+        Self {
+            rules,
+            pending: None,
+        }
+    }
+    /// Add a screen, returns the previous screen once it can be written
+    ///
+    /// Empty screens are ignored
+    pub fn push(&mut self, screen: TvScreen) -> Option<TvScreen> {
+        if screen.is_empty() {
+            return None;
+        }
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                self.pending = Some(screen);
+                return None;
+            }
+        };
+        let gap = screen.time_ms_show - pending.time_ms_hide;
+        if self.rules.merge_identical
+            && gap <= ADJACENT_GAP.max(self.rules.min_gap)
+            && same_text(&pending, &screen)
+        {
+            debug!(
+                "dtvcc_cue_queue: Merging screen at {} ms into the one at {} ms",
+                screen.time_ms_show, pending.time_ms_show
+            );
+            pending.time_ms_hide = pending.time_ms_hide.max(screen.time_ms_hide);
+            self.pending = Some(pending);
+            return None;
+        }
+        self.rules.apply(&mut pending, Some(screen.time_ms_show));
+        self.pending = Some(screen);
+        Some(pending)
+    }
+    /// Returns the last screen at the end of the stream
+    pub fn finish(&mut self) -> Option<TvScreen> {
+        let mut pending = self.pending.take()?;
+        self.rules.apply(&mut pending, None);
+        Some(pending)
+    }
+}
+
+/// Returns `true` if both screens show the same text at the same place
+fn same_text(a: &TvScreen, b: &TvScreen) -> bool {
+    (0..CCX_DTVCC_SCREENGRID_ROWS as usize).all(|row| {
+        a.chars[row]
+            .iter()
+            .zip(b.chars[row].iter())
+            .all(|(a, b)| a.is_set() == b.is_set() && (!a.is_set() || a.sym == b.sym))
+    })
+}
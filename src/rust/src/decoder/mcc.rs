@@ -0,0 +1,400 @@
+//! MCC (MacCaption) output of the cc_data stream
+//!
+//! Every frame of `cc_data` the decoder receives is wrapped in a Caption Distribution Packet
+//! (CDP) inside an ancillary data packet and written as one timecode line of an MCC file. The
+//! file contains all caption data unchanged, so it can be used as a lossless archive of the
+//! 708 services. This is the output of `-out=mcc`, it is written to the output of the encoder.
+//!
+//! References:
+//! - SMPTE ST 334-1 Vertical Ancillary Data Mapping of Caption Data and Other Related Data
+//! - SMPTE ST 334-2 Caption Distribution Packet (CDP) Definition
+
+use std::{
+    ffi::CStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::output::{CaptionWriter, FileWriter, StdoutWriter};
+use super::timing::{frame_count, frames_to_timecode};
+use crate::{bindings::*, utils::is_true};
+
+use log::{debug, warn};
+
+const ANC_DID_CLOSED_CAPTIONING: u8 = 0x61;
+const ANC_SDID_CEA_708: u8 = 0x01;
+const CDP_IDENTIFIER: [u8; 2] = [0x96, 0x69];
+const CC_DATA_ID: u8 = 0x72;
+const CDP_FOOTER_ID: u8 = 0x74;
+/// Maximum number of cc_data triplets in a CDP, as `cc_count` is a 5 bit field
+const CDP_MAX_CC_COUNT: usize = 31;
+
+/// Frame rate of the video, as used by `current_frame_rate` of the decoder context
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRate {
+    /// CDP frame rate code, SMPTE ST 334-2 Table 3
+    pub code: u8,
+    /// Frames per second as a fraction, e.g. 30000 / 1001 for 29.97 fps
+    pub rate: (i64, i64),
+    /// Number of frames per second of the timecode
+    pub timecode_rate: u32,
+    /// Whether drop-frame timecodes are used, only possible for 29.97 and 59.94 fps
+    pub drop_frame: bool,
+}
+
+impl FrameRate {
+    /// Create the frame rate from the frame rate code, 29.97 fps is used for unknown codes
+    pub fn new(code: u32) -> Self {
+        let (rate, timecode_rate, drop_frame) = match code {
+            1 => ((24000, 1001), 24, false),
+            2 => ((24, 1), 24, false),
+            3 => ((25, 1), 25, false),
+            4 => ((30000, 1001), 30, true),
+            5 => ((30, 1), 30, false),
+            6 => ((50, 1), 50, false),
+            7 => ((60000, 1001), 60, true),
+            8 => ((60, 1), 60, false),
+            _ => {
+                warn!("dtvcc_mcc: Unknown frame rate code {}, using 29.97", code);
+                return FrameRate::new(4);
+            }
+        };
+        Self {
+            code: code as u8,
+            rate,
+            timecode_rate,
+            drop_frame,
+        }
+    }
+    /// Returns the timecode of the frame shown at `fts`
+    fn timecode(&self, fts: LLONG) -> String {
+        let frame = frame_count(fts, self.rate.0, self.rate.1);
+        frames_to_timecode(frame, self.timecode_rate as i64, self.drop_frame)
+    }
+}
+
+/// Writes the cc_data of every frame to an MCC file
+pub struct MccWriter {
+    writer: Box<dyn CaptionWriter>,
+    /// Whether drop-frame timecodes are used for 29.97 and 59.94 fps
+    drop_frame: bool,
+    frame_rate: Option<FrameRate>,
+    /// Sequence counter of the CDPs
+    cdp_sequence: u16,
+}
+
+impl MccWriter {
+    /// Create a writer for the given output, the header is written with the first frame
+    pub fn new(writer: Box<dyn CaptionWriter>, drop_frame: bool) -> Self {
+        Self {
+            writer,
+            drop_frame,
+            frame_rate: None,
+            cdp_sequence: 0,
+        }
+    }
+    /// Create a writer for the output file of the encoder, stdout if it has no filename
+    ///
+    /// Drop-frame timecodes are used if the encoder forces them, like the C MCC encoder does.
+    pub fn for_encoder(encoder: &encoder_ctx) -> Result<Self, String> {
+        let out = unsafe { encoder.out.as_ref() }.ok_or("Encoder has no output")?;
+        let writer: Box<dyn CaptionWriter> = if out.filename.is_null() {
+            Box::new(StdoutWriter::new())
+        } else {
+            let filename = unsafe { CStr::from_ptr(out.filename) }.to_string_lossy();
+            Box::new(
+                FileWriter::create(&filename)
+                    .map_err(|err| format!("Unable to create MCC file {}: {}", filename, err))?,
+            )
+        };
+        Ok(Self::new(writer, is_true(encoder.force_dropframe)))
+    }
+    /// Write the cc_data of a single frame
+    ///
+    /// The timecode is the one of the frame shown at `fts`, frames with several blocks of cc_data
+    /// get several lines with the same timecode. cc_data with more triplets than fit into a
+    /// single CDP is split across several lines as well.
+    pub fn write_frame(
+        &mut self,
+        fts: LLONG,
+        frame_rate_code: u32,
+        cc_data: &[u8],
+    ) -> Result<(), String> {
+        let cc_count = cc_data.len() / 3;
+        if cc_count == 0 {
+            return Ok(());
+        }
+        let frame_rate = match self.frame_rate {
+            Some(frame_rate) => frame_rate,
+            None => {
+                let mut frame_rate = FrameRate::new(frame_rate_code);
+                frame_rate.drop_frame &= self.drop_frame;
+                self.write_header(&frame_rate)?;
+                debug!("dtvcc_mcc: Captions start at {}", frame_rate.timecode(fts));
+                self.frame_rate = Some(frame_rate);
+                frame_rate
+            }
+        };
+
+        let timecode = frame_rate.timecode(fts);
+        for chunk in cc_data[..cc_count * 3].chunks(CDP_MAX_CC_COUNT * 3) {
+            let packet = anc_packet(chunk, &frame_rate, self.cdp_sequence);
+            self.cdp_sequence = self.cdp_sequence.wrapping_add(1);
+            let line = format!("{}\t{}\n", timecode, compress(&packet));
+            self.writer
+                .write(line.as_bytes())
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+    fn write_header(&mut self, frame_rate: &FrameRate) -> Result<(), String> {
+        let (date, time) = creation_date_time();
+        let header = format!(
+            "File Format=MacCaption_MCC V1.0\n\n\
+             ///////////////////////////////////////////////////////////////////////////////////\n\
+             // Telestream, LLC\n\
+             // Ancillary Data Packet Transfer File\n\
+             //\n\
+             // Permission to generate this format is granted provided that\n\
+             //   1. This ANC Transfer file format is used on an as-is basis and no warranty is given, and\n\
+             //   2. This entire descriptive information text is included in a generated .mcc file.\n\
+             //\n\
+             // General file format:\n\
+             //   HH:MM:SS:FF(tab)[Hexadecimal ANC data in groups of 2 characters]\n\
+             //     Hexadecimal data starts with the Ancillary Data Packet DID (Data ID defined in S291M)\n\
+             //       and concludes with the Check Sum following the User Data Words.\n\
+             //     Each time code line must contain at most one complete ancillary data packet.\n\
+             //     To transfer additional ANC Data successive lines may contain identical time code.\n\
+             //     Time Code Rate=[24, 25, 30, 30DF, 50, 60, 60DF]\n\
+             //\n\
+             //   ANC data bytes may be represented by one ASCII character according to the following schema:\n\
+             //     G  FAh 00h 00h\n\
+             //     H  2 x (FAh 00h 00h)\n\
+             //     I  3 x (FAh 00h 00h)\n\
+             //     J  4 x (FAh 00h 00h)\n\
+             //     K  5 x (FAh 00h 00h)\n\
+             //     L  6 x (FAh 00h 00h)\n\
+             //     M  7 x (FAh 00h 00h)\n\
+             //     N  8 x (FAh 00h 00h)\n\
+             //     O  9 x (FAh 00h 00h)\n\
+             //     P  FBh 80h 80h\n\
+             //     Q  FCh 80h 80h\n\
+             //     R  FDh 80h 80h\n\
+             //     S  96h 69h\n\
+             //     T  61h 01h\n\
+             //     U  E1h 00h 00h 00h\n\
+             //     Z  00h\n\
+             //\n\
+             ///////////////////////////////////////////////////////////////////////////////////\n\n\
+             UUID={}\n\
+             Creation Program=CCExtractor\n\
+             Creation Date={}\n\
+             Creation Time={}\n\
+             Time Code Rate={}{}\n\n",
+            uuid4(),
+            date,
+            time,
+            frame_rate.timecode_rate,
+            if frame_rate.drop_frame { "DF" } else { "" }
+        );
+        self.writer
+            .write_header(header.as_bytes())
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Wrap the cc_data in a CDP and an ancillary data packet
+///
+/// cc_data must not have more than [CDP_MAX_CC_COUNT] triplets.
+fn anc_packet(cc_data: &[u8], frame_rate: &FrameRate, sequence: u16) -> Vec<u8> {
+    assert!(cc_data.len() <= CDP_MAX_CC_COUNT * 3);
+    let cc_count = (cc_data.len() / 3) as u8;
+    // Header, cc_data section and footer
+    let cdp_length = 7 + 2 + cc_data.len() + 4;
+
+    let mut cdp = Vec::with_capacity(cdp_length);
+    cdp.extend_from_slice(&CDP_IDENTIFIER);
+    cdp.push(cdp_length as u8);
+    cdp.push((frame_rate.code << 4) | 0x0F);
+    // ccdata_present, caption_service_active and the reserved bit
+    cdp.push(0x43);
+    cdp.extend_from_slice(&sequence.to_be_bytes());
+    cdp.push(CC_DATA_ID);
+    cdp.push(0xE0 | cc_count);
+    cdp.extend_from_slice(cc_data);
+    cdp.push(CDP_FOOTER_ID);
+    cdp.extend_from_slice(&sequence.to_be_bytes());
+    // The sum of all bytes of the CDP including the checksum is zero
+    let sum = cdp.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    cdp.push(0u8.wrapping_sub(sum));
+
+    let mut packet = vec![ANC_DID_CLOSED_CAPTIONING, ANC_SDID_CEA_708, cdp.len() as u8];
+    packet.extend(cdp);
+    packet
+}
+
+/// Returns the packet as hex, with the MCC compression codes applied
+fn compress(data: &[u8]) -> String {
+    const PADDING: [u8; 3] = [0xFA, 0x00, 0x00];
+    const CODES: [(&[u8], char); 6] = [
+        (&[0xFB, 0x80, 0x80], 'P'),
+        (&[0xFC, 0x80, 0x80], 'Q'),
+        (&[0xFD, 0x80, 0x80], 'R'),
+        (&[0x96, 0x69], 'S'),
+        (&[0x61, 0x01], 'T'),
+        (&[0xE1, 0x00, 0x00, 0x00], 'U'),
+    ];
+
+    let mut out = String::new();
+    let mut rest = data;
+    'outer: while !rest.is_empty() {
+        let mut padding = 0;
+        while padding < 9 && rest[padding * 3..].starts_with(&PADDING) {
+            padding += 1;
+        }
+        if padding > 0 {
+            out.push((b'G' + padding as u8 - 1) as char);
+            rest = &rest[padding * 3..];
+            continue;
+        }
+        for (pattern, code) in CODES.iter() {
+            if rest.starts_with(pattern) {
+                out.push(*code);
+                rest = &rest[pattern.len()..];
+                continue 'outer;
+            }
+        }
+        if rest[0] == 0 {
+            out.push('Z');
+        } else {
+            out.push_str(&format!("{:02X}", rest[0]));
+        }
+        rest = &rest[1..];
+    }
+    out
+}
+
+/// Returns the current date and time in UTC, e.g. ("Monday, October 19, 2026", "9:05:00")
+fn creation_date_time() -> (String, String) {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    const WEEKDAYS: [&str; 7] = [
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+    ];
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let days = (now / 86400) as i64;
+    let seconds = now % 86400;
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!(
+        "{}, {} {}, {}",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        day,
+        year
+    );
+    let time = format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    (date, time)
+}
+
+/// Returns a random version 4 UUID
+///
+/// The randomness comes from the hasher seeds of the standard library, which is good enough
+/// to tell files apart.
+fn uuid4() -> String {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+    let mut bytes = [0u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos())
+                .unwrap_or(0),
+        );
+        chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    bytes[6] = (bytes[6] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anc_packet_cdp() {
+        let cc_data = [0xFC, 0x94, 0x20, 0xFF, 0x02, 0x21, 0xFA, 0x00, 0x00];
+        let packet = anc_packet(&cc_data, &FrameRate::new(4), 0x1234);
+        assert_eq!(
+            packet,
+            [
+                0x61, 0x01, 0x16, // ANC header
+                0x96, 0x69, 0x16, 0x4F, 0x43, 0x12, 0x34, // CDP header
+                0x72, 0xE3, // cc_data section
+                0xFC, 0x94, 0x20, 0xFF, 0x02, 0x21, 0xFA, 0x00, 0x00, //
+                0x74, 0x12, 0x34, 0x38, // footer and checksum
+            ]
+        );
+        let sum = packet[3..]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(sum, 0);
+    }
+
+    #[test]
+    fn anc_packet_max_cc_count() {
+        let cc_data = [0xFA, 0x00, 0x00].repeat(CDP_MAX_CC_COUNT);
+        let packet = anc_packet(&cc_data, &FrameRate::new(4), 0);
+        assert_eq!(packet[2] as usize, packet.len() - 3);
+        assert_eq!(packet[5] as usize, packet.len() - 3);
+        assert_eq!(packet[11], 0xE0 | CDP_MAX_CC_COUNT as u8);
+    }
+}
@@ -2,13 +2,31 @@
 //!
 //! Provides a CEA 708 decoder as defined by ANSI/CTA-708-E R-2018
 
-mod commands;
+mod capture;
+pub mod commands;
+mod compliance;
+mod cues;
+mod mcc;
 mod output;
+mod report;
+mod scc;
 mod service_decoder;
 mod timing;
 mod tv_screen;
 mod window;
 
+use capture::{read_capture, PacketRecorder};
+use compliance::ComplianceChecker;
+pub use compliance::{print_findings, Finding, Severity};
+use cues::CueRules;
+use mcc::MccWriter;
+use output::{OutputConfig, OutputSpec};
+pub use report::{DecoderReport, PacketReport, ServiceReport};
+use service_decoder::ServiceDecoder;
+use timing::{TimeTransform, Timing};
+
+use std::{ffi::CStr, io::BufRead};
+
 use crate::{bindings::*, utils::is_true};
 
 use log::{debug, warn};
@@ -20,46 +38,192 @@ const CCX_DTVCC_SCREENGRID_COLUMNS: u8 = 210;
 const CCX_DTVCC_MAX_ROWS: u8 = 15;
 const CCX_DTVCC_MAX_COLUMNS: u8 = 32 * 2;
 
+/// Recovery applied to the service decoders when packets were lost
+///
+/// The values match `sequence_recovery` of `ccx_decoder_dtvcc_settings`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequenceRecovery {
+    /// Only count the gap and continue with the existing decoder state
+    Ignore,
+    /// Print the visible windows and reset the decoders
+    Flush,
+    /// Reset the decoders, dropping everything which was not printed yet
+    Reset,
+}
+
+impl SequenceRecovery {
+    /// Create the recovery mode from the value of the C settings
+    pub fn new(value: i32) -> Self {
+        match value {
+            1 => SequenceRecovery::Flush,
+            2 => SequenceRecovery::Reset,
+            0 => SequenceRecovery::Ignore,
+            _ => {
+                warn!("Unknown sequence recovery mode {}, ignoring gaps", value);
+                SequenceRecovery::Ignore
+            }
+        }
+    }
+}
+
 /// Context required for processing 708 data
+///
+/// The context lives for the whole lifetime of the C decoder context and owns all service
+/// decoders, so no decoder state has to be copied between calls. Only the encoder is
+/// taken from the C side, as it is assigned after the decoder has been created. Service blocks
+/// are skipped as long as no encoder is set, as their captions could not be written.
 pub struct Dtvcc<'a> {
     pub is_active: bool,
     pub active_services_count: u8,
     pub services_active: Vec<i32>,
     pub report_enabled: bool,
     pub report: &'a mut ccx_decoder_dtvcc_report,
-    pub decoders: Vec<&'a mut dtvcc_service_decoder>,
+    pub packet_report: PacketReport,
+    pub print_json_report: bool,
+    pub decoders: Vec<Option<ServiceDecoder>>,
     pub packet: Vec<u8>,
     pub packet_length: u8,
     pub is_header_parsed: bool,
     pub last_sequence: i32,
-    pub encoder: &'a mut encoder_ctx,
+    pub encoder: Option<&'a mut encoder_ctx>,
     pub no_rollup: bool,
-    pub timing: &'a mut ccx_common_timing_ctx,
+    pub sequence_recovery: SequenceRecovery,
+    pub capture: Option<PacketRecorder>,
+    /// Whether complete packets are decoded, otherwise they are only recorded to the capture
+    pub decode_packets: bool,
+    /// Capture decoded instead of the 708 data of the input
+    pub replay_filename: Option<String>,
+    /// Whether the capture has been replayed already
+    pub replayed: bool,
+    pub compliance: Option<ComplianceChecker>,
+    /// MCC output, opened with the first frame of `-out=mcc`
+    pub mcc: Option<MccWriter>,
+    /// Whether the MCC output could not be opened or written
+    mcc_failed: bool,
+    pub output_config: OutputConfig,
+    pub timing: Timing<'a>,
 }
 
 impl<'a> Dtvcc<'a> {
     /// Create a new dtvcc context
-    pub fn new(ctx: &'a mut dtvcc_ctx) -> Self {
-        let report = unsafe { &mut *ctx.report };
-        let encoder = unsafe { &mut *(ctx.encoder as *mut encoder_ctx) };
-        let timing = unsafe { &mut *ctx.timing };
+    ///
+    /// Decoders are created on demand, when the first block of an enabled service is received
+    pub fn new(opts: &'a ccx_decoder_dtvcc_settings) -> Self {
+        let report = unsafe { &mut *opts.report };
+        let timing = Timing::new(unsafe { &mut *opts.timing });
+
+        let decoders = (0..CCX_DTVCC_MAX_SERVICES).map(|_| None).collect();
+
+        let capture = if opts.capture_filename.is_null() {
+            None
+        } else {
+            let filename = unsafe { CStr::from_ptr(opts.capture_filename) }.to_string_lossy();
+            match PacketRecorder::create(&filename) {
+                Ok(recorder) => Some(recorder),
+                Err(err) => {
+                    warn!("{}", err);
+                    None
+                }
+            }
+        };
 
         Self {
-            is_active: is_true(ctx.is_active),
-            active_services_count: ctx.active_services_count as u8,
-            services_active: ctx.services_active.to_vec(),
-            report_enabled: is_true(ctx.report_enabled),
+            is_active: is_true(opts.enabled),
+            active_services_count: opts.active_services_count as u8,
+            services_active: opts.services_enabled.to_vec(),
+            report_enabled: is_true(opts.print_file_reports),
             report,
-            decoders: ctx.decoders.iter_mut().collect(),
-            packet: ctx.current_packet.to_vec(),
-            packet_length: ctx.current_packet_length as u8,
-            is_header_parsed: is_true(ctx.is_current_packet_header_parsed),
-            last_sequence: ctx.last_sequence,
-            encoder,
-            no_rollup: is_true(ctx.no_rollup),
+            packet_report: PacketReport::default(),
+            print_json_report: is_true(opts.print_json_report),
+            decoders,
+            packet: vec![0; CCX_DTVCC_MAX_PACKET_LENGTH as usize],
+            packet_length: 0,
+            is_header_parsed: false,
+            last_sequence: CCX_DTVCC_NO_LAST_SEQUENCE,
+            encoder: None,
+            no_rollup: is_true(opts.no_rollup),
+            sequence_recovery: SequenceRecovery::new(opts.sequence_recovery),
+            capture,
+            decode_packets: true,
+            replay_filename: if opts.replay_filename.is_null() {
+                None
+            } else {
+                let filename = unsafe { CStr::from_ptr(opts.replay_filename) };
+                Some(filename.to_string_lossy().into_owned())
+            },
+            replayed: false,
+            compliance: if is_true(opts.compliance_check) {
+                Some(ComplianceChecker::new())
+            } else {
+                None
+            },
+            mcc: None,
+            mcc_failed: false,
+            output_config: output_config(opts),
             timing,
         }
     }
+    /// Returns the statistics collected so far
+    pub fn decoding_report(&self) -> DecoderReport {
+        DecoderReport {
+            packets: self.packet_report.clone(),
+            services: self
+                .decoders
+                .iter()
+                .flatten()
+                .map(|decoder| decoder.report.clone())
+                .collect(),
+        }
+    }
+    /// Finish the compliance checks and return all findings sorted by time
+    ///
+    /// Returns `None` if compliance mode is disabled
+    pub fn compliance_findings(&mut self) -> Option<Vec<Finding>> {
+        let checker = self.compliance.as_mut()?;
+        checker.finish();
+        let mut findings = checker.findings.clone();
+        for decoder in self.decoders.iter_mut().flatten() {
+            decoder.finish_compliance();
+            findings.extend(decoder.findings.iter().flatten().cloned());
+        }
+        findings.sort_by_key(|finding| finding.fts);
+        Some(findings)
+    }
+    /// Add a packet level finding if compliance mode is enabled
+    fn add_finding(&mut self, severity: Severity, message: String) {
+        if let Some(checker) = &mut self.compliance {
+            checker.add(self.timing.get_fts(), severity, message);
+        }
+    }
+    /// Write the cc_data of a frame to the MCC output of `encoder`
+    ///
+    /// The output is opened with the first frame. If it cannot be opened or written, the error
+    /// is returned and no further frames are written.
+    pub fn write_mcc(
+        &mut self,
+        encoder: &encoder_ctx,
+        frame_rate_code: u32,
+        cc_data: &[u8],
+    ) -> Result<(), String> {
+        let mcc = match &mut self.mcc {
+            Some(mcc) => mcc,
+            None if self.mcc_failed => return Ok(()),
+            None => match MccWriter::for_encoder(encoder) {
+                Ok(mcc) => self.mcc.get_or_insert(mcc),
+                Err(err) => {
+                    self.mcc_failed = true;
+                    return Err(err);
+                }
+            },
+        };
+        let fts = self.timing.ctx.fts_now + self.timing.ctx.fts_global;
+        mcc.write_frame(fts, frame_rate_code, cc_data)
+            .map_err(|err| {
+                self.mcc = None;
+                self.mcc_failed = true;
+                format!("Unable to write MCC output: {}", err)
+            })
+    }
     /// Process cc data and add it to the dtvcc packet
     pub fn process_cc_data(&mut self, cc_valid: u8, cc_type: u8, data1: u8, data2: u8) {
         if !self.is_active && !self.report_enabled {
@@ -73,7 +237,9 @@ impl<'a> Dtvcc<'a> {
                 debug!("dtvcc_process_data: DTVCC Channel Packet Data");
                 if cc_valid == 1 && self.is_header_parsed {
                     if self.packet_length > 253 {
+                        self.packet_report.oversized += 1;
                         warn!("dtvcc_process_data: Warning: Legal packet size exceeded (1), data not added.");
+                        self.add_finding(Severity::Error, "Legal packet size exceeded".to_owned());
                     } else {
                         self.add_data_to_packet(data1, data2);
 
@@ -97,10 +263,22 @@ impl<'a> Dtvcc<'a> {
                 debug!("dtvcc_process_data: DTVCC Channel Packet Start");
                 if cc_valid == 1 {
                     if self.packet_length > (CCX_DTVCC_MAX_PACKET_LENGTH - 1) {
+                        self.packet_report.oversized += 1;
                         warn!("dtvcc_process_data: Warning: Legal packet size exceeded (2), data not added.");
+                        self.add_finding(Severity::Error, "Legal packet size exceeded".to_owned());
                     } else {
                         if self.is_header_parsed {
+                            // A new packet started before the current one was complete, so the
+                            // partial packet is dropped and parsing continues with the new one
+                            self.packet_report.truncated += 1;
                             warn!("dtvcc_process_data: Warning: Incorrect packet length specified. Packet will be skipped.");
+                            self.add_finding(
+                                Severity::Error,
+                                format!(
+                                    "Packet truncated after {} bytes by the start of a new packet",
+                                    self.packet_length
+                                ),
+                            );
                             self.clear_packet();
                         }
                         self.add_data_to_packet(data1, data2);
@@ -131,6 +309,21 @@ impl<'a> Dtvcc<'a> {
         if self.packet_length == 0 {
             return;
         }
+        self.packet_report.received += 1;
+
+        if let Some(capture) = &mut self.capture {
+            let fts = self.timing.get_fts();
+            if let Err(err) = capture.record(fts, seq, &self.packet[..len as usize]) {
+                warn!(
+                    "dtvcc_process_current_packet: Unable to write capture: {}",
+                    err
+                );
+            }
+        }
+        if !self.decode_packets {
+            self.clear_packet();
+            return;
+        }
 
         // Check if current sequence is correct
         // Sequence number is a 2 bit rolling sequence from (0-3)
@@ -138,22 +331,57 @@ impl<'a> Dtvcc<'a> {
             && (self.last_sequence + 1) % 4 != seq as i32
         {
             warn!("dtvcc_process_current_packet: Unexpected sequence number, it is {} but should be {}", seq, (self.last_sequence +1) % 4);
+            // Only the number of lost packets modulo 4 can be known
+            let lost = (seq as i32 - self.last_sequence - 1).rem_euclid(4);
+            self.add_finding(
+                Severity::Error,
+                format!(
+                    "Unexpected sequence number {}, expected {}",
+                    seq,
+                    (self.last_sequence + 1) % 4
+                ),
+            );
+            self.recover_from_sequence_loss(lost as u64);
         }
         self.last_sequence = seq as i32;
 
+        // Decoders of the services with blocks in this packet
+        let mut packet_services: Vec<usize> = Vec::new();
         let mut pos: u8 = 1;
         while pos < len {
             let mut service_number = (self.packet[pos as usize] & 0xE0) >> 5; // 3 more significant bits
             let block_length = self.packet[pos as usize] & 0x1F; // 5 less significant bits
             debug!("dtvcc_process_current_packet: Standard header Service number: {}, Block length: {}", service_number, block_length);
 
+            let mut is_legal = true;
             if service_number == 7 {
                 // There is an extended header
                 // CEA-708-E 6.2.2 Extended Service Block Header
                 pos += 1;
+                if pos >= len {
+                    warn!("dtvcc_process_current_packet: Extended header missing at the end of packet");
+                    self.add_finding(
+                        Severity::Error,
+                        "Extended service block header missing at the end of packet".to_owned(),
+                    );
+                    break;
+                }
                 service_number = self.packet[pos as usize] & 0x3F; // 6 more significant bits
-                if service_number > 7 {
+                debug!(
+                    "dtvcc_process_current_packet: Extended header Service number: {}",
+                    service_number
+                );
+                if service_number < 7 {
+                    // Extended headers are only allowed for services 7 to 63
                     warn!("dtvcc_process_current_packet: Illegal service number in extended header: {}", service_number);
+                    self.add_finding(
+                        Severity::Error,
+                        format!(
+                            "Illegal service number {} in extended service block header",
+                            service_number
+                        ),
+                    );
+                    is_legal = false;
                 }
             }
 
@@ -161,20 +389,71 @@ impl<'a> Dtvcc<'a> {
 
             if service_number == 0 && block_length != 0 {
                 // Illegal, but specs say what to do...
+                self.add_finding(
+                    Severity::Error,
+                    format!("Null service block with length {}", block_length),
+                );
                 pos = len; // Move to end
                 break;
             }
 
-            if block_length != 0 {
+            if pos + block_length > len {
+                warn!("dtvcc_process_current_packet: Service block of {} bytes exceeds the packet, skipping rest of packet.", block_length);
+                self.add_finding(
+                    Severity::Error,
+                    format!(
+                        "Service block of {} bytes exceeds the packet length of {}",
+                        block_length, len
+                    ),
+                );
+                pos = len;
+                break;
+            }
+
+            if block_length != 0 && is_legal {
                 self.report.services[service_number as usize] = 1;
+                if let Some(checker) = &mut self.compliance {
+                    let header_length = if service_number < 7 { 1 } else { 2 };
+                    checker.count_service_bytes(
+                        self.timing.get_fts(),
+                        service_number,
+                        (header_length + block_length) as u64,
+                    );
+                }
             }
 
-            if service_number > 0 && is_true(self.services_active[(service_number - 1) as usize]) {
-                let decoder = &mut self.decoders[(service_number - 1) as usize];
+            if service_number > 0
+                && is_legal
+                && is_true(self.services_active[(service_number - 1) as usize])
+            {
+                let compliance = self.compliance.is_some();
+                let output_config = &self.output_config;
+                let decoder =
+                    self.decoders[(service_number - 1) as usize].get_or_insert_with(|| {
+                        debug!(
+                            "dtvcc_process_current_packet: Creating decoder for service {}",
+                            service_number
+                        );
+                        ServiceDecoder::new(service_number as i32, compliance, output_config)
+                    });
+                decoder.report.service_blocks += 1;
+                if !packet_services.contains(&((service_number - 1) as usize)) {
+                    packet_services.push((service_number - 1) as usize);
+                }
+                let encoder = match self.encoder.as_deref_mut() {
+                    Some(encoder) => encoder,
+                    None => {
+                        warn!(
+                            "dtvcc_process_current_packet: No encoder set, skipping service block"
+                        );
+                        pos += block_length;
+                        continue;
+                    }
+                };
                 decoder.process_service_block(
                     &self.packet[pos as usize..(pos + block_length) as usize],
-                    self.encoder,
-                    self.timing,
+                    encoder,
+                    &mut self.timing,
                     self.no_rollup,
                 );
             }
@@ -182,11 +461,78 @@ impl<'a> Dtvcc<'a> {
             pos += block_length // Skip data
         }
 
-        self.clear_packet();
-
         if len < 128 && self.packet[pos as usize] != 0 {
             // Null header is mandatory if there is room
+            self.packet_report.missing_null_headers += 1;
+            for &index in &packet_services {
+                if let Some(decoder) = &mut self.decoders[index] {
+                    decoder.report.missing_null_headers += 1;
+                }
+            }
             warn!("dtvcc_process_current_packet: Warning: Null header expected but not found.");
+            self.add_finding(
+                Severity::Error,
+                "Null header expected but not found".to_owned(),
+            );
+        }
+
+        self.clear_packet();
+    }
+    /// Feed all packets of a capture into the decoder, writing the captions with `encoder`
+    ///
+    /// The FTS of every packet is restored before it is processed, so the output is the same as
+    /// the one of the run which recorded the capture. The recorded sequence numbers are checked
+    /// for lost packets like the ones of the input.
+    pub fn replay_capture<R: BufRead>(
+        &mut self,
+        reader: R,
+        encoder: &'a mut encoder_ctx,
+    ) -> Result<(), String> {
+        let packets = read_capture(reader)?;
+        self.encoder = Some(encoder);
+        for packet in packets {
+            let len = packet.data.len();
+            debug!(
+                "dtvcc_replay_capture: FTS: {}, sequence: {}, length: {}",
+                packet.fts, packet.sequence, len
+            );
+            self.timing.set_fts(packet.fts);
+            let mut data = packet.data;
+            // The sequence number of the capture line is used for the packet header
+            data[0] = (data[0] & 0x3F) | (packet.sequence << 6);
+            self.process_packet(&data);
+        }
+        Ok(())
+    }
+    /// Process a complete packet, e.g. one read from a capture, at the current FTS
+    pub fn process_packet(&mut self, packet: &[u8]) {
+        let len = packet.len().min(self.packet.len());
+        self.clear_packet();
+        self.packet[..len].copy_from_slice(&packet[..len]);
+        self.packet_length = len as u8;
+        self.process_current_packet(len as u8);
+    }
+    /// Count the lost packets and apply the configured recovery to the service decoders
+    ///
+    /// Commands and text of the lost packets are missing, so continuing with the old window state
+    /// could combine unrelated captions.
+    pub fn recover_from_sequence_loss(&mut self, lost_packets: u64) {
+        self.packet_report.sequence_errors += 1;
+        self.packet_report.lost_packets += lost_packets;
+        if self.sequence_recovery == SequenceRecovery::Ignore {
+            return;
+        }
+        debug!(
+            "dtvcc_process_current_packet: Recovering from sequence loss: {:?}",
+            self.sequence_recovery
+        );
+        for decoder in self.decoders.iter_mut().flatten() {
+            if self.sequence_recovery == SequenceRecovery::Flush {
+                if let Some(encoder) = self.encoder.as_deref_mut() {
+                    decoder.print_visible_windows(encoder, &mut self.timing);
+                }
+            }
+            decoder.handle_reset();
         }
     }
     /// Clear current packet
@@ -197,6 +543,51 @@ impl<'a> Dtvcc<'a> {
     }
 }
 
+/// Build the output configuration of the services from the settings
+///
+/// Invalid outputs and languages are skipped with a warning
+fn output_config(opts: &ccx_decoder_dtvcc_settings) -> OutputConfig {
+    let mut specs = Vec::new();
+    for spec in opts.outputs[..opts.outputs_count.max(0) as usize].iter() {
+        let spec = unsafe { CStr::from_ptr(*spec) }.to_string_lossy();
+        match OutputSpec::parse(&spec) {
+            Ok(spec) => specs.push(spec),
+            Err(err) => warn!("dtvcc_output_config: {}", err),
+        }
+    }
+    let languages = if opts.service_languages.is_null() {
+        Vec::new()
+    } else {
+        let list = unsafe { CStr::from_ptr(opts.service_languages) }.to_string_lossy();
+        OutputConfig::parse_languages(&list).unwrap_or_else(|err| {
+            warn!("dtvcc_output_config: {}", err);
+            Vec::new()
+        })
+    };
+    let time_transform = TimeTransform {
+        offset: opts.time_offset,
+        scale: if opts.time_scale > 0.0 {
+            opts.time_scale
+        } else {
+            1.0
+        },
+        clip_start: Some(opts.clip_start).filter(|time| *time >= 0),
+        clip_end: Some(opts.clip_end).filter(|time| *time >= 0),
+    };
+    OutputConfig {
+        specs,
+        languages,
+        rotate_size: opts.rotate_size.max(0) as u64 * 1024,
+        time_transform,
+        cue_rules: CueRules {
+            min_duration: Some(opts.min_duration as LLONG).filter(|time| *time > 0),
+            max_duration: Some(opts.max_duration as LLONG).filter(|time| *time > 0),
+            min_gap: opts.min_gap.max(0) as LLONG,
+            merge_identical: is_true(opts.merge_identical),
+        },
+    }
+}
+
 /// A single character symbol
 ///
 /// sym stores the symbol
@@ -215,6 +606,10 @@ impl dtvcc_symbol {
     pub fn is_set(&self) -> bool {
         is_true(self.init)
     }
+    /// Returns the character of the symbol, as written to the text outputs
+    pub fn to_char(&self) -> char {
+        tv_screen::symbol_to_char(self.sym)
+    }
 }
 
 impl Default for dtvcc_symbol {
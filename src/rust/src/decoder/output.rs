@@ -1,86 +1,557 @@
 //! Utilty functions to write captions
 
-#[cfg(unix)]
-use std::os::unix::prelude::{FromRawFd, IntoRawFd};
-#[cfg(windows)]
-use std::os::windows::io::{FromRawHandle, IntoRawHandle};
-use std::{fs::File, io::Write};
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{self, BufWriter, Stdout, Write},
+    path::Path,
+};
 
-use crate::{bindings::*, utils::is_true};
+use super::cues::CueRules;
+use super::scc::StyledChar;
+use super::timing::TimeTransform;
+use crate::{
+    bindings::*,
+    utils::{is_false, is_true},
+};
 
 use log::{debug, warn};
 
+/// Destination of the captions of a service
+///
+/// Outputs may buffer, everything is written out at the latest when [flush][Self::flush] is
+/// called.
+pub trait CaptionWriter {
+    /// Write the data
+    fn write(&mut self, buf: &[u8]) -> io::Result<()>;
+    /// Write data which has to be at the start of every output file, e.g. a BOM or a header
+    fn write_header(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.write(buf)
+    }
+    /// Mark the end of a caption, outputs are only split between captions
+    fn end_caption(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    /// Write out all buffered data
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Buffered output to a file
+pub struct FileWriter {
+    file: BufWriter<File>,
+}
+
+impl FileWriter {
+    pub fn create(filename: &str) -> io::Result<Self> {
+        debug!("dtvcc_file_writer: creating {}", filename);
+        Ok(Self {
+            file: BufWriter::new(File::create(filename)?),
+        })
+    }
+}
+
+impl CaptionWriter for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Buffered output to stdout
+///
+/// Every caption is flushed on its own, so the captions of different services are not mixed up.
+pub struct StdoutWriter {
+    stdout: BufWriter<Stdout>,
+}
+
+impl StdoutWriter {
+    pub fn new() -> Self {
+        Self {
+            stdout: BufWriter::new(io::stdout()),
+        }
+    }
+}
+
+impl Default for StdoutWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptionWriter for StdoutWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stdout.write_all(buf)
+    }
+    fn end_caption(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+/// Output to memory, e.g. to inspect the output of a writer
+impl CaptionWriter for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Buffered output to a series of files
+///
+/// Once a file has reached the maximum size, the next caption is written to a new file. The
+/// first file uses the given filename, the following ones get a counter appended to the file
+/// stem, e.g. `video_1.srt`. Every file starts with the headers and ends with the footer, so
+/// each of them is complete on its own.
+pub struct RotatingFileWriter {
+    filename: String,
+    max_size: u64,
+    footer: Vec<u8>,
+    headers: Vec<u8>,
+    file: FileWriter,
+    /// Number of the current file, 0 for the first one
+    index: u32,
+    /// Number of bytes written to the current file
+    size: u64,
+}
+
+impl RotatingFileWriter {
+    pub fn create(filename: &str, max_size: u64, footer: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            filename: filename.to_owned(),
+            max_size,
+            footer: footer.to_vec(),
+            headers: Vec::new(),
+            file: FileWriter::create(filename)?,
+            index: 0,
+            size: 0,
+        })
+    }
+    /// Returns the filename of the file with the given number
+    fn part_filename(&self, index: u32) -> String {
+        let path = Path::new(&self.filename);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, index, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, index),
+        };
+        path.with_file_name(name).to_string_lossy().into_owned()
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.write(&self.footer)?;
+        self.file.flush()?;
+        self.index += 1;
+        self.file = FileWriter::create(&self.part_filename(self.index))?;
+        self.file.write(&self.headers)?;
+        self.size = self.headers.len() as u64;
+        Ok(())
+    }
+}
+
+impl CaptionWriter for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.size += buf.len() as u64;
+        self.file.write(buf)
+    }
+    fn write_header(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.headers.extend_from_slice(buf);
+        self.write(buf)
+    }
+    fn end_caption(&mut self) -> io::Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Format of a caption output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Srt,
+    Sami,
+    Transcript,
+    Scc,
+    WebVtt,
+    /// One JSON object per caption and line
+    Json,
+    /// Format which the 708 decoder can't write, only a debug log is written
+    Unsupported,
+}
+
+impl OutputFormat {
+    /// Returns the format for the output format of the encoder
+    pub fn from_ccx(format: ccx_output_format) -> Self {
+        match format {
+            ccx_output_format::CCX_OF_SRT => OutputFormat::Srt,
+            ccx_output_format::CCX_OF_SAMI => OutputFormat::Sami,
+            ccx_output_format::CCX_OF_TRANSCRIPT => OutputFormat::Transcript,
+            ccx_output_format::CCX_OF_SCC => OutputFormat::Scc,
+            ccx_output_format::CCX_OF_WEBVTT => OutputFormat::WebVtt,
+            _ => OutputFormat::Unsupported,
+        }
+    }
+    /// Returns the format with the given name, e.g. `srt`
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "srt" => Ok(OutputFormat::Srt),
+            "sami" | "smi" => Ok(OutputFormat::Sami),
+            "transcript" | "txt" => Ok(OutputFormat::Transcript),
+            "scc" => Ok(OutputFormat::Scc),
+            "webvtt" | "vtt" => Ok(OutputFormat::WebVtt),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format {}", name)),
+        }
+    }
+    /// Returns the file extension of the format, including the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Srt => ".srt",
+            OutputFormat::Sami => ".smi",
+            OutputFormat::Transcript => ".txt",
+            OutputFormat::Scc => ".scc",
+            OutputFormat::WebVtt => ".vtt",
+            OutputFormat::Json => ".json",
+            OutputFormat::Unsupported => "",
+        }
+    }
+}
+
+/// Filename template used if an output only specifies the format
+const DEFAULT_TEMPLATE: &str = "{base}.p{program}.svc{service}{ext}";
+
+/// Additional output of every service, given as `FORMAT[:TEMPLATE]`
+///
+/// The template can contain the placeholders `{base}` (output filename without extension),
+/// `{program}`, `{service}` (two digits), `{lang}` (language of the service, `und` if unknown)
+/// and `{ext}` (extension of the format, including the dot).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSpec {
+    pub format: OutputFormat,
+    pub template: String,
+}
+
+impl OutputSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, template) = match spec.find(':') {
+            Some(pos) => (&spec[..pos], &spec[pos + 1..]),
+            None => (spec, DEFAULT_TEMPLATE),
+        };
+        let format = OutputFormat::from_name(&name.to_ascii_lowercase())?;
+        if template.is_empty() {
+            return Err(format!("Output {} has an empty filename template", spec));
+        }
+        Ok(Self {
+            format,
+            template: template.to_owned(),
+        })
+    }
+    /// Returns the filename of the output for the service
+    pub fn filename(&self, base: &str, program: i32, service: i32, language: &str) -> String {
+        self.template
+            .replace("{base}", base)
+            .replace("{program}", &program.to_string())
+            .replace("{service}", &format!("{:02}", service))
+            .replace("{lang}", language)
+            .replace("{ext}", self.format.extension())
+    }
+}
+
+/// Configuration of the outputs of all services
+#[derive(Debug, Clone, Default)]
+pub struct OutputConfig {
+    /// Outputs of every service, if empty the output of the encoder is used
+    pub specs: Vec<OutputSpec>,
+    /// Language of each service, for the `{lang}` placeholder and JSON output
+    pub languages: Vec<Option<String>>,
+    /// Start a new file after this many bytes, 0 to write a single file
+    pub rotate_size: u64,
+    /// Transformation of the caption times, without the delay of the encoder
+    pub time_transform: TimeTransform,
+    /// Limits of the display time of the screens
+    pub cue_rules: CueRules,
+}
+
+impl OutputConfig {
+    /// Parse the languages of the services, given as e.g. `1=eng,2=spa`
+    pub fn parse_languages(list: &str) -> Result<Vec<Option<String>>, String> {
+        let mut languages = vec![None; CCX_DTVCC_MAX_SERVICES as usize];
+        for entry in list.split(',').filter(|entry| !entry.is_empty()) {
+            let (service, language) = match entry.find('=') {
+                Some(pos) => (&entry[..pos], &entry[pos + 1..]),
+                None => return Err(format!("Invalid service language {}", entry)),
+            };
+            let service: usize = service
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid service number in {}", entry))?;
+            if service == 0 || service > languages.len() {
+                return Err(format!("Invalid service number in {}", entry));
+            }
+            languages[service - 1] = Some(language.trim().to_owned());
+        }
+        Ok(languages)
+    }
+    /// Returns the outputs of a service
+    pub fn service_outputs(&self, service_number: i32) -> Vec<ServiceOutput> {
+        let language = self
+            .languages
+            .get((service_number - 1) as usize)
+            .cloned()
+            .flatten();
+        if self.specs.is_empty() {
+            return vec![self.service_output(None, language)];
+        }
+        self.specs
+            .iter()
+            .map(|spec| self.service_output(Some(spec.clone()), language.clone()))
+            .collect()
+    }
+    fn service_output(&self, spec: Option<OutputSpec>, language: Option<String>) -> ServiceOutput {
+        ServiceOutput {
+            spec,
+            writer: None,
+            language,
+            rotate_size: self.rotate_size,
+            time_transform: self.time_transform,
+            counter: 0,
+            header_written: false,
+            scc_roll_up_lines: Vec::new(),
+        }
+    }
+}
+
+/// Output of a service
+///
+/// The writer is created with the first caption and kept for the lifetime of the service decoder.
+#[derive(Default)]
+pub struct ServiceOutput {
+    /// Format and filename of the output, `None` for the output of the encoder
+    pub spec: Option<OutputSpec>,
+    pub writer: Option<Box<dyn CaptionWriter>>,
+    /// Language of the service, if known
+    pub language: Option<String>,
+    /// Start a new file after this many bytes, 0 to write a single file
+    pub rotate_size: u64,
+    /// Transformation of the caption times, without the delay of the encoder
+    pub time_transform: TimeTransform,
+    /// Number of captions written
+    pub counter: u32,
+    /// Whether the header of the format (WebVTT, SAMI or SCC) was written
+    pub header_written: bool,
+    /// Lines of the last roll-up caption written as SCC, which 608 decoders still show
+    pub scc_roll_up_lines: Vec<Vec<StyledChar>>,
+}
+
 // Context for writing subtitles to file
 pub struct Writer<'a> {
-    pub cea_708_counter: &'a mut u32,
-    pub subs_delay: LLONG,
+    /// Transformation of the caption times, including the delay of the encoder
+    pub time_transform: TimeTransform,
     pub crlf: String,
-    pub write_format: ccx_output_format,
+    pub write_format: OutputFormat,
     pub writer_ctx: &'a mut dtvcc_writer_ctx,
     pub no_font_color: bool,
     pub transcript_settings: &'a ccx_encoders_transcript_format,
     pub no_bom: i32,
+    /// Number of rows which could not be converted to the charset
+    pub charset_failures: u32,
+    pub service_number: i32,
+    pub program_number: i32,
+    /// Filename of the output without extension, for filename templates
+    pub base_filename: Option<String>,
+    pub output: &'a mut ServiceOutput,
 }
 
 impl<'a> Writer<'a> {
-    /// Create a new writer context
+    /// Create a new writer context for the service, using the settings of the encoder
     pub fn new(
-        cea_708_counter: &'a mut u32,
-        subs_delay: LLONG,
-        write_format: ccx_output_format,
-        writer_ctx: &'a mut dtvcc_writer_ctx,
-        no_font_color: i32,
-        transcript_settings: &'a ccx_encoders_transcript_format,
-        no_bom: i32,
+        encoder: &'a mut encoder_ctx,
+        service_number: i32,
+        output: &'a mut ServiceOutput,
     ) -> Self {
+        let write_format = match &output.spec {
+            Some(spec) => spec.format,
+            None => OutputFormat::from_ccx(encoder.write_format),
+        };
+        let base_filename = if output.writer.is_none() && output.spec.is_some() {
+            base_filename(encoder, service_number)
+        } else {
+            None
+        };
         Self {
-            cea_708_counter,
-            subs_delay,
+            time_transform: TimeTransform {
+                offset: output.time_transform.offset + encoder.subs_delay,
+                ..output.time_transform
+            },
             crlf: "\r\n".to_owned(),
             write_format,
-            writer_ctx,
-            no_font_color: is_true(no_font_color),
-            transcript_settings,
-            no_bom,
+            writer_ctx: &mut encoder.dtvcc_writers[(service_number - 1) as usize],
+            no_font_color: is_true(encoder.no_font_color),
+            transcript_settings: unsafe { &*encoder.transcript_settings },
+            no_bom: encoder.no_bom,
+            charset_failures: 0,
+            service_number,
+            program_number: encoder.program_number,
+            base_filename,
+            output,
+        }
+    }
+    /// Returns the filename of the output, `None` for stdout
+    fn filename(&self) -> Result<Option<String>, String> {
+        if let Some(spec) = &self.output.spec {
+            let base = self
+                .base_filename
+                .as_ref()
+                .ok_or_else(|| "No base filename for the output template".to_owned())?;
+            let language = self.output.language.as_deref().unwrap_or("und");
+            return Ok(Some(spec.filename(
+                base,
+                self.program_number,
+                self.service_number,
+                language,
+            )));
+        }
+        if self.writer_ctx.filename.is_null() {
+            if self.writer_ctx.fd < 0 {
+                return Err("Filename missing".to_owned());
+            }
+            return Ok(None);
         }
+        let filename = unsafe {
+            CStr::from_ptr(self.writer_ctx.filename)
+                .to_str()
+                .map_err(|err| err.to_string())
+        }?;
+        Ok(Some(filename.to_owned()))
     }
-    /// Write subtitles to the file
+    /// Open the output of the service, if it is not open yet
     ///
-    /// File must already exist
-    /// On Unix Platforms, uses the raw fd from context to access the file
-    /// On Windows Platforms, uses the raw handle from context to access the file
-    pub fn write_to_file(&mut self, buf: &[u8]) -> Result<(), String> {
-        #[cfg(unix)]
-        {
-            let mut file = unsafe { File::from_raw_fd(self.writer_ctx.fd) };
-            file.write_all(buf).map_err(|err| err.to_string())?;
-            self.writer_ctx.fd = file.into_raw_fd();
+    /// Captions go to stdout if the writer context has no filename but a file descriptor,
+    /// otherwise the file is created. A BOM is written to new files unless disabled.
+    pub fn open(&mut self) -> Result<(), String> {
+        if self.output.writer.is_some() {
+            return Ok(());
         }
-        #[cfg(windows)]
+        let filename = self.filename()?;
+        let writer: Box<dyn CaptionWriter> = match &filename {
+            None => Box::new(StdoutWriter::new()),
+            Some(filename) => {
+                let footer: &[u8] = if self.write_format == OutputFormat::Sami {
+                    SAMI_FOOTER
+                } else {
+                    b""
+                };
+                if self.output.rotate_size > 0 {
+                    Box::new(
+                        RotatingFileWriter::create(filename, self.output.rotate_size, footer)
+                            .map_err(|err| format!("Unable to create {}: {}", filename, err))?,
+                    )
+                } else {
+                    Box::new(
+                        FileWriter::create(filename)
+                            .map_err(|err| format!("Unable to create {}: {}", filename, err))?,
+                    )
+                }
+            }
+        };
+        self.output.writer = Some(writer);
+
+        // SCC files are plain ASCII, JSON is always UTF-8 without BOM
+        if is_false(self.no_bom)
+            && filename.is_some()
+            && !matches!(self.write_format, OutputFormat::Scc | OutputFormat::Json)
         {
-            let mut file = unsafe { File::from_raw_handle(self.writer_ctx.fhandle) };
-            file.write_all(buf).map_err(|err| err.to_string())?;
-            self.writer_ctx.fhandle = file.into_raw_handle();
+            let BOM = [0xef, 0xbb, 0xbf];
+            self.write_header(&BOM)?;
         }
         Ok(())
     }
-    /// Finish writing up any remaining parts
+    /// Write subtitles to the output
+    ///
+    /// The output must already be open
+    pub fn write_to_file(&mut self, buf: &[u8]) -> Result<(), String> {
+        match &mut self.output.writer {
+            Some(writer) => writer.write(buf).map_err(|err| err.to_string()),
+            None => Err("Output is not open".to_owned()),
+        }
+    }
+    /// Write the header of the output file, which is repeated when a new file is started
+    pub fn write_header(&mut self, buf: &[u8]) -> Result<(), String> {
+        match &mut self.output.writer {
+            Some(writer) => writer.write_header(buf).map_err(|err| err.to_string()),
+            None => Err("Output is not open".to_owned()),
+        }
+    }
+    /// Mark the end of a caption
+    pub fn end_caption(&mut self) -> Result<(), String> {
+        match &mut self.output.writer {
+            Some(writer) => writer.end_caption().map_err(|err| err.to_string()),
+            None => Ok(()),
+        }
+    }
+    /// Finish writing up any remaining parts and flush the output
     pub fn write_done(&mut self) {
-        if self.write_format == ccx_output_format::CCX_OF_SAMI {
+        if self.output.writer.is_none() {
+            debug!("dtvcc_write_done: output was never opened");
+            return;
+        }
+        if self.write_format == OutputFormat::Sami {
             if let Err(err) = self.write_sami_footer() {
                 warn!("{}", err);
             }
         } else {
             debug!("dtvcc_write_done: no handling required");
         }
+        if let Some(writer) = &mut self.output.writer {
+            if let Err(err) = writer.flush() {
+                warn!("dtvcc_write_done: Unable to flush output: {}", err);
+            }
+        }
     }
     /// Writes the footer according to the SAMI format
     pub fn write_sami_footer(&mut self) -> Result<(), String> {
-        self.write_to_file(b"</body></sami>")?;
+        self.write_to_file(SAMI_FOOTER)?;
         Ok(())
     }
 }
 
+const SAMI_FOOTER: &[u8] = b"</body></sami>";
+
+/// Returns the filename of the encoder output without extension
+///
+/// The 708 outputs of the encoder are named `<base>.p<program>.svc<service><ext>`. If the
+/// encoder has no 708 output file, the name of the first input file is used.
+fn base_filename(encoder: &encoder_ctx, service_number: i32) -> Option<String> {
+    let writer_ctx = &encoder.dtvcc_writers[(service_number - 1) as usize];
+    let filename = if writer_ctx.filename.is_null() {
+        if encoder.first_input_file.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(encoder.first_input_file) }.to_string_lossy()
+    } else {
+        unsafe { CStr::from_ptr(writer_ctx.filename) }.to_string_lossy()
+    };
+    let path = Path::new(filename.as_ref());
+    let stem = path.with_extension("").to_string_lossy().into_owned();
+    let suffix = format!(".p{}.svc{:02}", encoder.program_number, service_number);
+    Some(match stem.strip_suffix(&suffix) {
+        Some(base) => base.to_owned(),
+        None => stem,
+    })
+}
+
 /// Write the symbol to the provided buffer
 ///
 /// If symbol is 8-bit, then it's written to the buffer
@@ -0,0 +1,144 @@
+//! Statistics collected while decoding 708 data
+//!
+//! Packet level problems are counted by the [Dtvcc][super::Dtvcc] context, everything that
+//! happens inside a service block is counted by the respective service decoder.
+//! [DecoderReport] combines both and can be printed as JSON at the end of a run.
+//!
+//! Missing null headers are counted for the services of the packet as well. Sequence errors,
+//! lost, truncated and oversized packets are only counted for the packets: the data of these
+//! packets was never parsed, so the services it belonged to are unknown.
+
+use super::commands::{C0CodeSet, C0Command, C1CodeSet, C1Command};
+
+/// Counters for the DTVCC packets
+#[derive(Debug, Default, Clone)]
+pub struct PacketReport {
+    /// Number of complete packets processed
+    pub received: u64,
+    /// Number of packets with an unexpected sequence number
+    pub sequence_errors: u64,
+    /// Number of packets lost before the sequence errors, modulo 4
+    pub lost_packets: u64,
+    /// Number of incomplete packets dropped because a new packet started
+    pub truncated: u64,
+    /// Number of packets which had room for a null header but did not contain one
+    pub missing_null_headers: u64,
+    /// Number of times the legal packet size was exceeded
+    pub oversized: u64,
+}
+
+/// Counters for a single caption service
+#[derive(Debug, Default, Clone)]
+pub struct ServiceReport {
+    pub service_number: u8,
+    /// Number of service blocks received
+    pub service_blocks: u64,
+    /// C0 commands, indexed by their code
+    pub c0_commands: [u64; 32],
+    /// C1 commands, indexed by their code - 0x80
+    pub c1_commands: [u64; 32],
+    /// Reserved C0, C1, C2 and C3 codes
+    pub reserved_codes: u64,
+    /// Packets with blocks of the service which had room for a null header but did not contain one
+    pub missing_null_headers: u64,
+    /// Characters received while no window was defined
+    pub undefined_window_writes: u64,
+    /// Rows which could not be converted to the charset of the service
+    pub charset_failures: u64,
+    /// Screens written to the output
+    pub screens: u64,
+}
+
+impl ServiceReport {
+    /// Create an empty report for the service
+    pub fn new(service_number: u8) -> Self {
+        Self {
+            service_number,
+            ..Default::default()
+        }
+    }
+    /// Count a C0 command, reserved codes are only counted as such
+    pub fn count_c0(&mut self, code: u8) {
+        match C0CodeSet::new(code) {
+            C0CodeSet::RESERVED => self.reserved_codes += 1,
+            _ => self.c0_commands[(code & 0x1F) as usize] += 1,
+        }
+    }
+    /// Count a C1 command, reserved codes are only counted as such
+    pub fn count_c1(&mut self, code: u8) {
+        match C1Command::new(code).command {
+            C1CodeSet::RESERVED => self.reserved_codes += 1,
+            _ => self.c1_commands[(code & 0x1F) as usize] += 1,
+        }
+    }
+    /// Returns the report as a JSON object
+    pub fn to_json(&self) -> String {
+        let c0 = self
+            .c0_commands
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(code, count)| {
+                let C0Command { command, .. } = C0Command::new(code as u8);
+                format!("\"{:?}\":{}", command, count)
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let c1 = self
+            .c1_commands
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(code, count)| {
+                let command = C1Command::new(code as u8 + 0x80);
+                format!("\"{}\":{}", command.name, count)
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"service\":{},\"service_blocks\":{},\"c0_commands\":{{{}}},\"c1_commands\":{{{}}},\
+             \"reserved_codes\":{},\"missing_null_headers\":{},\"undefined_window_writes\":{},\
+             \"charset_failures\":{},\"screens\":{}}}",
+            self.service_number,
+            self.service_blocks,
+            c0,
+            c1,
+            self.reserved_codes,
+            self.missing_null_headers,
+            self.undefined_window_writes,
+            self.charset_failures,
+            self.screens
+        )
+    }
+}
+
+/// Statistics of a 708 decoder instance
+#[derive(Debug, Default, Clone)]
+pub struct DecoderReport {
+    pub packets: PacketReport,
+    /// Reports of all services which have a decoder
+    pub services: Vec<ServiceReport>,
+}
+
+impl DecoderReport {
+    /// Returns the report as a JSON object
+    pub fn to_json(&self) -> String {
+        let services = self
+            .services
+            .iter()
+            .map(|service| service.to_json())
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"packets\":{{\"received\":{},\"sequence_errors\":{},\"lost_packets\":{},\"truncated\":{},\
+             \"missing_null_headers\":{},\"oversized\":{}}},\"services\":[{}]}}",
+            self.packets.received,
+            self.packets.sequence_errors,
+            self.packets.lost_packets,
+            self.packets.truncated,
+            self.packets.missing_null_headers,
+            self.packets.oversized,
+            services
+        )
+    }
+}
@@ -0,0 +1,504 @@
+//! Down-conversion of 708 screens to CEA-608 for SCC output
+//!
+//! A [TvScreen][super::tv_screen::TvScreen] is re-encoded as CEA-608 channel 1 data, either as a
+//! pop-on caption (RCL, text, EOC) or as roll-up lines (RUx, CR, text). 608 can show at most
+//! 15 rows of 32 columns, so longer rows are reflowed and every character which is not in the 608
+//! character sets is replaced by a similar basic character.
+//!
+//! Reference: CEA-608-E, 47 CFR 15.119
+
+use super::timing::{frame_count, frames_to_timecode, get_time_str};
+
+use log::debug;
+
+/// Number of rows of a 608 screen
+pub const SCC_ROWS: usize = 15;
+/// Number of columns of a 608 screen
+pub const SCC_COLUMNS: usize = 32;
+/// Header of every SCC file
+pub const SCC_HEADER: &str = "Scenarist_SCC V1.0";
+
+/// Resume Caption Loading
+const RCL: [u8; 2] = [0x14, 0x20];
+/// Carriage Return
+const CR: [u8; 2] = [0x14, 0x2D];
+/// Erase Displayed Memory
+const EDM: [u8; 2] = [0x14, 0x2C];
+/// Erase Non-displayed Memory
+const ENM: [u8; 2] = [0x14, 0x2E];
+/// End Of Caption (swap memories)
+const EOC: [u8; 2] = [0x14, 0x2F];
+
+/// Colors which can be selected with a mid-row code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color608 {
+    White = 0,
+    Green = 1,
+    Blue = 2,
+    Cyan = 3,
+    Red = 4,
+    Yellow = 5,
+    Magenta = 6,
+}
+
+impl Color608 {
+    /// Returns the closest 608 color of a 708 color with 2 bits per RGB component
+    ///
+    /// Black has no mid-row code, so it is shown as white
+    pub fn from_708(color: u8) -> Self {
+        let red = (color >> 4) & 0x3 >= 2;
+        let green = (color >> 2) & 0x3 >= 2;
+        let blue = color & 0x3 >= 2;
+        match (red, green, blue) {
+            (false, false, true) => Color608::Blue,
+            (false, true, false) => Color608::Green,
+            (false, true, true) => Color608::Cyan,
+            (true, false, false) => Color608::Red,
+            (true, false, true) => Color608::Magenta,
+            (true, true, false) => Color608::Yellow,
+            _ => Color608::White,
+        }
+    }
+}
+
+/// Style of a single character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style608 {
+    pub color: Color608,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Default for Style608 {
+    fn default() -> Self {
+        Self {
+            color: Color608::White,
+            italic: false,
+            underline: false,
+        }
+    }
+}
+
+/// A character together with its style
+pub type StyledChar = (char, Style608);
+
+/// How a character is encoded in 608
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Char608 {
+    /// Standard character, a single byte
+    Basic(u8),
+    /// Special North American character, 0x11 + code
+    Special(u8),
+    /// Extended character, first byte + code, preceded by a basic character for
+    /// decoders which don't support the extended set
+    Extended(u8, u8, u8),
+}
+
+/// Characters of the basic set which differ from ASCII
+const BASIC_CHARS: [(char, u8); 10] = [
+    ('á', 0x2A),
+    ('é', 0x5C),
+    ('í', 0x5E),
+    ('ó', 0x5F),
+    ('ú', 0x60),
+    ('ç', 0x7B),
+    ('÷', 0x7C),
+    ('Ñ', 0x7D),
+    ('ñ', 0x7E),
+    ('■', 0x7F),
+];
+
+/// Special North American characters, 0x11 0x30 to 0x3F, without the transparent space
+const SPECIAL_CHARS: [(char, u8); 15] = [
+    ('®', 0x30),
+    ('°', 0x31),
+    ('½', 0x32),
+    ('¿', 0x33),
+    ('™', 0x34),
+    ('¢', 0x35),
+    ('£', 0x36),
+    ('♪', 0x37),
+    ('à', 0x38),
+    ('è', 0x3A),
+    ('â', 0x3B),
+    ('ê', 0x3C),
+    ('î', 0x3D),
+    ('ô', 0x3E),
+    ('û', 0x3F),
+];
+
+/// Extended Western European characters, 0x12 0x20 to 0x3F
+const EXTENDED_CHARS_1: [char; 32] = [
+    'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '’', '—', '©', '℠', '•', '“', '”', 'À', 'Â', 'Ç',
+    'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+];
+
+/// Extended Western European characters, 0x13 0x20 to 0x3F
+const EXTENDED_CHARS_2: [char; 32] = [
+    'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~', 'Ä', 'ä', 'Ö',
+    'ö', 'ß', '¥', '¤', '¦', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+];
+
+/// Returns the basic character a character is replaced with if it can't be shown
+fn fallback(c: char) -> u8 {
+    let replacement = match c {
+        'À'..='Å' => 'A',
+        'à'..='å' => 'a',
+        'Ç' => 'C',
+        'ç' => 'c',
+        'È'..='Ë' => 'E',
+        'è'..='ë' => 'e',
+        'Ì'..='Ï' => 'I',
+        'ì'..='ï' => 'i',
+        'Ñ' => 'N',
+        'ñ' => 'n',
+        'Ò'..='Ö' | 'Ø' => 'O',
+        'ò'..='ö' | 'ø' => 'o',
+        'Ù'..='Ü' => 'U',
+        'ù'..='ü' => 'u',
+        'Ý' | 'Ÿ' => 'Y',
+        'ý' | 'ÿ' => 'y',
+        'Š' => 'S',
+        'š' => 's',
+        'ß' => 's',
+        'Œ' => 'O',
+        'œ' => 'o',
+        '‘' | '’' | '^' | '`' | '´' => '\'',
+        '“' | '”' | '«' | '»' => '"',
+        '—' | '–' | '─' | '_' | '~' => '-',
+        '{' | '[' => '(',
+        '}' | ']' => ')',
+        '\\' => '/',
+        '|' | '¦' | '│' => '!',
+        '¡' => '!',
+        '*' | '•' | '…' => '.',
+        '©' => 'c',
+        '℠' | '¤' => 's',
+        '¥' => 'Y',
+        '\u{A0}' => ' ',
+        ' '..='~' => c,
+        _ => '?',
+    };
+    replacement as u8
+}
+
+/// Returns the 608 encoding of a character
+fn encode_char(c: char) -> Char608 {
+    if let Some(&(_, code)) = BASIC_CHARS.iter().find(|(ch, _)| *ch == c) {
+        return Char608::Basic(code);
+    }
+    if let Some(&(_, code)) = SPECIAL_CHARS.iter().find(|(ch, _)| *ch == c) {
+        return Char608::Special(code);
+    }
+    if let Some(index) = EXTENDED_CHARS_1.iter().position(|&ch| ch == c) {
+        return Char608::Extended(0x12, 0x20 + index as u8, fallback(c));
+    }
+    if let Some(index) = EXTENDED_CHARS_2.iter().position(|&ch| ch == c) {
+        return Char608::Extended(0x13, 0x20 + index as u8, fallback(c));
+    }
+    match c {
+        ' '..='~' => Char608::Basic(c as u8),
+        _ => Char608::Basic(fallback(c)),
+    }
+}
+
+/// Adds the odd parity bit to a byte
+fn odd_parity(byte: u8) -> u8 {
+    if byte.count_ones() % 2 == 1 {
+        byte
+    } else {
+        byte | 0x80
+    }
+}
+
+/// Returns the preamble address code for the row and column, the column is rounded down
+/// to a multiple of 4
+fn preamble_code(row: usize, column: usize) -> [u8; 2] {
+    // First byte and whether the row uses the upper half of the second byte range
+    const ROWS: [(u8, bool); SCC_ROWS] = [
+        (0x11, false),
+        (0x11, true),
+        (0x12, false),
+        (0x12, true),
+        (0x15, false),
+        (0x15, true),
+        (0x16, false),
+        (0x16, true),
+        (0x17, false),
+        (0x17, true),
+        (0x10, false),
+        (0x13, false),
+        (0x13, true),
+        (0x14, false),
+        (0x14, true),
+    ];
+    let (first, upper) = ROWS[row.min(SCC_ROWS - 1)];
+    let base = if upper { 0x70 } else { 0x50 };
+    let indent = (column.min(SCC_COLUMNS - 1) / 4) as u8;
+    [first, base | (indent << 1)]
+}
+
+/// Returns the tab offset code to move the cursor to the exact column after a preamble code
+fn tab_offset_code(column: usize) -> Option<[u8; 2]> {
+    match column % 4 {
+        0 => None,
+        offset => Some([0x17, 0x20 + offset as u8]),
+    }
+}
+
+/// Returns the mid-row code for the style
+fn mid_row_code(style: Style608) -> [u8; 2] {
+    let code = if style.italic {
+        0x2E
+    } else {
+        0x20 + 2 * style.color as u8
+    };
+    [0x11, code | style.underline as u8]
+}
+
+/// Roll-up code for 2 to 4 rows
+fn roll_up_code(rows: u8) -> [u8; 2] {
+    [0x14, 0x25 + rows - 2]
+}
+
+/// A line of text at its 608 position
+#[derive(Debug, Clone)]
+pub struct Line608 {
+    pub row: usize,
+    pub column: usize,
+    pub chars: Vec<StyledChar>,
+}
+
+/// Content of a column of a 608 row
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    /// Mid-row code, shown as a space
+    MidRow(Style608),
+    Char(char),
+}
+
+/// Returns the columns of a line, with a mid-row code for every style change
+///
+/// A style change replaces the space before a word if possible, otherwise the mid-row code
+/// takes an extra column. Every line starts in the default style, as the preamble code resets it.
+fn cells(chars: &[StyledChar]) -> Vec<Cell> {
+    let mut cells = Vec::with_capacity(chars.len());
+    let mut style = Style608::default();
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, char_style) = chars[i];
+        if c == ' ' {
+            if let Some(&(next, next_style)) = chars.get(i + 1) {
+                if next != ' ' && next_style != style {
+                    cells.push(Cell::MidRow(next_style));
+                    style = next_style;
+                    i += 1;
+                    continue;
+                }
+            }
+        }
+        if char_style != style {
+            cells.push(Cell::MidRow(char_style));
+            style = char_style;
+        }
+        cells.push(Cell::Char(c));
+        i += 1;
+    }
+    cells
+}
+
+/// Returns the number of columns a line takes, including the mid-row codes
+pub fn columns(chars: &[StyledChar]) -> usize {
+    cells(chars).len()
+}
+
+/// Split a row into lines of at most [SCC_COLUMNS] columns, breaking at spaces if possible
+pub fn reflow(chars: &[StyledChar]) -> Vec<Vec<StyledChar>> {
+    let mut lines = Vec::new();
+    let mut rest = chars;
+    while columns(rest) > SCC_COLUMNS {
+        // Longest start of the rest which fits, a character takes two columns at most
+        let fit = (1..rest.len())
+            .take_while(|&len| columns(&rest[..len]) <= SCC_COLUMNS)
+            .last()
+            .unwrap_or(1);
+        let split = rest[..=fit]
+            .iter()
+            .rposition(|(c, _)| *c == ' ')
+            .filter(|&split| split > 0)
+            .unwrap_or(fit);
+        lines.push(rest[..split].to_vec());
+        rest = &rest[split..];
+        while let Some(((' ', _), tail)) = rest.split_first() {
+            rest = tail;
+        }
+    }
+    if !rest.is_empty() {
+        lines.push(rest.to_vec());
+    }
+    lines
+}
+
+/// 608 data of a single caption, as a list of byte pairs
+#[derive(Debug, Default)]
+struct Caption608 {
+    bytes: Vec<u8>,
+}
+
+impl Caption608 {
+    /// Add a control code, which always starts a new byte pair
+    ///
+    /// Control codes, including special and extended characters, are sent twice. Decoders ignore
+    /// the repeated code, it only takes effect if the first one was lost.
+    fn control(&mut self, code: [u8; 2]) {
+        self.pad();
+        self.bytes.extend_from_slice(&code);
+        self.bytes.extend_from_slice(&code);
+    }
+    /// Fill the current byte pair
+    fn pad(&mut self) {
+        if self.bytes.len() % 2 == 1 {
+            self.bytes.push(0);
+        }
+    }
+    fn char(&mut self, c: char) {
+        match encode_char(c) {
+            Char608::Basic(code) => self.bytes.push(code),
+            Char608::Special(code) => self.control([0x11, code]),
+            Char608::Extended(first, code, fallback) => {
+                self.bytes.push(fallback);
+                self.control([first, code]);
+            }
+        }
+    }
+    /// Add a line with its position and style changes, see [cells]
+    fn line(&mut self, line: &Line608) {
+        let cells = cells(&line.chars);
+        let column = match cells.first() {
+            // The mid-row code is shown as a space in front of the text
+            Some(Cell::MidRow(_)) if line.column > 0 => line.column - 1,
+            _ => line.column,
+        };
+        self.control(preamble_code(line.row, column));
+        if let Some(code) = tab_offset_code(column) {
+            self.control(code);
+        }
+        for cell in cells {
+            match cell {
+                Cell::MidRow(style) => self.control(mid_row_code(style)),
+                Cell::Char(c) => self.char(c),
+            }
+        }
+        self.pad();
+    }
+    /// Returns the byte pairs as SCC words with odd parity
+    fn to_words(&self) -> String {
+        self.bytes
+            .chunks(2)
+            .map(|pair| {
+                let second = pair.get(1).copied().unwrap_or(0);
+                format!("{:02x}{:02x}", odd_parity(pair[0]), odd_parity(second))
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Returns the SMPTE drop-frame timecode of a time in ms at 29.97 fps, as used by SCC
+fn timecode(time: i64) -> String {
+    frames_to_timecode(frame_count(time, 30000, 1001), 30, true)
+}
+
+/// Returns the SCC lines of a pop-on caption
+///
+/// The caption is loaded into non-displayed memory and shown at `start`, the displayed memory
+/// is erased at `end`.
+pub fn pop_on(lines: &[Line608], start: i64, end: i64, crlf: &str) -> String {
+    debug!(
+        "dtvcc_write_scc: pop-on {} --> {}",
+        get_time_str(start),
+        get_time_str(end)
+    );
+    let mut caption = Caption608::default();
+    caption.control(RCL);
+    caption.control(ENM);
+    for line in lines {
+        caption.line(line);
+    }
+    caption.control(EOC);
+
+    let mut clear = Caption608::default();
+    clear.control(EDM);
+
+    format!(
+        "{}{}{}\t{}{}{}{}\t{}",
+        crlf,
+        crlf,
+        timecode(start),
+        caption.to_words(),
+        crlf,
+        crlf,
+        timecode(end),
+        clear.to_words()
+    )
+}
+
+/// Returns the SCC line of roll-up captions, `None` if the screen has no new line
+///
+/// A 708 roll-up screen holds the whole window, while 608 decoders keep the lines already
+/// shown. `sent` holds the lines sent for the previous screen, and only the lines following
+/// them are added at the bottom of the roll-up window, so the window shows the last `rows`
+/// (2 to 4) lines of the screen.
+pub fn roll_up(
+    lines: &[Line608],
+    rows: u8,
+    start: i64,
+    crlf: &str,
+    sent: &mut Vec<Vec<StyledChar>>,
+) -> Option<String> {
+    // Longest end of the sent lines the screen starts with, the lines above rolled out
+    let kept = (0..=lines.len().min(sent.len()))
+        .rev()
+        .find(|&count| {
+            sent[sent.len() - count..]
+                .iter()
+                .zip(&lines[..count])
+                .all(|(sent, line)| *sent == line.chars)
+        })
+        .unwrap_or(0);
+    *sent = lines.iter().map(|line| line.chars.clone()).collect();
+    let new_lines = &lines[kept..];
+    if new_lines.is_empty() {
+        return None;
+    }
+
+    debug!(
+        "dtvcc_write_scc: roll-up with {} rows at {}, {} new lines",
+        rows,
+        get_time_str(start),
+        new_lines.len()
+    );
+    let rows = rows.clamp(2, 4);
+    let mut caption = Caption608::default();
+    caption.control(roll_up_code(rows));
+    let base_row = lines
+        .last()
+        .map(|line| line.row)
+        .unwrap_or(SCC_ROWS - 1)
+        .max(rows as usize - 1);
+    for line in new_lines {
+        caption.control(CR);
+        caption.line(&Line608 {
+            row: base_row,
+            column: line.column,
+            chars: line.chars.clone(),
+        });
+    }
+    Some(format!(
+        "{}{}{}\t{}",
+        crlf,
+        crlf,
+        timecode(start),
+        caption.to_words()
+    ))
+}
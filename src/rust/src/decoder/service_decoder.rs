@@ -2,20 +2,19 @@
 //!
 //! Caption Service decoder processes service blocks and handles the different [commands][super::commands] received
 
-use std::{
-    alloc::{alloc, dealloc, Layout},
-    os::raw::c_uchar,
-};
+use std::os::raw::c_uchar;
 
 use super::commands::{self, C0CodeSet, C0Command, C1CodeSet, C1Command};
-use super::window::{PenPreset, WindowPreset};
-use super::{
-    CCX_DTVCC_MAX_COLUMNS, CCX_DTVCC_MAX_ROWS, CCX_DTVCC_SCREENGRID_COLUMNS,
-    CCX_DTVCC_SCREENGRID_ROWS,
-};
+use super::compliance::{Finding, Severity};
+use super::cues::CueQueue;
+use super::report::ServiceReport;
+use super::timing::Timing;
+use super::tv_screen::TvScreen;
+use super::window::{PenPreset, Window, WindowPreset};
+use super::{Dtvcc, CCX_DTVCC_MAX_ROWS, CCX_DTVCC_SCREENGRID_COLUMNS, CCX_DTVCC_SCREENGRID_ROWS};
 use crate::{
     bindings::*,
-    decoder::output::Writer,
+    decoder::output::{OutputConfig, ServiceOutput, Writer},
     utils::{is_false, is_true},
 };
 
@@ -24,16 +23,89 @@ use log::{debug, error, warn};
 const CCX_DTVCC_MUSICAL_NOTE_CHAR: u16 = 9836;
 const CCX_DTVCC_MAX_WINDOWS: u8 = 8;
 const DTVCC_COMMANDS_C0_CODES_DTVCC_C0_EXT1: u8 = 16;
+/// Maximum number of columns of a window on 16:9 screens
+const CCX_DTVCC_MAX_WINDOW_COLUMNS: u8 = 42;
+/// Maximum number of columns of a window on 4:3 screens
+const CCX_DTVCC_MAX_WINDOW_COLUMNS_4_3: u8 = 32;
+
+/// Caption service decoder
+///
+/// Rust side counterpart of `dtvcc_service_decoder`, which owns its windows and TV screen
+pub struct ServiceDecoder {
+    pub windows: Vec<Window>,
+    pub current_window: i32,
+    pub tv: TvScreen,
+    pub cc_count: i32,
+    pub report: ServiceReport,
+    /// Violations found in compliance mode, `None` if the mode is disabled
+    pub findings: Option<Vec<Finding>>,
+    /// FTS of the service block being processed
+    block_fts: LLONG,
+    /// FTS of the last Delay command which was not cancelled yet
+    delay_fts: Option<LLONG>,
+    /// Outputs the screens are written to
+    pub outputs: Vec<ServiceOutput>,
+    /// Screens waiting for their final times, if any cue rules are set
+    pub cues: CueQueue,
+}
+
+impl ServiceDecoder {
+    /// Create a new decoder for the service, with all windows undefined
+    ///
+    /// In compliance mode, violations of the standard are collected and no captions are written
+    pub fn new(service_number: i32, compliance: bool, output_config: &OutputConfig) -> Self {
+        Self {
+            windows: (0..CCX_DTVCC_MAX_WINDOWS).map(|_| Window::new()).collect(),
+            current_window: -1,
+            tv: TvScreen::new(service_number),
+            cc_count: 0,
+            report: ServiceReport::new(service_number as u8),
+            findings: if compliance { Some(Vec::new()) } else { None },
+            block_fts: 0,
+            delay_fts: None,
+            outputs: output_config.service_outputs(service_number),
+            cues: CueQueue::new(output_config.cue_rules),
+        }
+    }
+
+    /// Add a finding if compliance mode is enabled
+    pub fn add_finding(&mut self, severity: Severity, message: String) {
+        if let Some(findings) = &mut self.findings {
+            findings.push(Finding {
+                fts: self.block_fts,
+                severity,
+                service: self.report.service_number,
+                message,
+            });
+        }
+    }
+
+    /// Add a finding for a command which requires a defined current window
+    fn add_undefined_window_finding(&mut self, command: &str) {
+        self.add_finding(
+            Severity::Error,
+            format!("{} used before a window was defined", command),
+        );
+    }
+
+    /// Add a finding for a command once the stream is complete, e.g. a pending Delay
+    pub fn finish_compliance(&mut self) {
+        if let Some(fts) = self.delay_fts.take() {
+            let block_fts = std::mem::replace(&mut self.block_fts, fts);
+            self.add_finding(Severity::Warning, "Delay was never cancelled".to_owned());
+            self.block_fts = block_fts;
+        }
+    }
 
-impl dtvcc_service_decoder {
     /// Process service block and call handlers for the respective codesets
     pub fn process_service_block(
         &mut self,
         block: &[u8],
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
         no_rollup: bool,
     ) {
+        self.block_fts = timing.get_fts();
         let mut i = 0;
         while i < block.len() {
             let consumed = if block[i] != DTVCC_COMMANDS_C0_CODES_DTVCC_C0_EXT1 {
@@ -64,12 +136,13 @@ impl dtvcc_service_decoder {
         &mut self,
         block: &[u8],
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
         no_rollup: bool,
     ) -> i32 {
         let code = block[0];
         let C0Command { command, length } = C0Command::new(code);
         debug!("C0: [{:?}] ({})", command, block.len());
+        self.report.count_c0(code);
         match command {
             // NUL command does nothing
             C0CodeSet::NUL => {}
@@ -81,7 +154,9 @@ impl dtvcc_service_decoder {
             // EXT1 is handled elsewhere as an extended command
             C0CodeSet::EXT1 => {}
             C0CodeSet::P16 => self.process_p16(&block[1..]),
-            C0CodeSet::RESERVED => {}
+            C0CodeSet::RESERVED => {
+                self.add_finding(Severity::Warning, format!("Reserved C0 code {:02X}", code))
+            }
         }
         if length as usize > block.len() {
             warn!(
@@ -100,14 +175,10 @@ impl dtvcc_service_decoder {
     ///
     /// Carriage Return (CR) moves the current entry point to the beginning of the next row. If the next row is
     /// below the visible window, the window “rolls up"
-    pub fn process_cr(
-        &mut self,
-        encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
-        no_rollup: bool,
-    ) {
+    pub fn process_cr(&mut self, encoder: &mut encoder_ctx, timing: &mut Timing, no_rollup: bool) {
         if self.current_window == -1 {
             warn!("dtvcc_process_cr: Window has to be defined first");
+            self.add_undefined_window_finding("CR");
             return;
         }
         let window = &mut self.windows[self.current_window as usize];
@@ -160,7 +231,7 @@ impl dtvcc_service_decoder {
 
             if rollup_required {
                 debug!("dtvcc_process_cr: rolling up");
-                self.copy_to_screen(&self.windows[self.current_window as usize]);
+                self.copy_to_screen(self.current_window as usize);
                 self.screen_print(encoder, timing);
                 if no_rollup {
                     self.windows[self.current_window as usize].clear_row(pen_row as usize);
@@ -181,6 +252,7 @@ impl dtvcc_service_decoder {
     pub fn process_hcr(&mut self) {
         if self.current_window == -1 {
             warn!("dtvcc_process_hcr: Window has to be defined first");
+            self.add_undefined_window_finding("HCR");
             return;
         }
         let window = &mut self.windows[self.current_window as usize];
@@ -196,6 +268,7 @@ impl dtvcc_service_decoder {
     pub fn process_ff(&mut self) {
         if self.current_window == -1 {
             warn!("dtvcc_process_ff: Window has to be defined first");
+            self.add_undefined_window_finding("FF");
             return;
         }
         let window = &mut self.windows[self.current_window as usize];
@@ -210,6 +283,7 @@ impl dtvcc_service_decoder {
     pub fn process_bs(&mut self) {
         if self.current_window == -1 {
             warn!("dtvcc_process_bs: Window has to be defined first");
+            self.add_undefined_window_finding("BS");
             return;
         }
         //it looks strange, but in some videos (rarely) we have a backspace command
@@ -252,6 +326,7 @@ impl dtvcc_service_decoder {
     pub fn process_p16(&mut self, block: &[c_uchar]) {
         if self.current_window == -1 {
             warn!("dtvcc_process_p16: Window has to be defined first");
+            self.add_undefined_window_finding("P16");
             return;
         }
         let sym = dtvcc_symbol::new_16(block[0], block[1]);
@@ -269,7 +344,7 @@ impl dtvcc_service_decoder {
         &mut self,
         block: &[c_uchar],
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
     ) -> i32 {
         let code = block[0];
         let C1Command {
@@ -284,6 +359,7 @@ impl dtvcc_service_decoder {
         }
 
         debug!("C1: [{:?}] [{}] ({})", command, name, length);
+        self.report.count_c1(code);
         match command {
             C1CodeSet::CW0
             | C1CodeSet::CW1
@@ -321,6 +397,7 @@ impl dtvcc_service_decoder {
             ),
             C1CodeSet::RESERVED => {
                 warn!("Warning, Found Reserved codes, ignored");
+                self.add_finding(Severity::Warning, format!("Reserved C1 code {:02X}", code));
             }
         };
         length as i32
@@ -334,7 +411,7 @@ impl dtvcc_service_decoder {
         &mut self,
         mut windows_bitmap: u8,
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
     ) {
         debug!("dtvcc_handle_CLW_ClearWindows: windows:");
         let mut screen_content_changed = false;
@@ -351,7 +428,7 @@ impl dtvcc_service_decoder {
                     if window_had_content {
                         screen_content_changed = true;
                         window.update_time_hide(timing);
-                        self.copy_to_screen(&self.windows[i as usize]);
+                        self.copy_to_screen(i as usize);
                     }
                     self.windows[i as usize].clear_text();
                 }
@@ -371,7 +448,7 @@ impl dtvcc_service_decoder {
         &mut self,
         mut windows_bitmap: u8,
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
     ) {
         debug!("dtvcc_handle_HDW_HideWindows: windows:");
         if windows_bitmap == 0 {
@@ -387,7 +464,7 @@ impl dtvcc_service_decoder {
                         window.visible = 0;
                         window.update_time_hide(timing);
                         if is_false(window.is_empty) {
-                            self.copy_to_screen(&self.windows[i as usize]);
+                            self.copy_to_screen(i as usize);
                         }
                     }
                 }
@@ -407,7 +484,7 @@ impl dtvcc_service_decoder {
         &mut self,
         mut windows_bitmap: u8,
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
     ) {
         debug!("dtvcc_handle_TGW_ToggleWindows: windows:");
         if windows_bitmap == 0 {
@@ -427,7 +504,7 @@ impl dtvcc_service_decoder {
                         window.update_time_hide(timing);
                         if is_false(window.is_empty) {
                             screen_content_changed = true;
-                            self.copy_to_screen(&self.windows[i as usize]);
+                            self.copy_to_screen(i as usize);
                         }
                     }
                 }
@@ -447,7 +524,7 @@ impl dtvcc_service_decoder {
         &mut self,
         mut windows_bitmap: u8,
         encoder: &mut encoder_ctx,
-        timing: &mut ccx_common_timing_ctx,
+        timing: &mut Timing,
     ) {
         debug!("dtvcc_handle_DLW_DeleteWindows: windows:");
         let mut screen_content_changed = false;
@@ -464,7 +541,7 @@ impl dtvcc_service_decoder {
                     if window_had_content {
                         screen_content_changed = true;
                         window.update_time_hide(timing);
-                        self.copy_to_screen(&self.windows[i as usize]);
+                        self.copy_to_screen(i as usize);
                         if self.current_window == i as i32 {
                             self.screen_print(encoder, timing);
                         }
@@ -489,11 +566,7 @@ impl dtvcc_service_decoder {
     /// DSW Display Windows
     ///
     /// Display all windows specified by bitmap.
-    pub fn handle_display_windows(
-        &mut self,
-        mut windows_bitmap: u8,
-        timing: &mut ccx_common_timing_ctx,
-    ) {
+    pub fn handle_display_windows(&mut self, mut windows_bitmap: u8, timing: &mut Timing) {
         debug!("dtvcc_handle_DSW_DisplayWindows: windows:");
         if windows_bitmap == 0 {
             debug!("none");
@@ -504,6 +577,10 @@ impl dtvcc_service_decoder {
                     debug!("[W{}]", i);
                     if is_false(window.is_defined) {
                         error!("Window {} was not defined", i);
+                        self.add_finding(
+                            Severity::Error,
+                            format!("DisplayWindows for undefined window {}", i),
+                        );
                         continue;
                     }
                     if is_false(window.visible) {
@@ -519,16 +596,14 @@ impl dtvcc_service_decoder {
     /// DFx Define Windows
     ///
     /// Define a new window with the provided attributes. New window is now the current window
-    pub fn handle_define_windows(
-        &mut self,
-        window_id: u8,
-        block: &[c_uchar],
-        timing: &mut ccx_common_timing_ctx,
-    ) {
+    pub fn handle_define_windows(&mut self, window_id: u8, block: &[c_uchar], timing: &mut Timing) {
         debug!(
             "dtvcc_handle_DFx_DefineWindow: W[{}], attributes:",
             window_id
         );
+        if self.findings.is_some() {
+            self.check_window_definition(window_id, block);
+        }
         let window = &mut self.windows[window_id as usize];
         let block = &block[..=5];
         let is_command_repeated = window
@@ -636,22 +711,6 @@ impl dtvcc_service_decoder {
             // are set to the fill color and the pen location is set to (0,0)
             window.pen_column = 0;
             window.pen_row = 0;
-            if is_false(window.memory_reserved) {
-                for i in 0..CCX_DTVCC_MAX_ROWS as usize {
-                    let layout = Layout::array::<dtvcc_symbol>(CCX_DTVCC_MAX_COLUMNS as usize);
-                    if let Err(e) = layout {
-                        error!("dtvcc_handle_DFx_DefineWindow: Incorrect Layout, {}", e);
-                    } else {
-                        let ptr = unsafe { alloc(layout.unwrap()) };
-                        if ptr.is_null() {
-                            error!("dtvcc_handle_DFx_DefineWindow: Not enough memory",);
-                        }
-                        // Exit here?
-                        window.rows[i] = ptr as *mut dtvcc_symbol;
-                    }
-                }
-                window.memory_reserved = 1;
-            }
             window.is_defined = 1;
             window.clear_text();
         } else if do_clear_window {
@@ -667,26 +726,77 @@ impl dtvcc_service_decoder {
         if is_true(window.visible) {
             window.update_time_show(timing);
         }
-        if is_false(window.memory_reserved) {
-            for i in 0..CCX_DTVCC_MAX_ROWS as usize {
-                let layout = Layout::array::<dtvcc_symbol>(CCX_DTVCC_MAX_COLUMNS as usize);
-                if let Err(e) = layout {
-                    error!("dtvcc_handle_DFx_DefineWindow: Incorrect Layout, {}", e);
-                } else {
-                    unsafe { dealloc(window.rows[i] as *mut u8, layout.unwrap()) };
-                }
-            }
-        }
         // ...also makes the defined windows the current window
         self.handle_set_current_window(window_id);
     }
 
+    /// Check the attributes of a DefineWindow command against the limits of CTA-708-E 8.4.7
+    fn check_window_definition(&mut self, window_id: u8, block: &[c_uchar]) {
+        let anchor_vertical = block[1] & 0x7f;
+        let relative_pos = block[1] >> 7;
+        let anchor_horizontal = block[2];
+        let row_count = (block[3] & 0xf) + 1;
+        let anchor_point = block[3] >> 4;
+        let col_count = (block[4] & 0x3f) + 1;
+
+        if row_count > CCX_DTVCC_MAX_ROWS {
+            self.add_finding(
+                Severity::Error,
+                format!(
+                    "Window {} is defined with {} rows, the maximum is {}",
+                    window_id, row_count, CCX_DTVCC_MAX_ROWS
+                ),
+            );
+        }
+        if col_count > CCX_DTVCC_MAX_WINDOW_COLUMNS {
+            self.add_finding(
+                Severity::Error,
+                format!(
+                    "Window {} is defined with {} columns, the maximum is {}",
+                    window_id, col_count, CCX_DTVCC_MAX_WINDOW_COLUMNS
+                ),
+            );
+        } else if col_count > CCX_DTVCC_MAX_WINDOW_COLUMNS_4_3 {
+            self.add_finding(
+                Severity::Warning,
+                format!(
+                    "Window {} is defined with {} columns, the maximum for 4:3 screens is {}",
+                    window_id, col_count, CCX_DTVCC_MAX_WINDOW_COLUMNS_4_3
+                ),
+            );
+        }
+        if let Err(e) = dtvcc_pen_anchor_point::new(anchor_point as i32) {
+            self.add_finding(Severity::Error, format!("Window {}: {}", window_id, e));
+        }
+        let (max_vertical, max_horizontal) = if is_true(relative_pos) {
+            (99, 99)
+        } else {
+            (
+                CCX_DTVCC_SCREENGRID_ROWS - 1,
+                CCX_DTVCC_SCREENGRID_COLUMNS - 1,
+            )
+        };
+        if anchor_vertical > max_vertical || anchor_horizontal > max_horizontal {
+            self.add_finding(
+                Severity::Error,
+                format!(
+                    "Window {} has an anchor outside of the screen: vertical {}, horizontal {}, relative {}",
+                    window_id,
+                    anchor_vertical,
+                    anchor_horizontal,
+                    if is_true(relative_pos) { "yes" } else { "no" }
+                ),
+            );
+        }
+    }
+
     /// SPA Set Pen Attributes
     ///
     /// Change pen attributes
     pub fn handle_set_pen_attributes(&mut self, block: &[c_uchar]) {
         if self.current_window == -1 {
             warn!("dtvcc_handle_SPA_SetPenAttributes: Window has to be defined first");
+            self.add_undefined_window_finding("SetPenAttributes");
             return;
         }
 
@@ -729,6 +839,7 @@ impl dtvcc_service_decoder {
     pub fn handle_set_pen_color(&mut self, block: &[c_uchar]) {
         if self.current_window == -1 {
             warn!("dtvcc_handle_SPC_SetPenColor: Window has to be defined first");
+            self.add_undefined_window_finding("SetPenColor");
             return;
         }
 
@@ -768,6 +879,7 @@ impl dtvcc_service_decoder {
     pub fn handle_set_pen_location(&mut self, block: &[c_uchar]) {
         if self.current_window == -1 {
             warn!("dtvcc_handle_SPL_SetPenLocation: Window has to be defined first");
+            self.add_undefined_window_finding("SetPenLocation");
             return;
         }
 
@@ -787,6 +899,7 @@ impl dtvcc_service_decoder {
     pub fn handle_set_window_attributes(&mut self, block: &[c_uchar]) {
         if self.current_window == -1 {
             warn!("dtvcc_handle_SWA_SetWindowAttributes: Window has to be defined first");
+            self.add_undefined_window_finding("SetWindowAttributes");
             return;
         }
 
@@ -842,6 +955,10 @@ impl dtvcc_service_decoder {
                 "dtvcc_handle_CWx_SetCurrentWindow: window [{}] is not defined",
                 window_id
             );
+            self.add_finding(
+                Severity::Warning,
+                format!("SetCurrentWindow for undefined window {}", window_id),
+            );
         }
     }
 
@@ -851,11 +968,24 @@ impl dtvcc_service_decoder {
             "dtvcc_handle_DLY_Delay: dely for {} tenths of second",
             tenths_of_sec
         );
+        if self.delay_fts.is_some() {
+            self.add_finding(
+                Severity::Warning,
+                "Delay while the previous Delay was not cancelled".to_owned(),
+            );
+        }
+        self.delay_fts = Some(self.block_fts);
     }
 
     /// DLC Delay Cancel
     pub fn handle_delay_cancel(&mut self) {
         debug!("dtvcc_handle_DLC_DelayCancel");
+        if self.delay_fts.take().is_none() {
+            self.add_finding(
+                Severity::Warning,
+                "DelayCancel without a preceding Delay".to_owned(),
+            );
+        }
     }
 
     /// RST Reset
@@ -870,36 +1000,34 @@ impl dtvcc_service_decoder {
             window.commands.fill(0);
         }
         self.current_window = -1;
-        unsafe { (*self.tv).clear() };
+        self.delay_fts = None;
+        self.tv.clear();
     }
 
     /// Print the contents of tv screen to the output file
-    pub fn screen_print(&mut self, encoder: &mut encoder_ctx, timing: &mut ccx_common_timing_ctx) {
+    pub fn screen_print(&mut self, encoder: &mut encoder_ctx, timing: &mut Timing) {
         debug!("dtvcc_screen_print rust");
         self.cc_count += 1;
-        unsafe {
-            let tv = &mut (*self.tv);
-            tv.cc_count += 1;
-            let sn = tv.service_number;
-            let writer_ctx = &mut encoder.dtvcc_writers[(sn - 1) as usize];
-
-            tv.update_time_hide(timing.get_visible_end(3));
-            let mut writer = Writer::new(
-                &mut encoder.cea_708_counter,
-                encoder.subs_delay,
-                encoder.write_format,
-                writer_ctx,
-                encoder.no_font_color,
-                &*encoder.transcript_settings,
-                encoder.no_bom,
-            );
-            tv.writer_output(&mut writer).unwrap();
-            tv.clear();
+        let tv = &mut self.tv;
+        tv.cc_count += 1;
+        tv.update_time_hide(timing.get_visible_end());
+        if self.findings.is_none() {
+            if !tv.is_empty() {
+                encoder.cea_708_counter += 1;
+            }
+            if !self.cues.rules.is_active() {
+                write_screen(tv, &mut self.outputs, &mut self.report, encoder);
+            } else if let Some(screen) = self.cues.push(tv.clone()) {
+                write_screen(&screen, &mut self.outputs, &mut self.report, encoder);
+            }
         }
+        self.report.screens += 1;
+        tv.clear();
     }
 
-    /// Copy the contents of window to the TV screen
-    pub fn copy_to_screen(&self, window: &dtvcc_window) {
+    /// Copy the contents of the window with the given id to the TV screen
+    pub fn copy_to_screen(&mut self, window_id: usize) {
+        let window = &self.windows[window_id];
         if self.is_window_overlapping(window) {
             debug!("dtvcc_window_copy_to_screen : window needs to be skipped");
             return;
@@ -974,25 +1102,33 @@ impl dtvcc_service_decoder {
         };
         debug!("{}*{} will be copied to the TV.", copy_rows, copy_cols);
 
-        unsafe {
-            let tv = &mut *self.tv;
-            for row in 0..copy_rows as usize {
-                for col in 0..CCX_DTVCC_SCREENGRID_COLUMNS as usize {
-                    if col < copy_cols as usize {
-                        tv.chars[top as usize + row][col] = window.rows[row].add(col).read();
-                    }
-                    tv.pen_attribs[top as usize + row][col] = window.pen_attribs[row][col];
-                    tv.pen_colors[top as usize + row][col] = window.pen_colors[row][col];
+        let tv = &mut self.tv;
+        for row in 0..copy_rows as usize {
+            for col in 0..CCX_DTVCC_SCREENGRID_COLUMNS as usize {
+                if col < copy_cols as usize {
+                    tv.chars[top as usize + row][col] = window.rows[row][col];
                 }
+                tv.pen_attribs[top as usize + row][col] = window.pen_attribs[row][col];
+                tv.pen_colors[top as usize + row][col] = window.pen_colors[row][col];
             }
-
-            tv.update_time_show(window.time_ms_show);
-            tv.update_time_hide(window.time_ms_hide);
+            tv.column_offsets[top as usize + row] = left as u8;
+        }
+        let is_roll_up = matches!(
+            WindowPreset::get_style(window.win_style as u8),
+            Ok(WindowPreset::NtscRollup)
+                | Ok(WindowPreset::Rollup)
+                | Ok(WindowPreset::NtscCenteredRollup)
+        );
+        if is_roll_up {
+            tv.roll_up_rows = Some(window.row_count as u8);
         }
+
+        tv.update_time_show(window.time_ms_show);
+        tv.update_time_hide(window.time_ms_hide);
     }
 
     /// Returns `true` if the given window is overlapping other windows
-    pub fn is_window_overlapping(&self, window: &dtvcc_window) -> bool {
+    pub fn is_window_overlapping(&self, window: &Window) -> bool {
         let mut flag = 0;
         let (a_x1, a_x2, a_y1, a_y2) = match window.get_dimensions() {
             Ok(val) => val,
@@ -1044,6 +1180,8 @@ impl dtvcc_service_decoder {
     pub fn handle_G0(&mut self, block: &[c_uchar]) -> i32 {
         if self.current_window == -1 {
             warn!("dtvcc_handle_G0: Window has to be defined first");
+            self.report.undefined_window_writes += 1;
+            self.add_undefined_window_finding("Text");
             return 1;
         }
 
@@ -1062,6 +1200,8 @@ impl dtvcc_service_decoder {
     pub fn handle_G1(&mut self, block: &[c_uchar]) -> i32 {
         if self.current_window == -1 {
             warn!("dtvcc_handle_G1: Window has to be defined first");
+            self.report.undefined_window_writes += 1;
+            self.add_undefined_window_finding("Text");
             return 1;
         }
 
@@ -1093,16 +1233,24 @@ impl dtvcc_service_decoder {
         );
 
         match code {
-            0..=0x1F => commands::handle_C2(code),
+            0..=0x1F => {
+                self.report.reserved_codes += 1;
+                self.add_finding(Severity::Warning, format!("Reserved C2 code {:02X}", code));
+                commands::handle_C2(code)
+            }
             0x20..=0x7F => {
-                let val = unsafe { dtvcc_get_internal_from_G2(code) };
+                let val = internal_from_G2(code);
                 let sym = dtvcc_symbol::new(val as u16);
                 self.process_character(sym);
                 1
             }
-            0x80..=0x9F => commands::handle_C3(code, block[1]),
+            0x80..=0x9F => {
+                self.report.reserved_codes += 1;
+                self.add_finding(Severity::Warning, format!("Reserved C3 code {:02X}", code));
+                commands::handle_C3(code, block[1])
+            }
             _ => {
-                let val = unsafe { dtvcc_get_internal_from_G3(code) };
+                let val = internal_from_G3(code);
                 let sym = dtvcc_symbol::new(val as u16);
                 self.process_character(sym);
                 1
@@ -1112,34 +1260,35 @@ impl dtvcc_service_decoder {
 
     /// Process the character and add it to the current window
     pub fn process_character(&mut self, sym: dtvcc_symbol) {
-        debug!("{}", self.current_window);
+        if self.current_window == -1 {
+            debug!(
+                "dtvcc_process_character: [{:04X}] - Window -1 [undefined]",
+                sym.sym
+            );
+            self.report.undefined_window_writes += 1;
+            self.add_undefined_window_finding("Text");
+            return;
+        }
         let window = &mut self.windows[self.current_window as usize];
         let window_state = if is_true(window.is_defined) {
             "OK"
         } else {
             "undefined"
         };
-        let (mut row, mut column) = (-1, -1);
-        if self.current_window != -1 {
-            row = window.pen_row;
-            column = window.pen_column
-        }
         debug!(
             "dtvcc_process_character: [{:04X}] - Window {} [{}], Pen: {}:{}",
-            sym.sym, self.current_window, window_state, row, column
+            sym.sym, self.current_window, window_state, window.pen_row, window.pen_column
         );
 
-        if self.current_window == -1 || is_false(window.is_defined) {
+        if is_false(window.is_defined) {
+            self.report.undefined_window_writes += 1;
+            self.add_undefined_window_finding("Text");
             return;
         }
 
         window.is_empty = 0;
         // Add symbol to window
-        unsafe {
-            window.rows[window.pen_row as usize]
-                .add(window.pen_column as usize)
-                .write(sym);
-        }
+        window.rows[window.pen_row as usize][window.pen_column as usize] = sym;
         // "Painting" char by pen - attribs
         window.pen_attribs[window.pen_row as usize][window.pen_column as usize] =
             window.pen_attribs_pattern;
@@ -1177,47 +1326,98 @@ impl dtvcc_service_decoder {
             }
         };
     }
+    /// Hide all visible windows and print their contents to the output file
+    pub fn print_visible_windows(&mut self, encoder: &mut encoder_ctx, timing: &mut Timing) {
+        let mut screen_content_changed = false;
+        for i in 0..CCX_DTVCC_MAX_WINDOWS as usize {
+            let window = &mut self.windows[i];
+            if is_true(window.visible) {
+                screen_content_changed = true;
+                window.update_time_hide(timing);
+                self.copy_to_screen(i);
+                self.windows[i].visible = 0
+            }
+        }
+        if screen_content_changed {
+            self.screen_print(encoder, timing);
+        }
+    }
     /// Flush the decoder of any remaining subtitles
-    pub fn flush(&self, encoder: &mut encoder_ctx) {
-        unsafe {
-            let tv = &mut (*self.tv);
-            let sn = tv.service_number;
-            let writer_ctx = &mut encoder.dtvcc_writers[(sn - 1) as usize];
-
-            let mut writer = Writer::new(
-                &mut encoder.cea_708_counter,
-                encoder.subs_delay,
-                encoder.write_format,
-                writer_ctx,
-                encoder.no_font_color,
-                &*encoder.transcript_settings,
-                encoder.no_bom,
-            );
+    pub fn flush(&mut self, encoder: &mut encoder_ctx) {
+        let sn = self.tv.service_number;
+        if let Some(screen) = self.cues.finish() {
+            write_screen(&screen, &mut self.outputs, &mut self.report, encoder);
+        }
+        for output in self.outputs.iter_mut() {
+            let mut writer = Writer::new(encoder, sn, output);
             writer.write_done();
         }
     }
 }
 
-/// Flush service decoder
-#[no_mangle]
-extern "C" fn ccxr_flush_decoder(dtvcc: *mut dtvcc_ctx, decoder: *mut dtvcc_service_decoder) {
-    debug!("dtvcc_decoder_flush: Flushing decoder");
-    let timing = unsafe { &mut *((*dtvcc).timing) };
-    let encoder = unsafe { &mut *((*dtvcc).encoder as *mut encoder_ctx) };
-    let decoder = unsafe { &mut *decoder };
+/// Returns the internal code of a G2 character, see `dtvcc_get_internal_from_G2`
+///
+/// G2 characters 20-3F are stored as 00-1F and 60-7F as 80-9F, the rest is a blank
+fn internal_from_G2(g2_char: u8) -> u8 {
+    match g2_char {
+        0x20..=0x3F => g2_char - 0x20,
+        0x60..=0x7F => g2_char + 0x20,
+        _ => 0x20,
+    }
+}
 
-    let mut screen_content_changed = false;
-    for i in 0..CCX_DTVCC_MAX_WINDOWS {
-        let window = &mut decoder.windows[i as usize];
-        if is_true(window.visible) {
-            screen_content_changed = true;
-            window.update_time_hide(timing);
-            decoder.copy_to_screen(&decoder.windows[i as usize]);
-            decoder.windows[i as usize].visible = 0
+/// Returns the internal code of a G3 character, see `dtvcc_get_internal_from_G3`
+///
+/// Only the CC icon is defined in G3, it is stored as 06
+fn internal_from_G3(g3_char: u8) -> u8 {
+    if g3_char == 0xA0 {
+        0x06
+    } else {
+        0x20
+    }
+}
+
+/// Write a screen to all outputs of the service
+///
+/// Every output gets the same screen, so the stream is only decoded once
+fn write_screen(
+    screen: &TvScreen,
+    outputs: &mut [ServiceOutput],
+    report: &mut ServiceReport,
+    encoder: &mut encoder_ctx,
+) {
+    for output in outputs.iter_mut() {
+        let mut writer = Writer::new(encoder, screen.service_number, output);
+        if let Err(err) = screen.writer_output(&mut writer) {
+            warn!("dtvcc_screen_print: {}", err);
         }
+        report.charset_failures += writer.charset_failures as u64;
     }
-    if screen_content_changed {
-        decoder.screen_print(encoder, timing);
+}
+
+/// Flush all active service decoders which have printed captions
+#[no_mangle]
+extern "C" fn ccxr_flush_active_decoders(dec_ctx: *mut lib_cc_decode) {
+    let dec_ctx = unsafe { &mut *dec_ctx };
+    let dtvcc = unsafe { &mut *(dec_ctx.dtvcc_rust as *mut Dtvcc) };
+    if !dtvcc.is_active {
+        return;
     }
+    let encoder = match unsafe { ((*dec_ctx.dtvcc).encoder as *mut encoder_ctx).as_mut() } {
+        Some(encoder) => encoder,
+        None => return,
+    };
+    for decoder in dtvcc.decoders.iter_mut().flatten() {
+        if decoder.cc_count > 0 {
+            dec_ctx.current_field = 3;
+            flush_decoder(decoder, encoder, &mut dtvcc.timing);
+        }
+    }
+}
+
+/// Flush service decoder
+fn flush_decoder(decoder: &mut ServiceDecoder, encoder: &mut encoder_ctx, timing: &mut Timing) {
+    debug!("dtvcc_decoder_flush: Flushing decoder");
+    decoder.print_visible_windows(encoder, timing);
     decoder.flush(encoder);
 }
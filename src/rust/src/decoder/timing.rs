@@ -1,44 +1,163 @@
 //! Utilty functions to get timing for captions
 
-use crate::{bindings::*, cb_708, cb_field1, cb_field2};
+use crate::bindings::*;
 
-use log::{debug, error};
+use log::debug;
 
-impl ccx_common_timing_ctx {
-    /// Return the current FTS
-    pub fn get_fts(&self, current_field: u8) -> LLONG {
-        unsafe {
-            match current_field {
-                1 => self.fts_now + self.fts_global + cb_field1 as i64 * 1001 / 30,
-                2 => self.fts_now + self.fts_global + cb_field2 as i64 * 1001 / 30,
-                3 => self.fts_now + self.fts_global + cb_708 as i64 * 1001 / 30,
-                _ => {
-                    error!("get_fts: Unknown field");
-                    0
-                }
-            }
+/// Timing of a single 708 decoder instance
+///
+/// Every caption block of a frame is shown 1001/30 ms after the previous one, so the FTS of a
+/// block depends on the number of blocks already seen in the current frame. The C decoders keep
+/// these counts in process wide globals, whereas this keeps its own count. Like `reset_cb` in
+/// `sequencing.c`, the count is only reset once the frame timestamp changes, so frames whose
+/// timestamp did not advance keep counting blocks. This allows several decoders to run in the
+/// same process.
+pub struct Timing<'a> {
+    pub ctx: &'a mut ccx_common_timing_ctx,
+    /// Number of 708 caption blocks seen in the current frame
+    pub cb_708: i32,
+    /// Frame timestamp the caption block count belongs to
+    frame_fts: LLONG,
+}
+
+impl<'a> Timing<'a> {
+    /// Create timing for the given C timing context
+    pub fn new(ctx: &'a mut ccx_common_timing_ctx) -> Self {
+        let frame_fts = ctx.fts_now;
+        Self {
+            ctx,
+            cb_708: 0,
+            frame_fts,
         }
     }
+    /// Reset the caption block count if the frame timestamp changed, to be called once per frame
+    /// of cc_data
+    pub fn sync_frame(&mut self) {
+        if self.ctx.fts_now != self.frame_fts {
+            self.frame_fts = self.ctx.fts_now;
+            self.cb_708 = 0;
+        }
+    }
+    /// Set the FTS of the current frame directly, used when replaying captured packets
+    pub fn set_fts(&mut self, fts: LLONG) {
+        self.ctx.fts_now = fts - self.ctx.fts_global;
+        self.frame_fts = self.ctx.fts_now;
+        self.cb_708 = 0;
+    }
+    /// Count a processed caption block
+    pub fn count_block(&mut self) {
+        self.cb_708 += 1;
+    }
+    /// Return the current FTS
+    pub fn get_fts(&self) -> LLONG {
+        self.ctx.fts_now + self.ctx.fts_global + self.cb_708 as i64 * 1001 / 30
+    }
     /// Returns the current FTS and saves it so it can be used by [get_visible_start][Self::get_visible_start()]
-    pub fn get_visible_end(&mut self, current_field: u8) -> LLONG {
-        let fts = self.get_fts(current_field);
-        if fts > self.minimum_fts {
-            self.minimum_fts = fts;
+    pub fn get_visible_end(&mut self) -> LLONG {
+        let fts = self.get_fts();
+        if fts > self.ctx.minimum_fts {
+            self.ctx.minimum_fts = fts;
         }
         debug!("Visible End time={}", get_time_str(fts));
         fts
     }
     /// Returns a FTS that is guaranteed to be at least 1 ms later than the end of the previous screen, so that there's no timing overlap
-    pub fn get_visible_start(self, current_field: u8) -> LLONG {
-        let mut fts = self.get_fts(current_field);
-        if fts <= self.minimum_fts {
-            fts = self.minimum_fts + 1;
+    pub fn get_visible_start(&self) -> LLONG {
+        let mut fts = self.get_fts();
+        if fts <= self.ctx.minimum_fts {
+            fts = self.ctx.minimum_fts + 1;
         }
         debug!("Visible Start time={}", get_time_str(fts));
         fts
     }
 }
 
+/// Transformation of the caption times for the output
+///
+/// Times are first scaled, e.g. to convert between frame rates, and then shifted by the offset.
+/// Captions are trimmed to the clipping range, which never starts before zero, and dropped if
+/// nothing of them is left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeTransform {
+    /// Added to all times, in ms
+    pub offset: LLONG,
+    /// Factor all times are multiplied with
+    pub scale: f64,
+    /// Start of the output in ms, captions before it are trimmed
+    pub clip_start: Option<LLONG>,
+    /// End of the output in ms, captions after it are trimmed
+    pub clip_end: Option<LLONG>,
+}
+
+impl Default for TimeTransform {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            scale: 1.0,
+            clip_start: None,
+            clip_end: None,
+        }
+    }
+}
+
+impl TimeTransform {
+    /// Returns the transformed time
+    pub fn time(&self, time: LLONG) -> LLONG {
+        if self.scale == 1.0 {
+            time + self.offset
+        } else {
+            (time as f64 * self.scale).round() as LLONG + self.offset
+        }
+    }
+    /// Returns the transformed show and hide time of a caption
+    ///
+    /// Returns `None` if the caption lies completely outside of the clipping range
+    pub fn apply(&self, show: LLONG, hide: LLONG) -> Option<(LLONG, LLONG)> {
+        let start = self.clip_start.unwrap_or(0).max(0);
+        let end = self.clip_end.unwrap_or(LLONG::MAX);
+        let (show, hide) = (self.time(show), self.time(hide));
+        if hide <= start || show >= end {
+            return None;
+        }
+        Some((show.max(start), hide.min(end)))
+    }
+}
+
+/// Returns the number of frames shown until `time` in ms at `rate_num` / `rate_den` frames per second
+pub fn frame_count(time: LLONG, rate_num: i64, rate_den: i64) -> i64 {
+    time.max(0) * rate_num / (rate_den * 1000)
+}
+
+/// Returns the SMPTE timecode of a frame number at a nominal rate of e.g. 30 or 60 frames
+///
+/// Drop-frame timecodes, for 29.97 and 59.94 fps, skip the first frame numbers of every minute
+/// except each tenth so that they stay in sync with the time, and separate the frames with `;`.
+pub fn frames_to_timecode(frames: i64, nominal_rate: i64, drop_frame: bool) -> String {
+    let frames = frames.max(0);
+    let (number, separator) = if drop_frame {
+        // 2 frame numbers are dropped at 30 fps, 4 at 60 fps
+        let dropped = nominal_rate / 15;
+        let per_ten_minutes = nominal_rate * 600 - 9 * dropped;
+        let per_minute = nominal_rate * 60 - dropped;
+        let rest = frames % per_ten_minutes;
+        let mut skipped = 9 * dropped * (frames / per_ten_minutes);
+        if rest > dropped {
+            skipped += dropped * ((rest - dropped) / per_minute);
+        }
+        (frames + skipped, ';')
+    } else {
+        (frames, ':')
+    };
+    format!(
+        "{:02}:{:02}:{:02}{}{:02}",
+        number / (nominal_rate * 3600),
+        number / (nominal_rate * 60) % 60,
+        number / nominal_rate % 60,
+        separator,
+        number % nominal_rate
+    )
+}
+
 /// Returns a hh:mm:ss,ms string of time
 pub fn get_time_str(time: LLONG) -> String {
     let hh = time / 1000 / 60 / 60;
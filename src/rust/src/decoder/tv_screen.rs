@@ -3,23 +3,60 @@
 //! TV screen contains the captions to be displayed.
 //! Captions are added to TV screen from a window when any of DSW, HDW, TGW, DLW or CR commands are received  
 
-#[cfg(unix)]
-use std::os::unix::prelude::IntoRawFd;
-#[cfg(windows)]
-use std::os::windows::io::IntoRawHandle;
-use std::{ffi::CStr, fs::File};
+use std::ffi::CStr;
 
-use super::output::{color_to_hex, write_char, Writer};
+use super::output::{color_to_hex, write_char, OutputFormat, Writer};
+use super::scc::{
+    self, Color608, Line608, Style608, StyledChar, SCC_COLUMNS, SCC_HEADER, SCC_ROWS,
+};
 use super::timing::get_time_str;
+use super::window::{new_grid, Grid};
 use super::{CCX_DTVCC_SCREENGRID_COLUMNS, CCX_DTVCC_SCREENGRID_ROWS};
 use crate::{
     bindings::*,
-    utils::{is_false, is_true},
+    utils::{is_false, is_true, json_escape},
 };
 
 use log::{debug, warn};
 
-impl dtvcc_tv_screen {
+/// Grid covering the whole TV screen
+type ScreenGrid<T> =
+    Grid<T, { CCX_DTVCC_SCREENGRID_COLUMNS as usize }, { CCX_DTVCC_SCREENGRID_ROWS as usize }>;
+
+/// TV screen of a single service
+///
+/// Rust side counterpart of `dtvcc_tv_screen`, owned by its service decoder
+#[derive(Clone)]
+pub struct TvScreen {
+    pub chars: ScreenGrid<dtvcc_symbol>,
+    pub pen_colors: ScreenGrid<dtvcc_pen_color>,
+    pub pen_attribs: ScreenGrid<dtvcc_pen_attribs>,
+    pub time_ms_show: LLONG,
+    pub time_ms_hide: LLONG,
+    pub cc_count: u32,
+    pub service_number: i32,
+    /// Screen column of the window each row was copied from, derived from the window anchor
+    pub column_offsets: [u8; CCX_DTVCC_SCREENGRID_ROWS as usize],
+    /// Number of rows of the window if it was a roll-up window, `None` for pop-on and others
+    pub roll_up_rows: Option<u8>,
+}
+
+impl TvScreen {
+    /// Create a new empty TV screen for the service
+    pub fn new(service_number: i32) -> Self {
+        Self {
+            chars: new_grid(dtvcc_symbol::default()),
+            pen_colors: new_grid(dtvcc_pen_color::default()),
+            pen_attribs: new_grid(dtvcc_pen_attribs::default()),
+            time_ms_show: -1,
+            time_ms_hide: -1,
+            cc_count: 0,
+            service_number,
+            column_offsets: [0; CCX_DTVCC_SCREENGRID_ROWS as usize],
+            roll_up_rows: None,
+        }
+    }
+
     /// Clear all text from TV screen
     pub fn clear(&mut self) {
         for row in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
@@ -27,6 +64,8 @@ impl dtvcc_tv_screen {
         }
         self.time_ms_hide = -1;
         self.time_ms_show = -1;
+        self.column_offsets.fill(0);
+        self.roll_up_rows = None;
     }
 
     /// Update TV screen show time
@@ -51,54 +90,13 @@ impl dtvcc_tv_screen {
 
     /// Write captions to the output file
     ///
-    /// Opens a new file when called for the first time.
-    /// Uses the already open file on subsequent calls.
+    /// Opens the output when called for the first time.
+    /// Uses the already open output on subsequent calls.
     pub fn writer_output(&self, writer: &mut Writer) -> Result<(), String> {
-        debug!(
-            "dtvcc_writer_output: writing... [{:?}][{}]",
-            unsafe { CStr::from_ptr(writer.writer_ctx.filename) },
-            writer.writer_ctx.fd
-        );
-
-        #[cfg(unix)]
-        if writer.writer_ctx.filename.is_null() && writer.writer_ctx.fd < 0 {
-            return Err("Filename missing".to_owned());
-        } else if writer.writer_ctx.fd < 0 {
-            let filename = unsafe {
-                CStr::from_ptr(writer.writer_ctx.filename)
-                    .to_str()
-                    .map_err(|err| err.to_string())
-            }?;
-            debug!("dtvcc_writer_output: creating {}", filename);
-            let file = File::create(filename).map_err(|err| err.to_string())?;
-            writer.writer_ctx.fd = file.into_raw_fd();
-
-            if is_false(writer.no_bom) {
-                let BOM = [0xef, 0xbb, 0xbf];
-                writer.write_to_file(&BOM)?;
-            }
-        }
-
-        #[cfg(windows)]
-        if writer.writer_ctx.filename.is_null() && writer.writer_ctx.fhandle.is_null() {
-            return Err("Filename missing".to_owned())?;
-        } else if writer.writer_ctx.fhandle.is_null() {
-            let filename = unsafe {
-                CStr::from_ptr(writer.writer_ctx.filename)
-                    .to_str()
-                    .map_err(|err| err.to_string())
-            }?;
-            debug!("dtvcc_writer_output: creating {}", filename);
-            let file = File::create(filename).map_err(|err| err.to_string())?;
-            writer.writer_ctx.fhandle = file.into_raw_handle();
-
-            if is_false(writer.no_bom) {
-                let BOM = [0xef, 0xbb, 0xbf];
-                writer.write_to_file(&BOM)?;
-            }
-        }
+        debug!("dtvcc_writer_output: writing...");
+        writer.open()?;
         self.write(writer);
-        Ok(())
+        writer.end_caption()
     }
 
     /// Returns the bounds in which captions are present
@@ -125,10 +123,13 @@ impl dtvcc_tv_screen {
     /// Calls the respective function for the output file type
     pub fn write(&self, writer: &mut Writer) {
         let result = match writer.write_format {
-            ccx_output_format::CCX_OF_SRT => self.write_srt(writer),
-            ccx_output_format::CCX_OF_SAMI => self.write_sami(writer),
-            ccx_output_format::CCX_OF_TRANSCRIPT => self.write_transcript(writer),
-            _ => {
+            OutputFormat::Srt => self.write_srt(writer),
+            OutputFormat::Sami => self.write_sami(writer),
+            OutputFormat::Transcript => self.write_transcript(writer),
+            OutputFormat::Scc => self.write_scc(writer),
+            OutputFormat::WebVtt => self.write_webvtt(writer),
+            OutputFormat::Json => self.write_json(writer),
+            OutputFormat::Unsupported => {
                 self.write_debug();
                 Err("Unsupported write format".to_owned())
             }
@@ -218,40 +219,66 @@ impl dtvcc_tv_screen {
             &mut buf,
         );
         // Tags can still be crossed e.g <f><i>text</f></i>, but testing HTML code has shown that they still are handled correctly.
-        if writer.writer_ctx.cd != (-1_isize) as iconv_t {
-            if writer.writer_ctx.charset.is_null() {
-                debug!("Charset: null");
-            } else {
-                let charset = unsafe {
-                    CStr::from_ptr(writer.writer_ctx.charset)
-                        .to_str()
-                        .map_err(|err| err.to_string())?
-                };
-                debug!("Charset: {}", charset);
-
-                let op = iconv::decode(&buf, charset).map_err(|err| err.to_string())?;
-                writer.write_to_file(op.as_bytes())?;
-            }
-        } else {
-            writer.write_to_file(&buf)?;
+        if let Some(text) = self.convert_charset(writer, buf)? {
+            writer.write_to_file(&text)?;
         }
-
         Ok(())
     }
 
+    /// Convert the text of a row from the charset of the service
+    ///
+    /// Returns `None` if a conversion is configured but the charset is unknown
+    fn convert_charset(
+        &self,
+        writer: &mut Writer,
+        buf: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, String> {
+        if writer.writer_ctx.cd == (-1_isize) as iconv_t {
+            return Ok(Some(buf));
+        }
+        if writer.writer_ctx.charset.is_null() {
+            debug!("Charset: null");
+            return Ok(None);
+        }
+        let charset = unsafe {
+            CStr::from_ptr(writer.writer_ctx.charset)
+                .to_str()
+                .map_err(|err| err.to_string())?
+        };
+        debug!("Charset: {}", charset);
+
+        let op = iconv::decode(&buf, charset).map_err(|err| {
+            writer.charset_failures += 1;
+            err.to_string()
+        })?;
+        Ok(Some(op.into_bytes()))
+    }
+
+    /// Returns the text of a row without any formatting
+    fn row_text(&self, writer: &mut Writer, row_index: usize) -> Result<String, String> {
+        let (first, last) = self.get_write_interval(row_index);
+        let mut buf = Vec::new();
+        for sym in self.chars[row_index][first..=last].iter() {
+            write_char(sym, &mut buf);
+        }
+        let text = self.convert_charset(writer, buf)?.unwrap_or_default();
+        Ok(String::from_utf8_lossy(&text).into_owned())
+    }
+
     /// Write captions in SRT format
     pub fn write_srt(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
         if self.is_screen_empty(writer) {
             return Ok(());
         }
-        if self.time_ms_show + writer.subs_delay < 0 {
-            return Ok(());
-        }
 
-        let time_show = get_time_str(self.time_ms_show);
-        let time_hide = get_time_str(self.time_ms_hide);
+        let time_show = get_time_str(time_show);
+        let time_hide = get_time_str(time_hide);
 
-        let counter = *writer.cea_708_counter;
+        let counter = writer.output.counter;
         let line = format!(
             "{}{}{} --> {}{}",
             counter, "\r\n", time_show, time_hide, "\r\n"
@@ -268,17 +295,85 @@ impl dtvcc_tv_screen {
         Ok(())
     }
 
-    /// Write captions in Transcripts format
-    pub fn write_transcript(&self, writer: &mut Writer) -> Result<(), String> {
+    /// Write captions in WebVTT format
+    pub fn write_webvtt(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
         if self.is_screen_empty(writer) {
             return Ok(());
         }
-        if self.time_ms_show + writer.subs_delay < 0 {
+        if !writer.output.header_written {
+            writer.write_header(b"WEBVTT\r\n\r\n")?;
+            writer.output.header_written = true;
+        }
+
+        let time_show = get_time_str(time_show).replace(',', ".");
+        let time_hide = get_time_str(time_hide).replace(',', ".");
+        let line = format!("{} --> {}\r\n", time_show, time_hide);
+        writer.write_to_file(line.as_bytes())?;
+
+        for row_index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
+            if !self.is_row_empty(row_index) {
+                self.write_row(writer, row_index, false)?;
+                writer.write_to_file(b"\r\n")?;
+            }
+        }
+        writer.write_to_file(b"\r\n")?;
+        Ok(())
+    }
+
+    /// Write captions as JSON, one object per caption and line
+    pub fn write_json(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
+        if self.is_screen_empty(writer) {
             return Ok(());
         }
 
-        let time_show = get_time_str(self.time_ms_show);
-        let time_hide = get_time_str(self.time_ms_hide);
+        let mut rows = Vec::new();
+        for row_index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
+            if !self.is_row_empty(row_index) {
+                let text = self.row_text(writer, row_index)?;
+                rows.push(format!(
+                    "{{\"row\":{},\"text\":\"{}\"}}",
+                    row_index,
+                    json_escape(&text)
+                ));
+            }
+        }
+        let language = match &writer.output.language {
+            Some(language) => format!("\"{}\"", json_escape(language)),
+            None => "null".to_owned(),
+        };
+        let line = format!(
+            "{{\"index\":{},\"service\":{},\"language\":{},\"start\":{},\"end\":{},\"rows\":[{}]}}\n",
+            writer.output.counter,
+            self.service_number,
+            language,
+            time_show,
+            time_hide,
+            rows.join(",")
+        );
+        writer.write_to_file(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Write captions in Transcripts format
+    pub fn write_transcript(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
+        if self.is_screen_empty(writer) {
+            return Ok(());
+        }
+
+        let time_show = get_time_str(time_show);
+        let time_hide = get_time_str(time_hide);
 
         for row_index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
             if !self.is_row_empty(row_index) {
@@ -308,22 +403,18 @@ impl dtvcc_tv_screen {
 
     /// Write captions in SAMI format
     pub fn write_sami(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
         if self.is_screen_empty(writer) {
             return Err("Sami:- Screen is empty".to_owned());
         }
-        if self.time_ms_show + writer.subs_delay < 0 {
-            return Err(format!(
-                "Sami:- timing is -ve, {}:{}",
-                self.time_ms_show, writer.subs_delay
-            ));
-        }
-        if self.cc_count == 1 {
+        if !writer.output.header_written {
             self.write_sami_header(writer)?;
+            writer.output.header_written = true;
         }
-        let buf = format!(
-            "<sync start={}><p class=\"unknowncc\">\r\n",
-            self.time_ms_show + writer.subs_delay
-        );
+        let buf = format!("<sync start={}><p class=\"unknowncc\">\r\n", time_show);
         writer.write_to_file(buf.as_bytes())?;
 
         for row_index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
@@ -334,12 +425,97 @@ impl dtvcc_tv_screen {
         }
         let buf = format!(
             "<sync start={}><p class=\"unknowncc\">&nbsp;</p></sync>\r\n\r\n",
-            self.time_ms_hide + writer.subs_delay
+            time_hide
         );
         writer.write_to_file(buf.as_bytes())?;
         Ok(())
     }
 
+    /// Write captions in SCC format, down-converted to CEA-608
+    ///
+    /// Rows are placed at the 608 row and column closest to their position on the 708 screen
+    /// and reflowed if they are longer than 32 columns. Captions of roll-up windows are written
+    /// as roll-up, everything else as pop-on.
+    pub fn write_scc(&self, writer: &mut Writer) -> Result<(), String> {
+        let (time_show, time_hide) = match self.cue_times(writer) {
+            Some(times) => times,
+            None => return Ok(()),
+        };
+        if self.is_screen_empty(writer) {
+            return Ok(());
+        }
+        if !writer.output.header_written {
+            writer.write_header(SCC_HEADER.as_bytes())?;
+            writer.output.header_written = true;
+        }
+
+        let mut lines: Vec<Line608> = Vec::new();
+        let mut first_row = None;
+        for row_index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
+            if self.is_row_empty(row_index) {
+                continue;
+            }
+            first_row.get_or_insert(row_index);
+            let (first, last) = self.get_write_interval(row_index);
+            let chars: Vec<StyledChar> = (first..=last)
+                .map(|col| {
+                    let sym = &self.chars[row_index][col];
+                    let c = if sym.is_set() {
+                        symbol_to_char(sym.sym)
+                    } else {
+                        ' '
+                    };
+                    let attribs = &self.pen_attribs[row_index][col];
+                    let style = Style608 {
+                        color: Color608::from_708(self.pen_colors[row_index][col].fg_color as u8),
+                        italic: is_true(attribs.italic),
+                        underline: is_true(attribs.underline),
+                    };
+                    (c, style)
+                })
+                .collect();
+            let screen_column = self.column_offsets[row_index] as usize + first;
+            for line in scc::reflow(&chars) {
+                let column = (screen_column * SCC_COLUMNS / CCX_DTVCC_SCREENGRID_COLUMNS as usize)
+                    .min(SCC_COLUMNS - scc::columns(&line));
+                lines.push(Line608 {
+                    row: 0,
+                    column,
+                    chars: line,
+                });
+            }
+        }
+        lines.truncate(SCC_ROWS);
+
+        // Keep the lines together, starting at the row of the first one if they fit
+        let first_row = first_row.unwrap_or(0) * SCC_ROWS / CCX_DTVCC_SCREENGRID_ROWS as usize;
+        let first_row = first_row.min(SCC_ROWS - lines.len());
+        for (index, line) in lines.iter_mut().enumerate() {
+            line.row = first_row + index;
+        }
+
+        let buf = match self.roll_up_rows {
+            Some(rows) => {
+                let crlf = writer.crlf.clone();
+                scc::roll_up(
+                    &lines,
+                    rows,
+                    time_show,
+                    &crlf,
+                    &mut writer.output.scc_roll_up_lines,
+                )
+            }
+            None => {
+                writer.output.scc_roll_up_lines.clear();
+                Some(scc::pop_on(&lines, time_show, time_hide, &writer.crlf))
+            }
+        };
+        if let Some(buf) = buf {
+            writer.write_to_file(buf.as_bytes())?;
+        }
+        Ok(())
+    }
+
     /// Writes the header according to the SAMI format
     pub fn write_sami_header(&self, writer: &mut Writer) -> Result<(), String> {
         let buf = b"<sami>\r\n\
@@ -354,7 +530,7 @@ impl dtvcc_tv_screen {
                             </head>\r\n\r\n\
                             <body>\r\n";
 
-        writer.write_to_file(buf)?;
+        writer.write_header(buf)?;
         Ok(())
     }
 
@@ -378,18 +554,37 @@ impl dtvcc_tv_screen {
         }
     }
 
+    /// Returns the show and hide time of the screen, transformed for the output
+    ///
+    /// Returns `None` if the caption lies completely outside of the output
+    pub fn cue_times(&self, writer: &Writer) -> Option<(LLONG, LLONG)> {
+        let times = writer
+            .time_transform
+            .apply(self.time_ms_show, self.time_ms_hide);
+        if times.is_none() {
+            debug!(
+                "dtvcc_cue_times: Dropping caption at {}, it is outside of the output",
+                get_time_str(self.time_ms_show)
+            );
+        }
+        times
+    }
+
     /// Returns `true` if TV screen has no text
     ///
-    /// If any text is found then 708 counter is incremented
+    /// If any text is found then the caption counter of the output is incremented
     pub fn is_screen_empty(&self, writer: &mut Writer) -> bool {
-        for index in 0..CCX_DTVCC_SCREENGRID_ROWS as usize {
-            if !self.is_row_empty(index) {
-                // we will write subtitle
-                *writer.cea_708_counter += 1;
-                return false;
-            }
+        if self.is_empty() {
+            return true;
         }
-        true
+        // we will write subtitle
+        writer.output.counter += 1;
+        false
+    }
+
+    /// Returns `true` if TV screen has no text
+    pub fn is_empty(&self) -> bool {
+        (0..CCX_DTVCC_SCREENGRID_ROWS as usize).all(|index| self.is_row_empty(index))
     }
 
     /// Returns `true` if row has no text
@@ -475,3 +670,42 @@ impl dtvcc_tv_screen {
         }
     }
 }
+
+/// Returns the character of a symbol, G2 and G3 characters are stored as internal codes
+///
+/// Refer `internal_from_G2` and `internal_from_G3` of the service decoder
+pub(crate) fn symbol_to_char(sym: u16) -> char {
+    match sym {
+        // Transparent and non-breaking transparent space
+        0x00 | 0x01 => ' ',
+        0x05 => '…',
+        // CC icon
+        0x06 => ' ',
+        0x0A => 'Š',
+        0x0C => 'Œ',
+        0x10 => '█',
+        0x11 => '‘',
+        0x12 => '’',
+        0x13 => '“',
+        0x14 => '”',
+        0x15 => '•',
+        0x19 => '™',
+        0x1A => 'š',
+        0x1C => 'œ',
+        0x1D => '℠',
+        0x1F => 'Ÿ',
+        0x96 => '⅛',
+        0x97 => '⅜',
+        0x98 => '⅝',
+        0x99 => '⅞',
+        0x9A => '│',
+        0x9B => '┐',
+        0x9C => '└',
+        0x9D => '─',
+        0x9E => '┘',
+        0x9F => '┌',
+        // Musical note of the G0 set
+        0x266A | 0x266C => '♪',
+        _ => std::char::from_u32(sym as u32).unwrap_or('?'),
+    }
+}
@@ -10,21 +10,108 @@
 //! At any time there is a current window to which all subsequent
 //! window/pen commands are directed. All caption text is written to the current window
 
-use std::{
-    alloc::{alloc_zeroed, dealloc, Layout},
-    intrinsics::copy_nonoverlapping,
-};
+use std::convert::TryInto;
 
-use super::timing::get_time_str;
+use super::timing::{get_time_str, Timing};
 use super::{
     CCX_DTVCC_MAX_COLUMNS, CCX_DTVCC_MAX_ROWS, CCX_DTVCC_SCREENGRID_COLUMNS,
     CCX_DTVCC_SCREENGRID_ROWS,
 };
-use crate::{bindings::*, utils::is_true};
+use crate::bindings::*;
+
+use log::debug;
 
-use log::{debug, error};
+/// Fixed size grid of rows, allocated on the heap
+pub type Grid<T, const COLUMNS: usize, const ROWS: usize> = Box<[[T; COLUMNS]; ROWS]>;
 
-impl dtvcc_window {
+/// Create a new heap allocated grid with every cell set to `value`
+///
+/// The grid is built directly on the heap, since screen sized grids are too large for the stack
+pub fn new_grid<T: Copy, const COLUMNS: usize, const ROWS: usize>(
+    value: T,
+) -> Grid<T, COLUMNS, ROWS> {
+    match vec![[value; COLUMNS]; ROWS].into_boxed_slice().try_into() {
+        Ok(grid) => grid,
+        Err(_) => unreachable!("vector has exactly ROWS rows"),
+    }
+}
+
+/// Caption window
+///
+/// Rust side counterpart of `dtvcc_window`. Text rows are owned by the window instead of being
+/// allocated separately, so clearing or rolling up a row never allocates.
+#[derive(Clone)]
+pub struct Window {
+    pub is_defined: i32,
+    pub number: i32,
+    pub priority: i32,
+    pub col_lock: i32,
+    pub row_lock: i32,
+    pub visible: i32,
+    pub anchor_vertical: i32,
+    pub relative_pos: i32,
+    pub anchor_horizontal: i32,
+    pub row_count: i32,
+    pub anchor_point: i32,
+    pub col_count: i32,
+    pub pen_style: i32,
+    pub win_style: i32,
+    /// Commands used to create this window
+    pub commands: [u8; 6],
+    pub attribs: dtvcc_window_attribs,
+    pub pen_row: i32,
+    pub pen_column: i32,
+    pub rows:
+        Grid<dtvcc_symbol, { CCX_DTVCC_MAX_COLUMNS as usize }, { CCX_DTVCC_MAX_ROWS as usize }>,
+    pub pen_colors: Grid<
+        dtvcc_pen_color,
+        { CCX_DTVCC_SCREENGRID_COLUMNS as usize },
+        { CCX_DTVCC_MAX_ROWS as usize },
+    >,
+    pub pen_attribs: Grid<
+        dtvcc_pen_attribs,
+        { CCX_DTVCC_SCREENGRID_COLUMNS as usize },
+        { CCX_DTVCC_MAX_ROWS as usize },
+    >,
+    pub pen_color_pattern: dtvcc_pen_color,
+    pub pen_attribs_pattern: dtvcc_pen_attribs,
+    pub is_empty: i32,
+    pub time_ms_show: LLONG,
+    pub time_ms_hide: LLONG,
+}
+
+impl Window {
+    /// Create a new undefined and hidden window
+    pub fn new() -> Self {
+        Self {
+            is_defined: 0,
+            number: 0,
+            priority: 0,
+            col_lock: 0,
+            row_lock: 0,
+            visible: 0,
+            anchor_vertical: 0,
+            relative_pos: 0,
+            anchor_horizontal: 0,
+            row_count: 0,
+            anchor_point: 0,
+            col_count: 0,
+            pen_style: 0,
+            win_style: 0,
+            commands: [0; 6],
+            attribs: dtvcc_window_attribs::default(),
+            pen_row: 0,
+            pen_column: 0,
+            rows: new_grid(dtvcc_symbol::default()),
+            pen_colors: new_grid(dtvcc_pen_color::default()),
+            pen_attribs: new_grid(dtvcc_pen_attribs::default()),
+            pen_color_pattern: dtvcc_pen_color::default(),
+            pen_attribs_pattern: dtvcc_pen_attribs::default(),
+            is_empty: 1,
+            time_ms_show: -1,
+            time_ms_hide: -1,
+        }
+    }
     /// Sets the window style according to the window preset
     pub fn set_style(&mut self, preset: WindowPreset) {
         let style_id = preset as i32;
@@ -60,14 +147,14 @@ impl dtvcc_window {
         pen_color.edge_color = pen_style.color.edge_color as i32;
     }
     /// Update the show time for the window
-    pub fn update_time_show(&mut self, timing: &mut ccx_common_timing_ctx) {
-        self.time_ms_show = timing.get_visible_start(3);
+    pub fn update_time_show(&mut self, timing: &mut Timing) {
+        self.time_ms_show = timing.get_visible_start();
         let time = get_time_str(self.time_ms_show);
         debug!("[W-{}] show time updated to {}", self.number, time);
     }
     /// Update the hide time for the window
-    pub fn update_time_hide(&mut self, timing: &mut ccx_common_timing_ctx) {
-        self.time_ms_hide = timing.get_visible_end(3);
+    pub fn update_time_hide(&mut self, timing: &mut Timing) {
+        self.time_ms_hide = timing.get_visible_end();
         let time = get_time_str(self.time_ms_hide);
         debug!("[W-{}] hide time updated to {}", self.number, time);
     }
@@ -159,44 +246,12 @@ impl dtvcc_window {
     }
     /// Clear text from the selected row
     pub fn clear_row(&mut self, row_index: usize) {
-        if is_true(self.memory_reserved) {
-            unsafe {
-                let layout = Layout::array::<dtvcc_symbol>(CCX_DTVCC_MAX_COLUMNS as usize);
-                if let Err(e) = layout {
-                    error!("clear_row: Incorrect Layout, {}", e);
-                } else {
-                    let layout = layout.unwrap();
-                    // deallocate previous memory
-                    dealloc(self.rows[row_index] as *mut u8, layout);
-
-                    // allocate new zero initialized memory
-                    let ptr = alloc_zeroed(layout);
-                    if ptr.is_null() {
-                        error!("clear_row: Not enough memory",);
-                    }
-                    self.rows[row_index] = ptr as *mut dtvcc_symbol;
-                }
-            }
-            for col in 0..CCX_DTVCC_MAX_COLUMNS as usize {
-                // Set pen attributes to default value
-                self.pen_attribs[row_index][col] = dtvcc_pen_attribs {
-                    pen_size: dtvcc_pen_size::DTVCC_PEN_SIZE_STANDART as i32,
-                    offset: 0,
-                    text_tag: dtvcc_pen_text_tag::DTVCC_PEN_TEXT_TAG_UNDEFINED_12 as i32,
-                    font_tag: 0,
-                    edge_type: dtvcc_pen_edge::DTVCC_PEN_EDGE_NONE as i32,
-                    underline: 0,
-                    italic: 0,
-                };
-                // Set pen color to default value
-                self.pen_colors[row_index][col] = dtvcc_pen_color {
-                    fg_color: 0x3F,
-                    fg_opacity: 0,
-                    bg_color: 0,
-                    bg_opacity: 0,
-                    edge_color: 0,
-                };
-            }
+        self.rows[row_index].fill(dtvcc_symbol::default());
+        for col in 0..CCX_DTVCC_MAX_COLUMNS as usize {
+            // Set pen attributes to default value
+            self.pen_attribs[row_index][col] = dtvcc_pen_attribs::default();
+            // Set pen color to default value
+            self.pen_colors[row_index][col] = dtvcc_pen_color::default();
         }
     }
     /// Roll-up the captions
@@ -205,9 +260,7 @@ impl dtvcc_window {
     pub fn rollup(&mut self) {
         debug!("roller");
         for row_index in 0..(self.row_count - 1) as usize {
-            let curr_row = self.rows[row_index];
-            let next_row = self.rows[row_index + 1] as *const dtvcc_symbol;
-            unsafe { copy_nonoverlapping(next_row, curr_row, CCX_DTVCC_MAX_COLUMNS as usize) };
+            self.rows[row_index] = self.rows[row_index + 1];
             for col_index in 0..CCX_DTVCC_MAX_COLUMNS as usize {
                 self.pen_colors[row_index][col_index] = self.pen_colors[row_index + 1][col_index];
                 self.pen_attribs[row_index][col_index] = self.pen_attribs[row_index + 1][col_index];
@@ -217,6 +270,12 @@ impl dtvcc_window {
     }
 }
 
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl dtvcc_window_pd {
     /// Create new window direction
     pub fn new(direction: i32) -> Result<Self, String> {
@@ -534,6 +593,25 @@ impl Default for dtvcc_pen_color {
     }
 }
 
+impl Default for dtvcc_window_attribs {
+    /// Returns zeroed window attributes, as set before any style is applied
+    fn default() -> Self {
+        Self {
+            justify: 0,
+            print_direction: 0,
+            scroll_direction: 0,
+            word_wrap: 0,
+            display_effect: 0,
+            effect_direction: 0,
+            effect_speed: 0,
+            fill_color: 0,
+            fill_opacity: 0,
+            border_type: 0,
+            border_color: 0,
+        }
+    }
+}
+
 impl Default for dtvcc_pen_attribs {
     /// Returns the default pen attributes
     fn default() -> Self {
@@ -0,0 +1,582 @@
+//! CEA 708 encoder
+//!
+//! Turns timed cues into DTVCC packets and `cc_data`, using the command codes of
+//! [decoder::commands][crate::decoder::commands]. Two caption modes are supported:
+//!
+//! - Pop-on: Every cue is loaded into a hidden window, which is shown with ToggleWindows (or
+//!   DisplayWindows) at the start of the cue. Two windows are used alternately, so the next cue can
+//!   be loaded while the current one is visible.
+//! - Roll-up: A single visible window, every line of a cue is added at the bottom and the window rolls up.
+//!
+//! The generated `cc_data` is paced at [CC_COUNT] triplets per frame, of which [CC_608_COUNT] are
+//! CEA-608 padding, so the DTVCC data never exceeds the bandwidth available in a 29.97 fps stream.
+
+use crate::decoder::commands::{C0CodeSet, C1CodeSet};
+
+/// Number of cc_data triplets per frame
+pub const CC_COUNT: usize = 20;
+/// Number of triplets per frame used for CEA-608 padding, the rest carries DTVCC data
+pub const CC_608_COUNT: usize = 2;
+/// Maximum size of the data of a service block
+const MAX_BLOCK_SIZE: usize = 31;
+/// Maximum size of a DTVCC packet including its header
+const MAX_PACKET_SIZE: usize = 128;
+/// Maximum number of columns of a caption window
+const MAX_COLUMNS: usize = 32;
+/// Maximum number of rows of a caption window
+const MAX_ROWS: usize = 15;
+/// Window style #1 NTSC Style Popup Captions
+const POP_ON_WINDOW_STYLE: u8 = 1;
+/// Window style #4 NTSC Style Rollup Captions
+const ROLL_UP_WINDOW_STYLE: u8 = 4;
+/// Pen style #1 Default NTSC Style
+const PEN_STYLE: u8 = 1;
+
+/// Text style of a cue
+#[derive(Debug, Clone, Copy)]
+pub struct CueStyle {
+    pub italic: bool,
+    pub underline: bool,
+    /// Foreground color with 2 bits per RGB component, e.g. 0x2A for white
+    pub fg_color: u8,
+}
+
+impl Default for CueStyle {
+    fn default() -> Self {
+        Self {
+            italic: false,
+            underline: false,
+            fg_color: 0x2A,
+        }
+    }
+}
+
+/// Position of the window of a cue
+#[derive(Debug, Clone, Copy)]
+pub struct CuePosition {
+    /// Anchor point of the window, from 0 (top left) to 8 (bottom right)
+    pub anchor_point: u8,
+    /// Vertical position of the anchor in percent of the screen height
+    pub anchor_vertical: u8,
+    /// Horizontal position of the anchor in percent of the screen width
+    pub anchor_horizontal: u8,
+}
+
+impl Default for CuePosition {
+    /// Bottom center of the screen
+    fn default() -> Self {
+        Self {
+            anchor_point: 7,
+            anchor_vertical: 90,
+            anchor_horizontal: 50,
+        }
+    }
+}
+
+/// A caption with its display time in ms
+#[derive(Debug, Clone)]
+pub struct Cue {
+    pub start: i64,
+    pub end: i64,
+    /// Text of the cue, lines are separated by `\n`
+    pub text: String,
+    pub style: CueStyle,
+    pub position: CuePosition,
+}
+
+impl Cue {
+    /// Create a new cue with default style and position
+    pub fn new(start: i64, end: i64, text: &str) -> Self {
+        Self {
+            start,
+            end,
+            text: text.to_owned(),
+            style: CueStyle::default(),
+            position: CuePosition::default(),
+        }
+    }
+    /// Returns the lines of the cue, wrapped to the maximum window width
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for text in self.text.lines() {
+            let mut line = String::new();
+            for word in text.split_whitespace() {
+                if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > MAX_COLUMNS
+                {
+                    lines.push(line);
+                    line = String::new();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+            lines.push(line.chars().take(MAX_COLUMNS).collect());
+        }
+        lines.truncate(MAX_ROWS);
+        lines
+    }
+}
+
+/// How captions are presented
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptionMode {
+    PopOn,
+    /// Roll-up with the given number of visible rows
+    RollUp(u8),
+}
+
+/// cc_data of a single frame
+#[derive(Debug, Clone)]
+pub struct CcFrame {
+    /// Time of the frame in ms
+    pub fts: i64,
+    /// cc_data triplets, [CC_COUNT] per frame
+    pub cc_data: Vec<u8>,
+}
+
+/// Commands which have to be received by the given time
+struct Event {
+    time: i64,
+    commands: Vec<Vec<u8>>,
+}
+
+/// CEA 708 encoder for a single caption service
+pub struct Encoder {
+    service_number: u8,
+    mode: CaptionMode,
+    /// Sequence number of the next packet
+    sequence: u8,
+}
+
+impl Encoder {
+    /// Create an encoder for the service
+    pub fn new(service_number: u8, mode: CaptionMode) -> Result<Self, String> {
+        if !(1..=63).contains(&service_number) {
+            return Err(format!("Invalid service number {}", service_number));
+        }
+        if let CaptionMode::RollUp(rows) = mode {
+            if rows == 0 || rows as usize > MAX_ROWS {
+                return Err(format!("Invalid number of roll-up rows {}", rows));
+            }
+        }
+        Ok(Self {
+            service_number,
+            mode,
+            sequence: 0,
+        })
+    }
+    /// Encode the cues into cc_data, starting with the frame at time 0
+    ///
+    /// Cues have to be sorted by their start time
+    pub fn encode(&mut self, cues: &[Cue]) -> Vec<CcFrame> {
+        let events = match self.mode {
+            CaptionMode::PopOn => pop_on_events(cues),
+            CaptionMode::RollUp(rows) => roll_up_events(cues, rows as usize),
+        };
+        self.schedule(events)
+    }
+    /// Pack commands into service blocks and DTVCC packets
+    ///
+    /// A command is never split between two service blocks
+    pub fn packets(&mut self, commands: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut blocks = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        for command in commands {
+            if data.len() + command.len() > MAX_BLOCK_SIZE {
+                blocks.push(self.service_block(&data));
+                data.clear();
+            }
+            data.extend_from_slice(command);
+        }
+        if !data.is_empty() {
+            blocks.push(self.service_block(&data));
+        }
+
+        let mut packets = Vec::new();
+        let mut packet = vec![0];
+        for block in blocks {
+            if packet.len() + block.len() > MAX_PACKET_SIZE {
+                packets.push(self.finish_packet(packet));
+                packet = vec![0];
+            }
+            packet.extend(block);
+        }
+        if packet.len() > 1 {
+            packets.push(self.finish_packet(packet));
+        }
+        packets
+    }
+    /// Add the service block header to the data
+    fn service_block(&self, data: &[u8]) -> Vec<u8> {
+        let mut block = if self.service_number < 7 {
+            vec![(self.service_number << 5) | data.len() as u8]
+        } else {
+            // CEA-708-E 6.2.2 Extended Service Block Header
+            vec![(7 << 5) | data.len() as u8, self.service_number & 0x3F]
+        };
+        block.extend_from_slice(data);
+        block
+    }
+    /// Add the null header and the packet header
+    fn finish_packet(&mut self, mut packet: Vec<u8>) -> Vec<u8> {
+        if packet.len() < MAX_PACKET_SIZE {
+            // Null header is mandatory if there is room
+            packet.push(0);
+        }
+        if packet.len() % 2 == 1 {
+            packet.push(0);
+        }
+        let size_code = if packet.len() == MAX_PACKET_SIZE {
+            0
+        } else {
+            packet.len() / 2
+        };
+        packet[0] = (self.sequence << 6) | size_code as u8;
+        self.sequence = (self.sequence + 1) % 4;
+        packet
+    }
+    /// Place the packets of the events into frames
+    ///
+    /// The data of an event is sent as late as possible, so that it is complete in the frame
+    /// of the event time. If the bandwidth is not sufficient, it is sent later.
+    fn schedule(&mut self, events: Vec<Event>) -> Vec<CcFrame> {
+        let per_frame = CC_COUNT - CC_608_COUNT;
+        let mut slots: Vec<Vec<[u8; 3]>> = Vec::new();
+        let mut cursor = 0;
+        for event in events {
+            let triplets: Vec<[u8; 3]> = self
+                .packets(&event.commands)
+                .iter()
+                .flat_map(|packet| packet_cc_data(packet))
+                .collect();
+            if triplets.is_empty() {
+                continue;
+            }
+            let needed = (triplets.len() - 1) / per_frame + 1;
+            let mut frame = cursor.max((frame_at(event.time) + 1).saturating_sub(needed));
+            for triplet in triplets {
+                if slots.len() <= frame {
+                    slots.resize(frame + 1, Vec::new());
+                }
+                if slots[frame].len() == per_frame {
+                    frame += 1;
+                    slots.resize(frame + 1, Vec::new());
+                }
+                slots[frame].push(triplet);
+            }
+            cursor = frame;
+        }
+
+        slots
+            .into_iter()
+            .enumerate()
+            .map(|(i, triplets)| {
+                let mut cc_data = vec![0xFC, 0x80, 0x80, 0xFD, 0x80, 0x80];
+                for triplet in &triplets {
+                    cc_data.extend_from_slice(triplet);
+                }
+                while cc_data.len() < CC_COUNT * 3 {
+                    // DTVCC padding
+                    cc_data.extend_from_slice(&[0xFA, 0x00, 0x00]);
+                }
+                CcFrame {
+                    fts: i as i64 * 1001 / 30,
+                    cc_data,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the frame which is shown at the given time
+fn frame_at(time: i64) -> usize {
+    (time.max(0) * 30 / 1001) as usize
+}
+
+/// Split a packet into cc_data triplets, the first one starts the packet
+fn packet_cc_data(packet: &[u8]) -> Vec<[u8; 3]> {
+    packet
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            // cc_valid set, cc_type 3 for the packet start and 2 for packet data
+            let cc_info = if i == 0 { 0xFF } else { 0xFE };
+            [cc_info, pair[0], pair.get(1).copied().unwrap_or(0)]
+        })
+        .collect()
+}
+
+/// Returns the byte value of a C1 command
+fn c1(command: C1CodeSet) -> u8 {
+    command.code().expect("C1 command is not reserved")
+}
+
+/// DFx DefineWindow
+fn define_window(
+    id: u8,
+    visible: bool,
+    rows: usize,
+    columns: usize,
+    position: &CuePosition,
+    win_style: u8,
+) -> Vec<u8> {
+    vec![
+        c1(C1CodeSet::DF0) + id,
+        // Priority 0, rows and columns locked
+        (1 << 3) | (1 << 4) | ((visible as u8) << 5),
+        // Relative position
+        0x80 | position.anchor_vertical.min(99),
+        position.anchor_horizontal.min(99),
+        (rows - 1) as u8 | (position.anchor_point.min(8) << 4),
+        (columns - 1) as u8,
+        PEN_STYLE | (win_style << 3),
+    ]
+}
+
+/// Window commands which take a window bitmap (CLW, DSW, HDW, TGW and DLW)
+fn windows_command(command: C1CodeSet, bitmap: u8) -> Vec<u8> {
+    vec![c1(command), bitmap]
+}
+
+/// SPA SetPenAttributes and SPC SetPenColor for the style
+fn pen_commands(style: &CueStyle) -> Vec<Vec<u8>> {
+    vec![
+        vec![
+            c1(C1CodeSet::SPA),
+            // Standard pen size, normal offset, dialog text tag
+            1 | (1 << 2),
+            ((style.underline as u8) << 6) | ((style.italic as u8) << 7),
+        ],
+        // Solid foreground on solid black background
+        vec![c1(C1CodeSet::SPC), style.fg_color & 0x3F, 0, 0],
+    ]
+}
+
+/// SPL SetPenLocation
+fn pen_location(row: usize, column: usize) -> Vec<u8> {
+    vec![c1(C1CodeSet::SPL), row as u8 & 0x0F, column as u8 & 0x3F]
+}
+
+/// Characters of a line as G0 and G1 codes, unsupported characters are replaced by '?'
+fn text_commands(line: &str) -> Vec<Vec<u8>> {
+    line.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{A0}'..='\u{FF}' => vec![c as u8],
+            '♪' => vec![0x7F],
+            _ => vec![b'?'],
+        })
+        .collect()
+}
+
+/// Events for pop-on captions
+///
+/// Cues without text are skipped, the windows alternate between the cues which are shown
+fn pop_on_events(cues: &[Cue]) -> Vec<Event> {
+    let cues: Vec<(&Cue, Vec<String>)> = cues
+        .iter()
+        .map(|cue| (cue, cue.lines()))
+        .filter(|(_, lines)| !lines.is_empty())
+        .collect();
+    let mut events = Vec::new();
+    let mut shown: Option<u8> = None;
+    for (i, (cue, lines)) in cues.iter().enumerate() {
+        let window = (i % 2) as u8;
+        let columns = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        // Load the cue into the hidden window
+        let mut load = vec![
+            windows_command(C1CodeSet::DLW, 1 << window),
+            define_window(
+                window,
+                false,
+                lines.len(),
+                columns,
+                &cue.position,
+                POP_ON_WINDOW_STYLE,
+            ),
+        ];
+        load.extend(pen_commands(&cue.style));
+        for (row, line) in lines.iter().enumerate() {
+            load.push(pen_location(row, 0));
+            load.extend(text_commands(line));
+        }
+        events.push(Event {
+            time: cue.start,
+            commands: load,
+        });
+
+        // Swap the windows, or just show the new one
+        let show = match shown {
+            Some(other) => windows_command(C1CodeSet::TGW, (1 << other) | (1 << window)),
+            None => windows_command(C1CodeSet::DSW, 1 << window),
+        };
+        events.push(Event {
+            time: cue.start,
+            commands: vec![show],
+        });
+        shown = Some(window);
+
+        // Hide the cue, unless the next one replaces it
+        if !matches!(cues.get(i + 1), Some((next, _)) if next.start <= cue.end) {
+            events.push(Event {
+                time: cue.end,
+                commands: vec![windows_command(C1CodeSet::HDW, 1 << window)],
+            });
+            shown = None;
+        }
+    }
+    events
+}
+
+/// Events for roll-up captions
+fn roll_up_events(cues: &[Cue], rows: usize) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut is_defined = false;
+    let mut is_cleared = true;
+    for (i, cue) in cues.iter().enumerate() {
+        let mut commands = Vec::new();
+        if !is_defined {
+            commands.push(windows_command(C1CodeSet::DLW, 1));
+            commands.push(define_window(
+                0,
+                true,
+                rows,
+                MAX_COLUMNS,
+                &cue.position,
+                ROLL_UP_WINDOW_STYLE,
+            ));
+            is_defined = true;
+        }
+        commands.extend(pen_commands(&cue.style));
+        for line in cue.lines() {
+            if is_cleared {
+                // Start at the bottom row
+                commands.push(pen_location(rows - 1, 0));
+                is_cleared = false;
+            } else {
+                commands.push(vec![C0CodeSet::CR.code().expect("CR is not reserved")]);
+            }
+            commands.extend(text_commands(&line));
+        }
+        events.push(Event {
+            time: cue.start,
+            commands,
+        });
+
+        if !matches!(cues.get(i + 1), Some(next) if next.start <= cue.end) {
+            events.push(Event {
+                time: cue.end,
+                commands: vec![windows_command(C1CodeSet::CLW, 1)],
+            });
+            is_cleared = true;
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bindings::*, decoder::Dtvcc};
+
+    /// Returns the settings of a decoder for service 1 which writes no captions
+    fn dtvcc_settings(
+        timing: &mut ccx_common_timing_ctx,
+        report: &mut ccx_decoder_dtvcc_report,
+    ) -> ccx_decoder_dtvcc_settings {
+        let mut settings: ccx_decoder_dtvcc_settings = unsafe { std::mem::zeroed() };
+        settings.enabled = 1;
+        settings.services_enabled[0] = 1;
+        settings.active_services_count = 1;
+        settings.timing = timing;
+        settings.report = report;
+        settings.compliance_check = 1;
+        settings.time_scale = 1.0;
+        settings.clip_start = -1;
+        settings.clip_end = -1;
+        settings
+    }
+
+    /// Returns the window id and text of the visible windows of service 1
+    fn visible_windows(dtvcc: &Dtvcc) -> Vec<(usize, String)> {
+        let decoder = match &dtvcc.decoders[0] {
+            Some(decoder) => decoder,
+            None => return Vec::new(),
+        };
+        decoder
+            .windows
+            .iter()
+            .enumerate()
+            .filter(|(_, window)| window.is_defined != 0 && window.visible != 0)
+            .map(|(id, window)| {
+                let text: String = window.rows[..window.row_count as usize]
+                    .iter()
+                    .map(|row| {
+                        row[..window.col_count as usize]
+                            .iter()
+                            .map(|sym| if sym.is_set() { sym.to_char() } else { ' ' })
+                            .collect::<String>()
+                            .trim_end()
+                            .to_owned()
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                (id, text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pop_on_round_trip() {
+        let cues = [
+            Cue::new(1000, 2000, "First"),
+            Cue::new(1500, 1800, ""),
+            Cue::new(3000, 4000, "Second\nline"),
+            Cue::new(4000, 5000, "Third"),
+        ];
+        let frames = Encoder::new(1, CaptionMode::PopOn).unwrap().encode(&cues);
+
+        let mut timing: ccx_common_timing_ctx = unsafe { std::mem::zeroed() };
+        let mut report: ccx_decoder_dtvcc_report = unsafe { std::mem::zeroed() };
+        let mut encoder: encoder_ctx = unsafe { std::mem::zeroed() };
+        let settings = dtvcc_settings(&mut timing, &mut report);
+        let mut dtvcc = Dtvcc::new(&settings);
+        dtvcc.encoder = Some(&mut encoder);
+
+        let mut shown = Vec::new();
+        for frame in &frames {
+            dtvcc.timing.set_fts(frame.fts);
+            for triplet in frame.cc_data.chunks_exact(3) {
+                let cc_valid = (triplet[0] & 4) >> 2;
+                let cc_type = triplet[0] & 3;
+                if cc_type >= 2 && (cc_valid == 1 || cc_type == 3) {
+                    dtvcc.process_cc_data(cc_valid, cc_type, triplet[1], triplet[2]);
+                }
+            }
+            shown.push((frame.fts, visible_windows(&dtvcc)));
+        }
+        let shown_at = |time: i64| {
+            shown
+                .iter()
+                .rev()
+                .find(|(fts, _)| *fts <= time)
+                .map(|(_, windows)| windows.clone())
+                .unwrap_or_default()
+        };
+
+        assert_eq!(shown_at(500), vec![]);
+        assert_eq!(shown_at(1600), vec![(0, "First".to_owned())]);
+        // The empty cue does not keep the first one on screen
+        assert_eq!(shown_at(2500), vec![]);
+        assert_eq!(shown_at(3500), vec![(1, "Second\nline".to_owned())]);
+        assert_eq!(shown_at(4500), vec![(0, "Third".to_owned())]);
+        assert_eq!(shown_at(5500), vec![]);
+        assert_eq!(dtvcc.packet_report.sequence_errors, 0);
+    }
+}
@@ -4,6 +4,7 @@ use tesseract_sys::*;
 #[cfg(feature = "hardsubx_ocr")]
 use leptonica_sys::*;
 
+use std::convert::TryFrom;
 use std::ffi;
 use std::ptr::null;
 
@@ -12,24 +13,52 @@ pub type subdatatype = ::std::os::raw::c_uint;
 pub type subtype = ::std::os::raw::c_uint;
 pub type ccx_encoding_type = ::std::os::raw::c_uint;
 
+use crate::hardsubx::error::HardsubxError;
 use crate::hardsubx::lib_hardsubx_ctx;
 use crate::utils::string_to_c_char;
 
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_int};
 
 use log::warn;
 
+/// How the text of a frame is recognized, with the values of `enum hardsubx_ocr_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcrMode {
+    /// The whole frame at once
+    Frame = 0,
+    /// Word by word, which can also detect italics
+    Word = 1,
+    /// Letter by letter
+    Letter = 2,
+}
+
+impl TryFrom<c_int> for OcrMode {
+    type Error = HardsubxError;
+
+    fn try_from(mode: c_int) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(OcrMode::Frame),
+            1 => Ok(OcrMode::Word),
+            2 => Ok(OcrMode::Letter),
+            _ => Err(HardsubxError::InvalidOcrMode(mode)),
+        }
+    }
+}
+
 /// # Safety
 /// The function accepts and dereferences a raw pointer
 /// The function also makes calls to functions whose safety is not guaranteed
 /// The function returns a raw pointer which is a string made in C
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_simple_threshold(
     ctx: *mut lib_hardsubx_ctx,
     image: *mut Pix,
     threshold: std::os::raw::c_float,
 ) -> *mut ::std::os::raw::c_char {
+    if ctx.is_null() || (*ctx).tess_handle.is_null() || image.is_null() {
+        return null::<c_char>() as *mut c_char;
+    }
     let mut text_out: *mut ::std::os::raw::c_char;
 
     TessBaseAPISetImage2((*ctx).tess_handle, image);
@@ -44,15 +73,11 @@ pub unsafe extern "C" fn get_ocr_text_simple_threshold(
             warn!("Error getting text, skipping frame\n");
         }
 
-        if threshold > 0.0 {
-            // non-zero conf, only then we'll make the call to check for confidence
-            let conf = TessBaseAPIMeanTextConf((*ctx).tess_handle);
-
-            if (conf as std::os::raw::c_float) < threshold {
-                text_out = null::<c_char>() as *mut c_char;
-            } else {
-                (*ctx).cur_conf = conf as std::os::raw::c_float;
-            }
+        let conf = TessBaseAPIMeanTextConf((*ctx).tess_handle) as std::os::raw::c_float;
+        (*ctx).cur_conf = conf;
+        if threshold > 0.0 && conf < threshold {
+            TessDeleteText(text_out);
+            text_out = null::<c_char>() as *mut c_char;
         }
         text_out
     }
@@ -64,7 +89,7 @@ pub unsafe extern "C" fn get_ocr_text_simple_threshold(
 /// The function accepts and dereferences a raw pointer
 /// The function also makes calls to functions whose safety is not guaranteed
 /// The function returns a raw pointer which is a string made in C
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_simple(
     ctx: *mut lib_hardsubx_ctx,
@@ -73,6 +98,15 @@ pub unsafe extern "C" fn get_ocr_text_simple(
     get_ocr_text_simple_threshold(ctx, image, 0.0)
 }
 
+/// Mean of the confidences of `count` words or letters, 0 if none was recognized
+fn mean_confidence(total: f32, count: c_int) -> f32 {
+    if count > 0 {
+        total / count as f32
+    } else {
+        0.0
+    }
+}
+
 /// Function extracts string from tess iterator object
 /// frees memory associated with tesseract string
 /// takes and gives ownership of the string
@@ -102,13 +136,16 @@ unsafe fn _tess_string_helper(it: *mut TessResultIterator, level: TessPageIterat
 /// The function also calls other functions whose safety is not guaranteed
 /// The function returns a raw pointer of a String created in Rust
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_wordwise_threshold(
     ctx: *mut lib_hardsubx_ctx,
     image: *mut Pix,
     threshold: std::os::raw::c_float,
 ) -> *mut ::std::os::raw::c_char {
+    if ctx.is_null() || (*ctx).tess_handle.is_null() || image.is_null() {
+        return null::<c_char>() as *mut c_char;
+    }
     let mut text_out = String::new();
 
     TessBaseAPISetImage2((*ctx).tess_handle, image);
@@ -144,16 +181,12 @@ pub unsafe extern "C" fn get_ocr_text_wordwise_threshold(
                 continue;
             }
 
-            if threshold > 0.0 {
-                // we don't even want to check for confidence if threshold is 0 or less
-                let conf = TessResultIteratorConfidence(it, level);
-                if conf < threshold {
-                    continue;
-                }
-
-                total_conf += conf;
-                num_words += 1;
+            let conf = TessResultIteratorConfidence(it, level);
+            if threshold > 0.0 && conf < threshold {
+                continue;
             }
+            total_conf += conf;
+            num_words += 1;
 
             if (*ctx).detect_italics != 0 {
                 let mut italic: i32 = 0;
@@ -183,10 +216,7 @@ pub unsafe extern "C" fn get_ocr_text_wordwise_threshold(
         }
     }
 
-    if threshold > 0.0 {
-        // any confidence calculation has happened if and only if threshold > 0
-        (*ctx).cur_conf = total_conf / (num_words as std::os::raw::c_float);
-    }
+    (*ctx).cur_conf = mean_confidence(total_conf, num_words);
 
     TessResultIteratorDelete(it);
 
@@ -198,7 +228,7 @@ pub unsafe extern "C" fn get_ocr_text_wordwise_threshold(
 /// The function also calls other functions whose safety is not guaranteed
 /// The function returns a raw pointer of a String created in Rust
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_wordwise(
     ctx: *mut lib_hardsubx_ctx,
@@ -212,13 +242,16 @@ pub unsafe extern "C" fn get_ocr_text_wordwise(
 /// The function also calls other functions whose safety is not guaranteed
 /// The function returns a raw pointer of a String created in Rust
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_letterwise_threshold(
     ctx: *mut lib_hardsubx_ctx,
     image: *mut Pix,
     threshold: std::os::raw::c_float,
 ) -> *mut ::std::os::raw::c_char {
+    if ctx.is_null() || (*ctx).tess_handle.is_null() || image.is_null() {
+        return null::<c_char>() as *mut c_char;
+    }
     let mut text_out: String = String::new();
 
     TessBaseAPISetImage2((*ctx).tess_handle, image);
@@ -247,23 +280,16 @@ pub unsafe extern "C" fn get_ocr_text_letterwise_threshold(
             let letter = _tess_string_helper(it, level);
             text_out = format!("{}{}", text_out, letter);
 
-            if threshold > 0.0 {
-                // we don't even want to bother with this call if threshold is 0 or less
-                let conf: std::os::raw::c_float = TessResultIteratorConfidence(it, level);
-                if conf < threshold {
-                    continue;
-                }
-
-                total_conf += conf;
-                num_characters += 1;
+            let conf: std::os::raw::c_float = TessResultIteratorConfidence(it, level);
+            if threshold > 0.0 && conf < threshold {
+                continue;
             }
+            total_conf += conf;
+            num_characters += 1;
         }
     }
 
-    if threshold > 0.0 {
-        // No confidence calculation has been done if threshold is 0 or less
-        (*ctx).cur_conf = total_conf / (num_characters as std::os::raw::c_float);
-    }
+    (*ctx).cur_conf = mean_confidence(total_conf, num_characters);
 
     TessResultIteratorDelete(it);
 
@@ -275,7 +301,7 @@ pub unsafe extern "C" fn get_ocr_text_letterwise_threshold(
 /// The function also calls other functions whose safety is not guaranteed
 /// The function returns a raw pointer of a String created in Rust
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
-/// ctx should be not null
+/// Returns null if ctx, its Tesseract handle or image is null
 #[no_mangle]
 pub unsafe extern "C" fn get_ocr_text_letterwise(
     ctx: *mut lib_hardsubx_ctx,
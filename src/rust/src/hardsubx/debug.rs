@@ -0,0 +1,116 @@
+//! Images of the processing stages of a frame, written with `-debug_dir` to tune the thresholds
+
+#[cfg(feature = "hardsubx_ocr")]
+use leptonica_sys::*;
+
+use std::convert::TryInto;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::c_int;
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use log::warn;
+
+use crate::hardsubx::imgops::{clip_pix, pix_from_rows};
+use crate::hardsubx::lib_hardsubx_ctx;
+use crate::hardsubx::roi::Rect;
+use crate::hardsubx::utility::convert_pts_to_ms;
+
+/// Writes the stages of one frame as PNG files, named after the frame number, time and stage
+pub struct StageDump {
+    dir: PathBuf,
+    index: c_int,
+    time_ms: i64,
+    clip: Option<Rect>,
+}
+
+impl StageDump {
+    /// Returns `None` if no debug directory is set or it can not be created
+    ///
+    /// The images are clipped to `clip`, usually the bounds of the searched region.
+    ///
+    /// # Safety
+    /// `ctx` must be a valid pointer
+    pub unsafe fn new(
+        ctx: *const lib_hardsubx_ctx,
+        index: c_int,
+        clip: Option<Rect>,
+    ) -> Option<StageDump> {
+        if (*ctx).debug_dir.is_null() {
+            return None;
+        }
+        let dir = PathBuf::from(
+            CStr::from_ptr((*ctx).debug_dir)
+                .to_string_lossy()
+                .into_owned(),
+        );
+        if let Err(err) = fs::create_dir_all(&dir) {
+            warn!("Unable to create {}: {}", dir.display(), err);
+            return None;
+        }
+        Some(StageDump {
+            dir,
+            index,
+            time_ms: frame_time_ms(ctx),
+            clip,
+        })
+    }
+
+    /// Writes one stage, `pix` stays owned by the caller
+    ///
+    /// # Safety
+    /// `pix` must be null or a valid Pix
+    pub unsafe fn write(&self, stage: &str, pix: *mut Pix) {
+        if pix.is_null() {
+            return;
+        }
+        let path = self.dir.join(format!(
+            "frame{:06}_{}ms_{}.png",
+            self.index, self.time_ms, stage
+        ));
+        let path_c = match CString::new(path.to_string_lossy().into_owned()) {
+            Ok(path_c) => path_c,
+            Err(_) => return,
+        };
+        let mut clipped = match &self.clip {
+            Some(clip) => clip_pix(pix, clip),
+            None => null_mut(),
+        };
+        let image = if clipped.is_null() { pix } else { clipped };
+        if pixWrite(path_c.as_ptr(), image, IFF_PNG.try_into().unwrap()) != 0 {
+            warn!("Unable to write {}", path.display());
+        }
+        pixDestroy(&mut clipped);
+    }
+
+    /// Writes one stage held as the rows of a 32 bpp image
+    ///
+    /// # Safety
+    /// `rows` must hold `width` x `height` pixels
+    pub unsafe fn write_rows(&self, stage: &str, rows: &[u32], width: c_int, height: c_int) {
+        let mut pix = pix_from_rows(rows, width, height);
+        self.write(stage, pix);
+        pixDestroy(&mut pix);
+    }
+}
+
+/// Time of the current packet in ms, 0 if the stream is not known
+///
+/// # Safety
+/// `ctx` must be a valid pointer
+unsafe fn frame_time_ms(ctx: *const lib_hardsubx_ctx) -> i64 {
+    let format_ctx = (*ctx).format_ctx;
+    if format_ctx.is_null()
+        || (*format_ctx).streams.is_null()
+        || (*ctx).video_stream_id < 0
+        || (*ctx).video_stream_id as u32 >= (*format_ctx).nb_streams
+    {
+        return 0;
+    }
+    let stream = *(*format_ctx).streams.add((*ctx).video_stream_id as usize);
+    if stream.is_null() {
+        return 0;
+    }
+    convert_pts_to_ms((*ctx).packet.pts, (*stream).time_base)
+}
@@ -1,72 +1,142 @@
 #[cfg(feature = "hardsubx_ocr")]
 use leptonica_sys::*;
+#[cfg(feature = "hardsubx_ocr")]
+use tesseract_sys::TessDeleteText;
 
-// #[cfg(feature = "hardsubx_ocr")]
-// use rsmpeg::*;
-
-use std::convert::TryInto;
-use std::eprintln;
+use std::convert::{TryFrom, TryInto};
 use std::ffi;
-use std::format;
-use std::os::raw::c_char;
-use std::process::exit;
-use std::ptr::null;
+use std::os::raw::{c_char, c_int};
+use std::ptr::{null, null_mut};
+
+use log::warn;
 
 #[cfg(feature = "hardsubx_ocr")]
-// use crate::bindings::{hardsubx_ocr_mode_HARDSUBX_OCRMODE_WORD};
 use crate::bindings::AVFrame;
 use crate::hardsubx::classifier::*;
-use crate::hardsubx::imgops::{rgb_to_hsv, rgb_to_lab};
+use crate::hardsubx::debug::StageDump;
+use crate::hardsubx::error::HardsubxError;
+use crate::hardsubx::gate::change_gate;
+use crate::hardsubx::imgops::{
+    clip_pix, mask_rows, par_rows, pix_from_rows, read_frame, ColorKey, ColorTable, PixRows, WHITE,
+};
+use crate::hardsubx::layout::{self, FrameLayout};
+use crate::hardsubx::letterbox;
 use crate::hardsubx::lib_hardsubx_ctx;
+use crate::hardsubx::roi::{Rect, Region};
 use crate::utils::string_to_c_char;
 
-static EXIT_MALFORMED_PARAMETER: i32 = 7;
-
-// TODO: turn into an enum definition when the hardsubx context is rewritten
-// static HARDSUBX_OCRMODE_FRAME: i32 = 0;
-static HARDSUBX_OCRMODE_WORD: i32 = 1;
-// static HARDSUBX_OCRMODE_LETTER: i32 = 2;
+/// Recognizes the text of `im` with the OCR mode and confidence threshold of `ctx`
+///
+/// Frames which could not be recognized, or only below the threshold, give an empty text.
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, and `im` null or a valid image
+pub unsafe fn dispatch_classifier_functions(
+    ctx: *mut lib_hardsubx_ctx,
+    im: *mut Pix,
+) -> Result<String, HardsubxError> {
+    if ctx.is_null() {
+        return Err(HardsubxError::NullPointer("ctx"));
+    }
+    if im.is_null() {
+        return Err(HardsubxError::NullPointer("image"));
+    }
 
-// enum hardsubx_ocr_mode {
-//     HARDSUBX_OCRMODE_FRAME,
-//     HARDSUBX_OCRMODE_WORD,
-//     HARDSUBX_OCRMODE_LETTER
-// };
+    let threshold = (*ctx).conf_thresh;
+    let text = match OcrMode::try_from((*ctx).ocr_mode)? {
+        OcrMode::Frame => take_tess_text(get_ocr_text_simple_threshold(ctx, im, threshold)),
+        OcrMode::Word => take_rust_text(get_ocr_text_wordwise_threshold(ctx, im, threshold)),
+        OcrMode::Letter => take_rust_text(get_ocr_text_letterwise_threshold(ctx, im, threshold)),
+    };
+    Ok(text)
+}
 
+/// Recognizes the text of `feat_im`, `bounds` is the part of the frame searched for it
+///
+/// If positioned subtitles, confidence comments or the OCR JSON file are wanted, only the part of
+/// the frame inside `bounds` is recognized, and the words are kept with their boxes in frame
+/// coordinates and their confidence. Otherwise the whole frame is recognized.
+///
 /// # Safety
-/// dereferences a raw pointer
-/// calls functions that are not necessarily safe
-pub unsafe fn dispatch_classifier_functions(ctx: *mut lib_hardsubx_ctx, im: *mut Pix) -> String {
-    // function that calls the classifier functions
-    match (*ctx).ocr_mode {
-        0 => {
-            let ret_char_arr = get_ocr_text_wordwise_threshold(ctx, im, (*ctx).conf_thresh);
-            ffi::CStr::from_ptr(ret_char_arr)
-                .to_string_lossy()
-                .into_owned()
-        }
-        1 => {
-            let ret_char_arr = get_ocr_text_letterwise_threshold(ctx, im, (*ctx).conf_thresh);
-            let text_out_result = ffi::CString::from_raw(ret_char_arr).into_string();
-            match text_out_result {
-                Ok(T) => T,
-                Err(_E) => "".to_string(),
-            }
-        }
+/// `ctx` must be a valid pointer and `feat_im` a valid image of the `width` x `height` frame
+unsafe fn recognize(
+    ctx: *mut lib_hardsubx_ctx,
+    feat_im: *mut Pix,
+    bounds: Option<Rect>,
+    width: usize,
+    height: usize,
+) -> Result<String, HardsubxError> {
+    let wants_words = layout::wants_words(ctx);
+    let mut clipped = match bounds.as_ref().filter(|_| wants_words) {
+        Some(bounds) => clip_pix(feat_im, bounds),
+        None => null_mut(),
+    };
+    let (image, offset) = match bounds {
+        Some(bounds) if !clipped.is_null() => (clipped, (bounds.left, bounds.top)),
+        _ => (feat_im, (0, 0)),
+    };
+    let text = dispatch_classifier_functions(ctx, image);
+    if text.is_ok() && wants_words {
+        let words = layout::read_words((*ctx).tess_handle, (*ctx).conf_thresh, offset);
+        layout::record_layout(
+            ctx,
+            FrameLayout {
+                frame_width: width,
+                frame_height: height,
+                confidence: (*ctx).cur_conf,
+                words,
+            },
+        );
+    }
+    pixDestroy(&mut clipped);
+    text
+}
 
-        2 => {
-            let ret_char_arr = get_ocr_text_simple_threshold(ctx, im, (*ctx).conf_thresh);
-            let text_out_result = ffi::CString::from_raw(ret_char_arr).into_string();
-            match text_out_result {
-                Ok(T) => T,
-                Err(_E) => "".to_string(),
-            }
-        }
+/// Takes a text allocated by Tesseract, empty for null
+unsafe fn take_tess_text(text: *mut c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    let owned = ffi::CStr::from_ptr(text).to_string_lossy().into_owned();
+    TessDeleteText(text);
+    owned
+}
+
+/// Takes a text allocated by [string_to_c_char], empty for null
+unsafe fn take_rust_text(text: *mut c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    ffi::CString::from_raw(text)
+        .into_string()
+        .unwrap_or_default()
+}
+
+/// Checks the arguments the frame processing functions get from C
+unsafe fn check_frame(
+    ctx: *const lib_hardsubx_ctx,
+    frame: *const AVFrame,
+    width: c_int,
+    height: c_int,
+) -> Result<(), HardsubxError> {
+    if ctx.is_null() {
+        Err(HardsubxError::NullPointer("ctx"))
+    } else if frame.is_null() || (*frame).data[0].is_null() {
+        Err(HardsubxError::NullPointer("frame"))
+    } else if width <= 0 || height <= 0 {
+        Err(HardsubxError::InvalidFrameSize { width, height })
+    } else {
+        Ok(())
+    }
+}
 
-        _ => {
-            eprintln!("Invalid OCR Mode");
-            exit(EXIT_MALFORMED_PARAMETER);
-            // "".to_string()
+/// Hands a recognized text to C, or logs the error and returns null
+unsafe fn text_to_c(text: Result<String, HardsubxError>) -> *mut c_char {
+    match text {
+        Ok(text) => string_to_c_char(&text),
+        Err(err) => {
+            warn!("{}, skipping frame", err);
+            null_mut()
         }
     }
 }
@@ -74,7 +144,7 @@ pub unsafe fn dispatch_classifier_functions(ctx: *mut lib_hardsubx_ctx, im: *mut
 /// # Safety
 /// The function dereferences a raw pointer
 /// The function also calls other functions whose safety is not guaranteed
-/// The function returns a raw pointer of a String created in Rust
+/// The function returns a raw pointer of a String created in Rust, or null on errors
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
 #[no_mangle]
 pub unsafe extern "C" fn _process_frame_white_basic(
@@ -82,79 +152,89 @@ pub unsafe extern "C" fn _process_frame_white_basic(
     frame: *mut AVFrame,
     width: ::std::os::raw::c_int,
     height: ::std::os::raw::c_int,
-    _index: ::std::os::raw::c_int,
+    index: ::std::os::raw::c_int,
 ) -> *mut ::std::os::raw::c_char {
-    let mut im: *mut Pix = pixCreate(width, height, 32);
-    let mut lum_im: *mut Pix = pixCreate(width, height, 32);
-    let frame_deref = *frame;
-
-    for i in (3 * height / 4)..height {
-        for j in 0..width {
-            let p: isize = (j * 3 + i * frame_deref.linesize[0]).try_into().unwrap();
-            let r: i32 = (*(frame_deref.data[0]).offset(p)).into();
-            let g: i32 = (*(frame_deref.data[0]).offset(p + 1)).into();
-            let b: i32 = (*(frame_deref.data[0]).offset(p + 2)).into();
-            pixSetRGBPixel(im, j, i, r, g, b);
-
-            let mut L: f32 = 0.0;
-            let mut A: f32 = 0.0;
-            let mut B: f32 = 0.0;
-
-            rgb_to_lab(r as f32, g as f32, b as f32, &mut L, &mut A, &mut B);
-
-            if L > (*ctx).lum_thresh {
-                pixSetRGBPixel(lum_im, j, i, 255, 255, 255);
-            } else {
-                pixSetRGBPixel(lum_im, j, i, 0, 0, 0);
-            }
-        }
-    }
+    text_to_c(process_frame_white_basic(ctx, frame, width, height, index))
+}
 
+/// Recognizes white subtitles in an RGB24 frame
+///
+/// # Safety
+/// `ctx` and `frame` must be null or valid pointers, and `frame` must be `width` x `height`
+pub unsafe fn process_frame_white_basic(
+    ctx: *mut lib_hardsubx_ctx,
+    frame: *mut AVFrame,
+    width: c_int,
+    height: c_int,
+    index: c_int,
+) -> Result<String, HardsubxError> {
+    check_frame(ctx, frame, width, height)?;
+    let (w, h) = (width as usize, height as usize);
+    letterbox::update(ctx, &*frame, w, h);
+    let region = Region::from_ctx(ctx, w, h, |h| 3 * h / 4);
+    let feat_region = Region::from_ctx(ctx, w, h, |h| 3 * (h / 4));
+    let rgb_rows = read_frame(&*frame, w, h, &region);
+    let lum_table = ColorTable::get(ColorKey::Luminance((*ctx).lum_thresh));
+    let lum_rows = mask_rows(&rgb_rows, w, &region, &lum_table, true);
+
+    let mut im: *mut Pix = pix_from_rows(&rgb_rows, width, height);
     let mut gray_im: *mut Pix = pixConvertRGBToGray(im, 0.0, 0.0, 0.0);
     let mut sobel_edge_im: *mut Pix =
         pixSobelEdgeFilter(gray_im, L_VERTICAL_EDGES.try_into().unwrap());
+    if let Some(active) = letterbox::active_area(ctx, w, h) {
+        letterbox::clear_bar_edges(sobel_edge_im, &active);
+    }
     let mut dilate_gray_im: *mut Pix = pixDilateGray(sobel_edge_im, 21, 1);
     let mut edge_im: *mut Pix = pixThresholdToBinary(dilate_gray_im, 50);
 
-    let mut feat_im: *mut Pix = pixCreate(width, height, 32);
-
-    for i in (3 * (height / 4))..height {
-        for j in 0..width {
-            let mut p1: u32 = 0;
-            let mut p2: u32 = 0;
-
-            pixGetPixel(edge_im, j, i, &mut p1);
-            pixGetPixel(lum_im, j, i, &mut p2);
-
-            if p1 == 0 && p2 > 0 {
-                pixSetRGBPixel(feat_im, j, i, 255, 255, 255);
-            } else {
-                pixSetRGBPixel(feat_im, j, i, 0, 0, 0);
+    let edges = PixRows::new(edge_im);
+    let mut feat_rows = vec![0; w * h];
+    par_rows(&mut feat_rows, w, feat_region.rows(), |i, row| {
+        for j in feat_region.spans(i).flatten() {
+            if edges.bit(i, j) == 0 && lum_rows[i * w + j] > 0 {
+                row[j] = WHITE;
             }
         }
+    });
+    let mut feat_im: *mut Pix = pix_from_rows(&feat_rows, width, height);
+
+    if let Some(dump) = StageDump::new(ctx, index, region.bounds()) {
+        dump.write("rgb", im);
+        dump.write_rows("lum", &lum_rows, width, height);
+        dump.write("sobel", sobel_edge_im);
+        dump.write("dilate", dilate_gray_im);
+        dump.write("edges", edge_im);
+        dump.write("feat", feat_im);
     }
 
     if (*ctx).detect_italics != 0 {
-        (*ctx).ocr_mode = HARDSUBX_OCRMODE_WORD;
+        (*ctx).ocr_mode = OcrMode::Word as c_int;
     }
 
-    let subtitle_text = dispatch_classifier_functions(ctx, feat_im);
+    let gate_text =
+        change_gate(ctx).unchanged_text(&feat_rows, w, feat_region.bounds(), (*ctx).change_thresh);
+    let subtitle_text = match gate_text {
+        Some(text) => Ok(text),
+        None => recognize(ctx, feat_im, feat_region.bounds(), w, h).map(|text| {
+            change_gate(ctx).record(&text);
+            text
+        }),
+    };
 
     pixDestroy(&mut im as *mut *mut Pix);
     pixDestroy(&mut gray_im as *mut *mut Pix);
     pixDestroy(&mut sobel_edge_im as *mut *mut Pix);
     pixDestroy(&mut dilate_gray_im as *mut *mut Pix);
     pixDestroy(&mut edge_im as *mut *mut Pix);
-    pixDestroy(&mut lum_im as *mut *mut Pix);
     pixDestroy(&mut feat_im as *mut *mut Pix);
 
-    string_to_c_char(&subtitle_text)
+    subtitle_text
 }
 
 /// # Safety
 /// The function dereferences a raw pointer
 /// The function also calls other functions whose safety is not guaranteed
-/// The function returns a raw pointer of a String created in Rust
+/// The function returns a raw pointer of a String created in Rust, or null on errors
 /// This has to be deallocated at some point using from_raw() lest it be a memory leak
 #[no_mangle]
 pub unsafe extern "C" fn _process_frame_color_basic(
@@ -162,35 +242,40 @@ pub unsafe extern "C" fn _process_frame_color_basic(
     frame: *mut AVFrame,
     width: ::std::os::raw::c_int,
     height: ::std::os::raw::c_int,
-    _index: ::std::os::raw::c_int,
+    index: ::std::os::raw::c_int,
 ) -> *mut ::std::os::raw::c_char {
-    let mut im: *mut Pix = pixCreate(width, height, 32);
-    let mut hue_im: *mut Pix = pixCreate(width, height, 32);
-    let frame_deref = *frame;
-
-    for i in 0..height {
-        for j in 0..width {
-            let p: isize = (j * 3 + i * frame_deref.linesize[0]).try_into().unwrap();
-            let r: i32 = (*(frame_deref.data[0]).offset(p)).into();
-            let g: i32 = (*(frame_deref.data[0]).offset(p + 1)).into();
-            let b: i32 = (*(frame_deref.data[0]).offset(p + 2)).into();
-            pixSetRGBPixel(im, j, i, r, g, b);
-
-            let mut H: f32 = 0.0;
-            let mut S: f32 = 0.0;
-            let mut V: f32 = 0.0;
-
-            rgb_to_hsv(r as f32, g as f32, b as f32, &mut H, &mut S, &mut V);
-
-            if ((H - (*ctx).hue).abs()) < 20.0 {
-                pixSetRGBPixel(hue_im, j, i, r, g, b);
-            }
-        }
-    }
+    text_to_c(process_frame_color_basic(ctx, frame, width, height, index))
+}
+
+/// Recognizes subtitles of the configured hue in an RGB24 frame
+///
+/// # Safety
+/// `ctx` and `frame` must be null or valid pointers, and `frame` must be `width` x `height`
+pub unsafe fn process_frame_color_basic(
+    ctx: *mut lib_hardsubx_ctx,
+    frame: *mut AVFrame,
+    width: c_int,
+    height: c_int,
+    index: c_int,
+) -> Result<String, HardsubxError> {
+    check_frame(ctx, frame, width, height)?;
+    let (w, h) = (width as usize, height as usize);
+    letterbox::update(ctx, &*frame, w, h);
+    let region = Region::from_ctx(ctx, w, h, |_| 0);
+    let feat_region = Region::from_ctx(ctx, w, h, |h| 3 * (h / 4));
+    let rgb_rows = read_frame(&*frame, w, h, &region);
+    let hue_table = ColorTable::get(ColorKey::Hue((*ctx).hue));
+    let hue_rows = mask_rows(&rgb_rows, w, &region, &hue_table, false);
+
+    let mut im: *mut Pix = pix_from_rows(&rgb_rows, width, height);
+    let mut hue_im: *mut Pix = pix_from_rows(&hue_rows, width, height);
 
     let mut gray_im: *mut Pix = pixConvertRGBToGray(im, 0.0, 0.0, 0.0);
     let mut sobel_edge_im: *mut Pix =
         pixSobelEdgeFilter(gray_im, L_VERTICAL_EDGES.try_into().unwrap());
+    if let Some(active) = letterbox::active_area(ctx, w, h) {
+        letterbox::clear_bar_edges(sobel_edge_im, &active);
+    }
     let mut dilate_gray_im: *mut Pix = pixDilateGray(sobel_edge_im, 21, 1);
     let mut edge_im: *mut Pix = pixThresholdToBinary(dilate_gray_im, 50);
 
@@ -209,29 +294,43 @@ pub unsafe extern "C" fn _process_frame_color_basic(
         &mut pixd,
     );
 
-    let mut feat_im: *mut Pix = pixCreate(width, height, 32);
-
-    for i in (3 * (height / 4))..height {
-        for j in 0..width {
-            let mut p1: u32 = 0;
-            let mut p2: u32 = 0;
-            let mut p3: u32 = 0;
-
-            pixGetPixel(edge_im, j, i, &mut p1);
-            pixGetPixel(pixd, j, i, &mut p2);
-            pixGetPixel(edge_im_2, j, i, &mut p3);
-
-            if p1 == 0 && p2 == 0 && p3 > 0 {
-                pixSetRGBPixel(feat_im, j, i, 255, 255, 255);
+    let edges = PixRows::new(edge_im);
+    let binary = PixRows::new(pixd);
+    let hue_edges = PixRows::new(edge_im_2);
+    let mut feat_rows = vec![0; w * h];
+    par_rows(&mut feat_rows, w, feat_region.rows(), |i, row| {
+        for j in feat_region.spans(i).flatten() {
+            if edges.bit(i, j) == 0 && binary.bit(i, j) == 0 && hue_edges.byte(i, j) > 0 {
+                row[j] = WHITE;
             }
         }
+    });
+    let mut feat_im: *mut Pix = pix_from_rows(&feat_rows, width, height);
+
+    if let Some(dump) = StageDump::new(ctx, index, region.bounds()) {
+        dump.write("rgb", im);
+        dump.write("hue", hue_im);
+        dump.write("sobel", sobel_edge_im);
+        dump.write("dilate", dilate_gray_im);
+        dump.write("edges", edge_im);
+        dump.write("hue_dilate", edge_im_2);
+        dump.write("sauvola", pixd);
+        dump.write("feat", feat_im);
     }
 
     if (*ctx).detect_italics != 0 {
-        (*ctx).ocr_mode = HARDSUBX_OCRMODE_WORD;
+        (*ctx).ocr_mode = OcrMode::Word as c_int;
     }
 
-    let subtitle_text = dispatch_classifier_functions(ctx, feat_im);
+    let gate_text =
+        change_gate(ctx).unchanged_text(&feat_rows, w, feat_region.bounds(), (*ctx).change_thresh);
+    let subtitle_text = match gate_text {
+        Some(text) => Ok(text),
+        None => recognize(ctx, feat_im, feat_region.bounds(), w, h).map(|text| {
+            change_gate(ctx).record(&text);
+            text
+        }),
+    };
 
     pixDestroy(&mut im as *mut *mut Pix);
     pixDestroy(&mut hue_im as *mut *mut Pix);
@@ -244,14 +343,13 @@ pub unsafe extern "C" fn _process_frame_color_basic(
     pixDestroy(&mut pixd as *mut *mut Pix);
     pixDestroy(&mut feat_im as *mut *mut Pix);
 
-    // This is a memory leak
-    // the returned thing needs to be deallocated by caller
-    string_to_c_char(&subtitle_text)
+    subtitle_text
 }
+
 /// # Safety
 /// The function accepts and dereferences a raw pointer
 /// The function also makes calls to functions whose safety is not guaranteed
-/// The function returns a raw pointer which is a string made in C
+/// The function returns a raw pointer which is a string made in C, or null on errors
 #[no_mangle]
 pub unsafe extern "C" fn _process_frame_tickertext(
     ctx: *mut lib_hardsubx_ctx,
@@ -260,68 +358,51 @@ pub unsafe extern "C" fn _process_frame_tickertext(
     height: ::std::os::raw::c_int,
     index: ::std::os::raw::c_int,
 ) -> *mut ::std::os::raw::c_char {
-    let mut im: *mut Pix = pixCreate(width, height, 32);
-    let mut lum_im: *mut Pix = pixCreate(width, height, 32);
-    let frame_deref = *frame;
-
-    for i in ((92 * height) / 100)..height {
-        for j in 0..width {
-            let p: isize = (j * 3 + i * frame_deref.linesize[0]).try_into().unwrap();
-            let r: i32 = (*(frame_deref.data[0]).offset(p)).into();
-            let g: i32 = (*(frame_deref.data[0]).offset(p + 1)).into();
-            let b: i32 = (*(frame_deref.data[0]).offset(p + 2)).into();
-            pixSetRGBPixel(im, j, i, r, g, b);
-
-            let mut L: f32 = 0.0;
-            let mut A: f32 = 0.0;
-            let mut B: f32 = 0.0;
-
-            rgb_to_lab(r as f32, g as f32, b as f32, &mut L, &mut A, &mut B);
-
-            if L > (*ctx).lum_thresh {
-                pixSetRGBPixel(lum_im, j, i, 255, 255, 255);
-            } else {
-                pixSetRGBPixel(lum_im, j, i, 0, 0, 0);
-            }
-        }
+    if let Err(err) = check_frame(ctx, frame, width, height) {
+        warn!("{}, skipping frame", err);
+        return null_mut();
     }
-
+    let (w, h) = (width as usize, height as usize);
+    letterbox::update(ctx, &*frame, w, h);
+    let region = Region::from_ctx(ctx, w, h, |h| (92 * h) / 100);
+    let feat_region = Region::from_ctx(ctx, w, h, |h| 92 * (h / 100));
+    let rgb_rows = read_frame(&*frame, w, h, &region);
+    let lum_table = ColorTable::get(ColorKey::Luminance((*ctx).lum_thresh));
+    let lum_rows = mask_rows(&rgb_rows, w, &region, &lum_table, true);
+
+    let mut im: *mut Pix = pix_from_rows(&rgb_rows, width, height);
+    let mut lum_im: *mut Pix = pix_from_rows(&lum_rows, width, height);
     let mut gray_im: *mut Pix = pixConvertRGBToGray(im, 0.0, 0.0, 0.0);
     let mut sobel_edge_im: *mut Pix =
         pixSobelEdgeFilter(gray_im, L_VERTICAL_EDGES.try_into().unwrap());
+    if let Some(active) = letterbox::active_area(ctx, w, h) {
+        letterbox::clear_bar_edges(sobel_edge_im, &active);
+    }
     let mut dilate_gray_im: *mut Pix = pixDilateGray(sobel_edge_im, 21, 11);
     let mut edge_im: *mut Pix = pixThresholdToBinary(dilate_gray_im, 50);
 
-    let mut feat_im: *mut Pix = pixCreate(width, height, 32);
-
-    for i in (92 * (height / 100))..height {
-        for j in 0..width {
-            let mut p1: u32 = 0;
-            let mut p2: u32 = 0;
-
-            pixGetPixel(edge_im, j, i, &mut p1);
-            pixGetPixel(lum_im, j, i, &mut p2);
-
-            if p1 == 0 && p2 > 0 {
-                pixSetRGBPixel(feat_im, j, i, 255, 255, 255);
-            } else {
-                pixSetRGBPixel(feat_im, j, i, 0, 0, 0);
+    let edges = PixRows::new(edge_im);
+    let mut feat_rows = vec![0; w * h];
+    par_rows(&mut feat_rows, w, feat_region.rows(), |i, row| {
+        for j in feat_region.spans(i).flatten() {
+            if edges.bit(i, j) == 0 && lum_rows[i * w + j] > 0 {
+                row[j] = WHITE;
             }
         }
+    });
+    let mut feat_im: *mut Pix = pix_from_rows(&feat_rows, width, height);
+
+    if let Some(dump) = StageDump::new(ctx, index, region.bounds()) {
+        dump.write("rgb", im);
+        dump.write("lum", lum_im);
+        dump.write("sobel", sobel_edge_im);
+        dump.write("dilate", dilate_gray_im);
+        dump.write("edges", edge_im);
+        dump.write("feat", feat_im);
     }
 
     let subtitle_text = get_ocr_text_simple_threshold(ctx, lum_im, 0.0);
 
-    let write_path: String = format!("./lum_im{}.jpg", index);
-    let write_path_c: *mut c_char = string_to_c_char(&write_path);
-    pixWrite(write_path_c, lum_im, IFF_JFIF_JPEG.try_into().unwrap());
-    let _dealloc = std::ffi::CString::from_raw(write_path_c); // for memory reasons
-
-    let write_path: String = format!("./im{}.jpg", index);
-    let write_path_c: *mut c_char = string_to_c_char(&write_path);
-    pixWrite(write_path_c, lum_im, IFF_JFIF_JPEG.try_into().unwrap());
-    let _dealloc = std::ffi::CString::from_raw(write_path_c); // for memory reasons
-
     pixDestroy(&mut im as *mut *mut Pix);
     pixDestroy(&mut gray_im as *mut *mut Pix);
     pixDestroy(&mut sobel_edge_im as *mut *mut Pix);
@@ -0,0 +1,39 @@
+//! Errors of the burned-in subtitle extraction
+//!
+//! They are returned to the Rust callers. At the C boundary they are logged, and the functions
+//! return null or -1 instead, so a bad parameter never ends the process.
+
+use std::error::Error;
+use std::fmt;
+use std::os::raw::c_int;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HardsubxError {
+    /// A pointer passed in from C was null, named by the argument
+    NullPointer(&'static str),
+    /// `ocr_mode` is none of the values of [OcrMode][super::classifier::OcrMode]
+    InvalidOcrMode(c_int),
+    /// The frame has no pixels
+    InvalidFrameSize { width: c_int, height: c_int },
+    /// Tesseract could not be set up
+    OcrInit(String),
+}
+
+impl fmt::Display for HardsubxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HardsubxError::NullPointer(name) => write!(f, "{} is null", name),
+            HardsubxError::InvalidOcrMode(mode) => write!(
+                f,
+                "Invalid OCR mode {}, valid modes are 0 (frame), 1 (word) and 2 (letter)",
+                mode
+            ),
+            HardsubxError::InvalidFrameSize { width, height } => {
+                write!(f, "Invalid frame size {}x{}", width, height)
+            }
+            HardsubxError::OcrInit(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for HardsubxError {}
@@ -0,0 +1,120 @@
+//! Skipping OCR of frames whose subtitle region did not change
+//!
+//! Subtitles stay on screen for many sampled frames. The feature image of each frame is compared
+//! with the one of the last frame which was recognized, and as long as only a few of its text
+//! pixels differ, the text of that frame is used again. The caller then sees the same text and
+//! extends the end time of the subtitle.
+
+use std::os::raw::{c_char, c_ulonglong, c_void};
+
+use crate::bindings::mprint;
+use crate::hardsubx::imgops::par_rows;
+use crate::hardsubx::lib_hardsubx_ctx;
+use crate::hardsubx::roi::Rect;
+
+/// Feature image and text of the last recognized frame
+#[derive(Debug, Default)]
+pub struct ChangeGate {
+    /// One bit per pixel of `bounds`, set for text pixels
+    bits: Vec<u64>,
+    bounds: Option<Rect>,
+    text: Option<String>,
+    recognized: u64,
+    skipped: u64,
+}
+
+impl ChangeGate {
+    /// Returns the text of the last recognized frame if the feature image barely changed
+    ///
+    /// Otherwise the feature image is kept for the next frames, and the text must be given with
+    /// [ChangeGate::record] once it is recognized. `threshold` is the changed part of the text
+    /// pixels in percent at which a frame is recognized again, 0 to recognize every frame.
+    pub fn unchanged_text(
+        &mut self,
+        feat_rows: &[u32],
+        width: usize,
+        bounds: Option<Rect>,
+        threshold: f32,
+    ) -> Option<String> {
+        let bits = feature_bits(feat_rows, width, bounds);
+        if threshold > 0.0 && bounds == self.bounds {
+            if let Some(text) = &self.text {
+                let changed: u32 = bits
+                    .iter()
+                    .zip(&self.bits)
+                    .map(|(a, b)| (a ^ b).count_ones())
+                    .sum();
+                let text_pixels = count_ones(&bits).max(count_ones(&self.bits)).max(1);
+                if (changed as f32) * 100.0 < threshold * text_pixels as f32 {
+                    self.skipped += 1;
+                    return Some(text.clone());
+                }
+            }
+        }
+        self.bits = bits;
+        self.bounds = bounds;
+        self.text = None;
+        None
+    }
+
+    /// Stores the text recognized for the frame last passed to [ChangeGate::unchanged_text]
+    pub fn record(&mut self, text: &str) {
+        self.recognized += 1;
+        self.text = Some(text.to_owned());
+    }
+}
+
+fn count_ones(bits: &[u64]) -> u32 {
+    bits.iter().map(|word| word.count_ones()).sum()
+}
+
+/// Packs the pixels of `bounds` of a feature image into bits, rows start at a new word
+fn feature_bits(feat_rows: &[u32], width: usize, bounds: Option<Rect>) -> Vec<u64> {
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => return Vec::new(),
+    };
+    let words_per_row = (bounds.width() + 63) / 64;
+    let mut bits = vec![0; words_per_row * bounds.height()];
+    par_rows(&mut bits, words_per_row, 0..bounds.height(), |i, row| {
+        let line = &feat_rows[(bounds.top + i) * width..(bounds.top + i + 1) * width];
+        for (n, &pixel) in line[bounds.columns()].iter().enumerate() {
+            if pixel != 0 {
+                row[n / 64] |= 1 << (n % 64);
+            }
+        }
+    });
+    bits
+}
+
+/// Returns the change gate of `ctx`, creating it on first use
+///
+/// # Safety
+/// `ctx` must be a valid pointer, whose `change_gate` is null or was created here
+pub unsafe fn change_gate<'a>(ctx: *mut lib_hardsubx_ctx) -> &'a mut ChangeGate {
+    if (*ctx).change_gate.is_null() {
+        (*ctx).change_gate = Box::into_raw(Box::new(ChangeGate::default())) as *mut c_void;
+    }
+    &mut *((*ctx).change_gate as *mut ChangeGate)
+}
+
+/// Prints how many frames were recognized and frees the change gate of `ctx`
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, whose `change_gate` is null or was created by Rust
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_free_change_gate(ctx: *mut lib_hardsubx_ctx) {
+    if ctx.is_null() || (*ctx).change_gate.is_null() {
+        return;
+    }
+    let gate = Box::from_raw((*ctx).change_gate as *mut ChangeGate);
+    (*ctx).change_gate = std::ptr::null_mut();
+    if gate.skipped > 0 {
+        mprint(
+            "Unchanged subtitle region : OCR skipped for %llu of %llu frames\n\0".as_ptr()
+                as *const c_char,
+            gate.skipped as c_ulonglong,
+            (gate.skipped + gate.recognized) as c_ulonglong,
+        );
+    }
+}
@@ -1,4 +1,16 @@
+#[cfg(feature = "hardsubx_ocr")]
+use leptonica_sys::*;
 use palette::{FromColor, Hsv, Lab, Srgb};
+use rayon::prelude::*;
+
+use std::cell::RefCell;
+use std::ops::Range;
+use std::os::raw::c_int;
+use std::rc::Rc;
+use std::{ptr, slice};
+
+use crate::bindings::AVFrame;
+use crate::hardsubx::roi::{Rect, Region};
 
 #[no_mangle]
 pub extern "C" fn rgb_to_hsv(R: f32, G: f32, B: f32, H: &mut f32, S: &mut f32, V: &mut f32) {
@@ -22,3 +34,224 @@ pub extern "C" fn rgb_to_lab(R: f32, G: f32, B: f32, L: &mut f32, a: &mut f32, b
     *a = lab_rep.a;
     *b = lab_rep.b;
 }
+
+/// Value of a white pixel in a 32 bpp Pix
+pub const WHITE: u32 = 0xffff_ff00;
+
+/// Value of a pixel in a 32 bpp Pix, as set by `pixSetRGBPixel`
+pub fn compose_rgb(r: u8, g: u8, b: u8) -> u32 {
+    (r as u32) << 24 | (g as u32) << 16 | (b as u32) << 8
+}
+
+/// Colours which are kept by a mask
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorKey {
+    /// Luminance (L of Lab) above the threshold
+    Luminance(f32),
+    /// Hue less than 20 degrees away from the given hue
+    Hue(f32),
+}
+
+impl ColorKey {
+    fn contains(&self, r: u8, g: u8, b: u8) -> bool {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        match *self {
+            ColorKey::Luminance(threshold) => {
+                rgb_to_lab(r as f32, g as f32, b as f32, &mut x, &mut y, &mut z);
+                x > threshold
+            }
+            ColorKey::Hue(hue) => {
+                rgb_to_hsv(r as f32, g as f32, b as f32, &mut x, &mut y, &mut z);
+                (x - hue).abs() < 20.0
+            }
+        }
+    }
+}
+
+/// Lookup table with one bit for each of the 2^24 RGB colours
+pub struct ColorTable {
+    key: ColorKey,
+    bits: Vec<u64>,
+}
+
+thread_local! {
+    /// Tables built so far, as building one takes a while
+    static COLOR_TABLES: RefCell<Vec<Rc<ColorTable>>> = RefCell::new(Vec::new());
+}
+
+impl ColorTable {
+    /// Returns the table for `key`, building it on first use
+    pub fn get(key: ColorKey) -> Rc<ColorTable> {
+        COLOR_TABLES.with(|tables| {
+            let mut tables = tables.borrow_mut();
+            if let Some(table) = tables.iter().find(|table| table.key == key) {
+                return table.clone();
+            }
+            let table = Rc::new(ColorTable::build(key));
+            tables.push(table.clone());
+            table
+        })
+    }
+
+    fn build(key: ColorKey) -> ColorTable {
+        // One row of 4 words holds the 256 blue values of a red and green value
+        let mut bits = vec![0; (1 << 24) / 64];
+        par_rows(&mut bits, 4, 0..(1 << 16), |rg, row| {
+            let (r, g) = ((rg >> 8) as u8, rg as u8);
+            for b in 0..=255u8 {
+                if key.contains(r, g, b) {
+                    row[b as usize / 64] |= 1 << (b % 64);
+                }
+            }
+        });
+        ColorTable { key, bits }
+    }
+
+    /// Returns `true` if the colour of a 32 bpp pixel is kept
+    pub fn contains(&self, pixel: u32) -> bool {
+        let index = (pixel >> 8) as usize;
+        self.bits[index / 64] >> (index % 64) & 1 != 0
+    }
+}
+
+/// Calls `f` with the index and the data of each of the `rows` of `buf`, `width` items per row
+///
+/// The rows are processed by the global rayon thread pool, so no threads are started per call.
+pub fn par_rows<T, F>(buf: &mut [T], width: usize, rows: Range<usize>, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync + Send,
+{
+    if width == 0 || rows.start >= rows.end {
+        return;
+    }
+    let first_row = rows.start;
+    buf[rows.start * width..rows.end * width]
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(i, row)| f(first_row + i, row));
+}
+
+/// Reads the `region` of a RGB24 frame into the word layout of a 32 bpp Pix, other pixels stay black
+///
+/// # Safety
+/// `frame` must hold RGB24 data of at least `width` x `height` pixels
+pub unsafe fn read_frame(
+    frame: &AVFrame,
+    width: usize,
+    height: usize,
+    region: &Region,
+) -> Vec<u32> {
+    let mut im = vec![0; width * height];
+    if height == 0 || frame.data[0].is_null() || frame.linesize[0] <= 0 {
+        return im;
+    }
+    let linesize = frame.linesize[0] as usize;
+    let data = slice::from_raw_parts(frame.data[0], linesize * (height - 1) + width * 3);
+    par_rows(&mut im, width, region.rows(), |i, row| {
+        let line = &data[i * linesize..i * linesize + width * 3];
+        for span in region.spans(i) {
+            for (pixel, rgb) in row[span.clone()]
+                .iter_mut()
+                .zip(line[span.start * 3..span.end * 3].chunks_exact(3))
+            {
+                *pixel = compose_rgb(rgb[0], rgb[1], rgb[2]);
+            }
+        }
+    });
+    im
+}
+
+/// Keeps the pixels in the `region` of `im` whose colour is in `table`, as white if `white` is set
+pub fn mask_rows(
+    im: &[u32],
+    width: usize,
+    region: &Region,
+    table: &ColorTable,
+    white: bool,
+) -> Vec<u32> {
+    let mut mask = vec![0; im.len()];
+    par_rows(&mut mask, width, region.rows(), |i, row| {
+        let line = &im[i * width..(i + 1) * width];
+        for span in region.spans(i) {
+            for (pixel, &color) in row[span.clone()].iter_mut().zip(&line[span]) {
+                if table.contains(color) {
+                    *pixel = if white { WHITE } else { color };
+                }
+            }
+        }
+    });
+    mask
+}
+
+/// Creates a 32 bpp Pix from the rows of `buf`
+///
+/// # Safety
+/// `buf` must hold `width` x `height` pixels
+pub unsafe fn pix_from_rows(buf: &[u32], width: c_int, height: c_int) -> *mut Pix {
+    let pix = pixCreate(width, height, 32);
+    if pix.is_null() || width <= 0 {
+        return pix;
+    }
+    let wpl = pixGetWpl(pix) as usize;
+    let data = pixGetData(pix);
+    for (i, row) in buf.chunks(width as usize).take(height as usize).enumerate() {
+        ptr::copy_nonoverlapping(row.as_ptr(), data.add(i * wpl), row.len());
+    }
+    pix
+}
+
+/// Copies `rect` of `pix` into a new Pix, null if it is not inside the image
+///
+/// # Safety
+/// `pix` must be a valid Pix
+pub unsafe fn clip_pix(pix: *mut Pix, rect: &Rect) -> *mut Pix {
+    let mut clip_box = boxCreate(
+        rect.left as c_int,
+        rect.top as c_int,
+        rect.width() as c_int,
+        rect.height() as c_int,
+    );
+    let clipped = pixClipRectangle(pix, clip_box, ptr::null_mut());
+    boxDestroy(&mut clip_box);
+    clipped
+}
+
+/// Read only view of the data of a Pix, which can be shared between threads
+pub struct PixRows<'a> {
+    data: &'a [u32],
+    wpl: usize,
+}
+
+impl<'a> PixRows<'a> {
+    /// # Safety
+    /// `pix` must be null or a valid Pix which outlives the view
+    pub unsafe fn new(pix: *mut Pix) -> PixRows<'a> {
+        if pix.is_null() {
+            return PixRows { data: &[], wpl: 0 };
+        }
+        let wpl = pixGetWpl(pix) as usize;
+        let height = pixGetHeight(pix) as usize;
+        PixRows {
+            data: slice::from_raw_parts(pixGetData(pix), wpl * height),
+            wpl,
+        }
+    }
+
+    fn word(&self, i: usize, n: usize) -> u32 {
+        if n >= self.wpl {
+            return 0;
+        }
+        self.data.get(i * self.wpl + n).copied().unwrap_or(0)
+    }
+
+    /// Pixel of a 1 bpp Pix, 0 outside of the image like `pixGetPixel`
+    pub fn bit(&self, i: usize, j: usize) -> u32 {
+        self.word(i, j / 32) >> (31 - j % 32) & 1
+    }
+
+    /// Pixel of a 8 bpp Pix, 0 outside of the image like `pixGetPixel`
+    pub fn byte(&self, i: usize, j: usize) -> u32 {
+        self.word(i, j / 4) >> (8 * (3 - j % 4)) & 0xff
+    }
+}
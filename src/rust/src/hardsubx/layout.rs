@@ -0,0 +1,380 @@
+//! Where the recognized words are in the frame, for positioned subtitles and the `-ocr_json` file
+//!
+//! After each recognition the words Tesseract found are read back with their boxes, moved from
+//! the clipped subtitle region to frame coordinates and kept as the [FrameLayout] of the frame.
+//! C copies it into every subtitle it starts, and when the subtitle ends, the position and
+//! confidence of its text are given to the encoders and its words are appended to the JSON file.
+
+#[cfg(feature = "hardsubx_ocr")]
+use tesseract_sys::*;
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::raw::{c_char, c_float, c_int, c_void};
+use std::ptr::null_mut;
+
+use log::warn;
+
+use crate::hardsubx::lib_hardsubx_ctx;
+use crate::hardsubx::roi::Rect;
+use crate::utils::json_escape;
+
+/// A word recognized by Tesseract, with its box in frame coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    /// Confidence of Tesseract, 0 to 100
+    pub confidence: f32,
+    pub bbox: Rect,
+}
+
+/// The words recognized in one frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameLayout {
+    pub frame_width: usize,
+    pub frame_height: usize,
+    /// Mean confidence of the text as computed by the OCR mode, used if there are no words
+    pub confidence: f32,
+    pub words: Vec<OcrWord>,
+}
+
+impl FrameLayout {
+    /// Smallest rectangle holding all words, `None` without words
+    pub fn bounds(&self) -> Option<Rect> {
+        self.words
+            .iter()
+            .map(|word| word.bbox)
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Top and horizontal center of the text in percent of the frame size
+    pub fn position(&self) -> Option<(f32, f32)> {
+        let bounds = self.bounds()?;
+        if self.frame_width == 0 || self.frame_height == 0 {
+            return None;
+        }
+        let line = bounds.top as f32 * 100.0 / self.frame_height as f32;
+        let position = (bounds.left + bounds.right) as f32 * 50.0 / self.frame_width as f32;
+        Some((line, position))
+    }
+
+    /// Mean and minimum confidence of the words
+    pub fn confidence(&self) -> (f32, f32) {
+        if self.words.is_empty() {
+            return (self.confidence, self.confidence);
+        }
+        let total: f32 = self.words.iter().map(|word| word.confidence).sum();
+        let min = self
+            .words
+            .iter()
+            .map(|word| word.confidence)
+            .fold(f32::INFINITY, f32::min);
+        (total / self.words.len() as f32, min)
+    }
+
+    /// Whether the least confident word is below `review_thresh`, 0 to never review
+    pub fn needs_review(&self, review_thresh: f32) -> bool {
+        review_thresh > 0.0 && self.confidence().1 < review_thresh
+    }
+
+    /// The subtitle `text` shown from `start_ms` to `end_ms` with these words, as a JSON object
+    pub fn to_json(&self, text: &str, start_ms: i64, end_ms: i64, review_thresh: f32) -> String {
+        let words = self
+            .words
+            .iter()
+            .map(|word| {
+                format!(
+                    "{{\"text\":\"{}\",\"confidence\":{:.2},\"box\":{}}}",
+                    json_escape(&word.text),
+                    word.confidence,
+                    rect_to_json(&word.bbox)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let bounds = match self.bounds() {
+            Some(bounds) => rect_to_json(&bounds),
+            None => "null".to_string(),
+        };
+        let (mean, min) = self.confidence();
+        format!(
+            "{{\"start\":{},\"end\":{},\"text\":\"{}\",\"frame\":{{\"width\":{},\"height\":{}}},\
+             \"box\":{},\"confidence\":{{\"mean\":{:.2},\"min\":{:.2}}},\"needs_review\":{},\
+             \"words\":[{}]}}",
+            start_ms,
+            end_ms,
+            json_escape(text),
+            self.frame_width,
+            self.frame_height,
+            bounds,
+            mean,
+            min,
+            self.needs_review(review_thresh),
+            words
+        )
+    }
+}
+
+fn rect_to_json(rect: &Rect) -> String {
+    format!(
+        "{{\"left\":{},\"top\":{},\"right\":{},\"bottom\":{}}}",
+        rect.left, rect.top, rect.right, rect.bottom
+    )
+}
+
+/// Reads the words of the last recognition of `handle`
+///
+/// Words below `threshold` are left out, like the classifiers leave them out of the text. The
+/// boxes are moved by `offset`, the position of the recognized image in the frame.
+///
+/// # Safety
+/// `handle` must be a valid Tesseract instance
+pub unsafe fn read_words(
+    handle: *mut TessBaseAPI,
+    threshold: f32,
+    offset: (usize, usize),
+) -> Vec<OcrWord> {
+    let mut words = Vec::new();
+    let it = TessBaseAPIGetIterator(handle);
+    if it.is_null() {
+        return words;
+    }
+    let level = TessPageIteratorLevel_RIL_WORD;
+    loop {
+        let text = TessResultIteratorGetUTF8Text(it, level);
+        if !text.is_null() {
+            let word = CStr::from_ptr(text).to_string_lossy().trim().to_string();
+            TessDeleteText(text);
+            let confidence = TessResultIteratorConfidence(it, level);
+            let (mut left, mut top, mut right, mut bottom) = (0, 0, 0, 0);
+            let has_box = TessPageIteratorBoundingBox(
+                it as *const TessPageIterator,
+                level,
+                &mut left,
+                &mut top,
+                &mut right,
+                &mut bottom,
+            ) != 0;
+            if has_box && !word.is_empty() && (threshold <= 0.0 || confidence >= threshold) {
+                let at = |value: c_int, by: usize| value.max(0) as usize + by;
+                words.push(OcrWord {
+                    text: word,
+                    confidence,
+                    bbox: Rect {
+                        left: at(left, offset.0),
+                        top: at(top, offset.1),
+                        right: at(right, offset.0),
+                        bottom: at(bottom, offset.1),
+                    },
+                });
+            }
+        }
+        if TessPageIteratorNext(it as *mut TessPageIterator, level) == 0 {
+            break;
+        }
+    }
+    TessResultIteratorDelete(it);
+    words
+}
+
+/// Writes the subtitles with their words as a JSON array
+struct OcrJson {
+    out: BufWriter<File>,
+    entries: usize,
+}
+
+impl OcrJson {
+    fn create(path: &str) -> io::Result<OcrJson> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"[\n")?;
+        Ok(OcrJson { out, entries: 0 })
+    }
+
+    fn append(&mut self, entry: &str) -> io::Result<()> {
+        if self.entries > 0 {
+            self.out.write_all(b",\n")?;
+        }
+        self.entries += 1;
+        self.out.write_all(entry.as_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(b"\n]\n")?;
+        self.out.flush()
+    }
+}
+
+/// Words of the last recognized frame and the open `-ocr_json` file
+#[derive(Default)]
+pub struct LayoutState {
+    last: FrameLayout,
+    json: Option<OcrJson>,
+    /// Set once the JSON file could not be written, to warn only once
+    json_failed: bool,
+}
+
+/// Returns the layout state of `ctx`, creating it on first use
+///
+/// # Safety
+/// `ctx` must be a valid pointer, whose `word_layout` is null or was created here
+unsafe fn layout_state<'a>(ctx: *mut lib_hardsubx_ctx) -> &'a mut LayoutState {
+    if (*ctx).word_layout.is_null() {
+        (*ctx).word_layout = Box::into_raw(Box::<LayoutState>::default()) as *mut c_void;
+    }
+    &mut *((*ctx).word_layout as *mut LayoutState)
+}
+
+/// Whether the words are needed, for positioned subtitles, confidence comments or the JSON file
+///
+/// # Safety
+/// `ctx` must be a valid pointer
+pub unsafe fn wants_words(ctx: *const lib_hardsubx_ctx) -> bool {
+    (*ctx).cue_position != 0 || (*ctx).conf_comment != 0 || !(*ctx).ocr_json.is_null()
+}
+
+/// Keeps `layout` as the words of the last recognized frame
+///
+/// # Safety
+/// `ctx` must be a valid pointer
+pub unsafe fn record_layout(ctx: *mut lib_hardsubx_ctx, layout: FrameLayout) {
+    layout_state(ctx).last = layout;
+}
+
+/// Copies the words of the last recognized frame, null if they are not needed
+///
+/// The copy is freed with [hardsubx_free_layout].
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_copy_layout(ctx: *mut lib_hardsubx_ctx) -> *mut c_void {
+    if ctx.is_null() || !wants_words(ctx) {
+        return null_mut();
+    }
+    Box::into_raw(Box::new(layout_state(ctx).last.clone())) as *mut c_void
+}
+
+/// Frees a copy made with [hardsubx_copy_layout]
+///
+/// # Safety
+/// `layout` must be null or returned by [hardsubx_copy_layout]
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_free_layout(layout: *mut c_void) {
+    if !layout.is_null() {
+        drop(Box::from_raw(layout as *mut FrameLayout));
+    }
+}
+
+/// Gives the top and horizontal center of the words of `layout` in percent of the frame size
+///
+/// Returns 1 if the position was set, 0 if `layout` is null or has no words.
+///
+/// # Safety
+/// `layout` must be null or returned by [hardsubx_copy_layout], the other pointers valid
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_layout_position(
+    layout: *const c_void,
+    line_percent: *mut c_float,
+    position_percent: *mut c_float,
+) -> c_int {
+    if layout.is_null() || line_percent.is_null() || position_percent.is_null() {
+        return 0;
+    }
+    match (*(layout as *const FrameLayout)).position() {
+        Some((line, position)) => {
+            *line_percent = line;
+            *position_percent = position;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Gives the mean and minimum confidence of the words of `layout`
+///
+/// Returns 1 if they were set, 0 if `layout` is null.
+///
+/// # Safety
+/// `layout` must be null or returned by [hardsubx_copy_layout], the other pointers valid
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_layout_confidence(
+    layout: *const c_void,
+    mean_confidence: *mut c_float,
+    min_confidence: *mut c_float,
+) -> c_int {
+    if layout.is_null() || mean_confidence.is_null() || min_confidence.is_null() {
+        return 0;
+    }
+    let (mean, min) = (*(layout as *const FrameLayout)).confidence();
+    *mean_confidence = mean;
+    *min_confidence = min;
+    1
+}
+
+/// Appends a subtitle with the words of `layout` to the `-ocr_json` file, if one is set
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, `layout` null or returned by [hardsubx_copy_layout] and
+/// `text` null or nul terminated
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_write_layout(
+    ctx: *mut lib_hardsubx_ctx,
+    layout: *const c_void,
+    text: *const c_char,
+    start_ms: i64,
+    end_ms: i64,
+) {
+    if ctx.is_null() || (*ctx).ocr_json.is_null() || text.is_null() {
+        return;
+    }
+    let path = CStr::from_ptr((*ctx).ocr_json)
+        .to_string_lossy()
+        .into_owned();
+    let state = layout_state(ctx);
+    if state.json_failed {
+        return;
+    }
+    if state.json.is_none() {
+        match OcrJson::create(&path) {
+            Ok(json) => state.json = Some(json),
+            Err(err) => {
+                warn!("Unable to create {}: {}", path, err);
+                state.json_failed = true;
+                return;
+            }
+        }
+    }
+
+    let text = CStr::from_ptr(text).to_string_lossy();
+    let review_thresh = (*ctx).review_thresh;
+    let entry = if layout.is_null() {
+        FrameLayout::default().to_json(&text, start_ms, end_ms, review_thresh)
+    } else {
+        (*(layout as *const FrameLayout)).to_json(&text, start_ms, end_ms, review_thresh)
+    };
+    if let Some(json) = &mut state.json {
+        if let Err(err) = json.append(&entry) {
+            warn!("Unable to write {}: {}", path, err);
+            state.json_failed = true;
+        }
+    }
+}
+
+/// Closes the `-ocr_json` file and frees the layout state of `ctx`
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, whose `word_layout` is null or was created by Rust
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_free_layout_state(ctx: *mut lib_hardsubx_ctx) {
+    if ctx.is_null() || (*ctx).word_layout.is_null() {
+        return;
+    }
+    let state = Box::from_raw((*ctx).word_layout as *mut LayoutState);
+    (*ctx).word_layout = null_mut();
+    if let Some(json) = state.json {
+        if let Err(err) = json.finish() {
+            warn!("Unable to write the OCR JSON file: {}", err);
+        }
+    }
+}
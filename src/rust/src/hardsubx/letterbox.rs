@@ -0,0 +1,190 @@
+//! Detection of the black bars around the picture
+//!
+//! Letterboxed content has black bars above and below the picture, which often hold the burned-in
+//! subtitles. The active picture area is the union of the rows and columns which are mostly not
+//! black, over the first `-letterbox` frames which are not black. Subtitles in the bars only cover
+//! a small part of their rows, so they are not taken as picture.
+
+#[cfg(feature = "hardsubx_ocr")]
+use leptonica_sys::*;
+
+use std::os::raw::c_char;
+use std::slice;
+
+use crate::bindings::{mprint, AVFrame};
+use crate::hardsubx::imgops::{par_rows, read_frame};
+use crate::hardsubx::lib_hardsubx_ctx;
+use crate::hardsubx::roi::{Rect, Region};
+
+/// Luma up to which a pixel is black
+const BLACK_LEVEL: u32 = 32;
+
+/// Part of a row or column which must not be black for it to be picture, in percent
+const PICTURE_PERCENT: usize = 50;
+
+/// Bars thinner than this part of the frame size are ignored, in percent
+const MIN_BAR_PERCENT: usize = 2;
+
+/// Luma of a 32 bpp pixel
+fn luma(pixel: u32) -> u32 {
+    let (r, g, b) = (pixel >> 24, pixel >> 16 & 0xff, pixel >> 8 & 0xff);
+    (77 * r + 150 * g + 29 * b) >> 8
+}
+
+fn is_picture(count: usize, len: usize) -> bool {
+    count * 100 >= len * PICTURE_PERCENT
+}
+
+/// Picture area of one frame, `None` if the whole frame is black
+fn picture_area(im: &[u32], width: usize, height: usize) -> Option<Rect> {
+    let mut row_counts = vec![0; height];
+    par_rows(&mut row_counts, 1, 0..height, |i, count| {
+        let row = &im[i * width..(i + 1) * width];
+        count[0] = row
+            .iter()
+            .filter(|&&pixel| luma(pixel) > BLACK_LEVEL)
+            .count();
+    });
+    let top = row_counts.iter().position(|&n| is_picture(n, width))?;
+    let bottom = row_counts.iter().rposition(|&n| is_picture(n, width))? + 1;
+
+    let mut column_counts = vec![0; width];
+    par_rows(&mut column_counts, 1, 0..width, |j, count| {
+        count[0] = (top..bottom)
+            .filter(|&i| luma(im[i * width + j]) > BLACK_LEVEL)
+            .count();
+    });
+    let left = column_counts
+        .iter()
+        .position(|&n| is_picture(n, bottom - top))?;
+    let right = column_counts
+        .iter()
+        .rposition(|&n| is_picture(n, bottom - top))?
+        + 1;
+
+    Some(Rect {
+        left,
+        top,
+        right,
+        bottom,
+    })
+}
+
+/// Adds the picture area of a frame to the active area of `ctx`, until enough frames were seen
+///
+/// # Safety
+/// `ctx` must be a valid pointer and `frame` must hold RGB24 data of `width` x `height` pixels
+pub unsafe fn update(ctx: *mut lib_hardsubx_ctx, frame: &AVFrame, width: usize, height: usize) {
+    let ctx = &mut *ctx;
+    if ctx.letterbox_frames_seen >= ctx.letterbox_frames {
+        return;
+    }
+    let im = read_frame(frame, width, height, &Region::rows_of(width, 0..height));
+    let area = match picture_area(&im, width, height) {
+        Some(area) => area,
+        // Black frames, e.g. of a fade in, tell nothing about the bars
+        None => return,
+    };
+    if ctx.active_right == 0 {
+        ctx.active_left = area.left as i32;
+        ctx.active_top = area.top as i32;
+        ctx.active_right = area.right as i32;
+        ctx.active_bottom = area.bottom as i32;
+    } else {
+        ctx.active_left = ctx.active_left.min(area.left as i32);
+        ctx.active_top = ctx.active_top.min(area.top as i32);
+        ctx.active_right = ctx.active_right.max(area.right as i32);
+        ctx.active_bottom = ctx.active_bottom.max(area.bottom as i32);
+    }
+    ctx.letterbox_frames_seen += 1;
+
+    if ctx.letterbox_frames_seen == ctx.letterbox_frames {
+        if active_area(ctx, width, height).is_some() {
+            mprint(
+                "Active picture area : %dx%d at %d,%d\n\0".as_ptr() as *const c_char,
+                ctx.active_right - ctx.active_left,
+                ctx.active_bottom - ctx.active_top,
+                ctx.active_left,
+                ctx.active_top,
+            );
+        } else {
+            mprint(
+                "Active picture area : Whole frame, no black bars found\n\0".as_ptr()
+                    as *const c_char,
+            );
+        }
+    }
+}
+
+/// Active picture area of `ctx`, `None` until it is detected or if the frame has no black bars
+///
+/// # Safety
+/// `ctx` must be a valid pointer
+pub unsafe fn active_area(
+    ctx: *const lib_hardsubx_ctx,
+    width: usize,
+    height: usize,
+) -> Option<Rect> {
+    let ctx = &*ctx;
+    if ctx.letterbox_frames <= 0
+        || ctx.letterbox_frames_seen < ctx.letterbox_frames
+        || ctx.active_right <= 0
+    {
+        return None;
+    }
+    let active = Rect {
+        left: (ctx.active_left.max(0) as usize).min(width),
+        top: (ctx.active_top.max(0) as usize).min(height),
+        right: (ctx.active_right as usize).min(width),
+        bottom: (ctx.active_bottom.max(0) as usize).min(height),
+    };
+    let min_rows = height * MIN_BAR_PERCENT / 100;
+    let min_columns = width * MIN_BAR_PERCENT / 100;
+    let has_bars = active.top > min_rows
+        || height - active.bottom > min_rows
+        || active.left > min_columns
+        || width - active.right > min_columns;
+    if has_bars && active.left < active.right && active.top < active.bottom {
+        Some(active)
+    } else {
+        None
+    }
+}
+
+/// Clears the edges found along the borders of the `active` area in a 8 bpp edge image
+///
+/// The step from a black bar to the picture is a strong edge, which would be taken as text.
+///
+/// # Safety
+/// `pix` must be null or a valid 8 bpp Pix
+pub unsafe fn clear_bar_edges(pix: *mut Pix, active: &Rect) {
+    if pix.is_null() {
+        return;
+    }
+    let width = pixGetWidth(pix).max(0) as usize;
+    let height = pixGetHeight(pix).max(0) as usize;
+    let wpl = pixGetWpl(pix).max(0) as usize;
+    let data = pixGetData(pix);
+    if data.is_null() || wpl == 0 {
+        return;
+    }
+    let data = slice::from_raw_parts_mut(data, wpl * height);
+    // The 3x3 Sobel filter responds on both sides of a border
+    let lines = |border: usize, len: usize| {
+        if border == 0 || border >= len {
+            0..0
+        } else {
+            border - 1..border + 1
+        }
+    };
+    for i in lines(active.top, height).chain(lines(active.bottom, height)) {
+        data[i * wpl..(i + 1) * wpl].fill(0);
+    }
+    for j in lines(active.left, width).chain(lines(active.right, width)) {
+        // Pixels of a 8 bpp Pix are stored four per word, the first one in the high byte
+        let keep = !(0xff << (8 * (3 - j % 4)));
+        for row in data.chunks_exact_mut(wpl) {
+            row[j / 4] &= keep;
+        }
+    }
+}
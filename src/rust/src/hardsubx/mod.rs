@@ -1,6 +1,13 @@
 pub mod classifier;
+pub mod debug;
 pub mod decoder;
+pub mod error;
+pub mod gate;
 pub mod imgops;
+pub mod layout;
+pub mod letterbox;
+pub mod ocr;
+pub mod roi;
 pub mod utility;
 
 #[cfg(feature = "hardsubx_ocr")]
@@ -42,6 +49,7 @@ pub struct lib_hardsubx_ctx {
     pub video_stream_id: ::std::os::raw::c_int,
     pub im: *mut Pix,
     pub tess_handle: *mut TessBaseAPI,
+    pub tess_api: *mut ::std::os::raw::c_void,
     pub cur_conf: f32,
     pub prev_conf: f32,
     pub tickertext: ::std::os::raw::c_int,
@@ -54,4 +62,21 @@ pub struct lib_hardsubx_ctx {
     pub conf_thresh: f32,
     pub hue: f32,
     pub lum_thresh: f32,
+    pub rois: [bindings::hardsubx_roi; 8usize],
+    pub roi_count: ::std::os::raw::c_int,
+    pub letterbox_frames: ::std::os::raw::c_int,
+    pub letterbox_frames_seen: ::std::os::raw::c_int,
+    pub active_left: ::std::os::raw::c_int,
+    pub active_top: ::std::os::raw::c_int,
+    pub active_right: ::std::os::raw::c_int,
+    pub active_bottom: ::std::os::raw::c_int,
+    pub debug_dir: *mut ::std::os::raw::c_char,
+    pub change_thresh: f32,
+    pub change_gate: *mut ::std::os::raw::c_void,
+    pub merge_similarity: f32,
+    pub cue_position: ::std::os::raw::c_int,
+    pub ocr_json: *mut ::std::os::raw::c_char,
+    pub word_layout: *mut ::std::os::raw::c_void,
+    pub conf_comment: ::std::os::raw::c_int,
+    pub review_thresh: f32,
 }
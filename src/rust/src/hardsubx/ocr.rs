@@ -0,0 +1,308 @@
+//! Creating the Tesseract instance which recognizes the burned-in subtitles
+//!
+//! The language packs, the tessdata directory, the engine and page segmentation modes and the
+//! characters to recognize are collected in an [OcrConfig], which creates a [TessApi] owning the
+//! `TessBaseAPI`. Several language packs are joined with `+`, e.g. `eng+hin` for subtitles mixing
+//! English and Hindi.
+
+#[cfg(feature = "hardsubx_ocr")]
+use tesseract_sys::*;
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::iter;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+
+use log::warn;
+
+use crate::bindings::mprint;
+use crate::hardsubx::error::HardsubxError;
+use crate::hardsubx::lib_hardsubx_ctx;
+
+/// Directories searched for `tessdata` after `TESSDATA_PREFIX`, as by `probe_tessdata_location`
+const TESSDATA_PREFIXES: &[&str] = &[
+    "./",
+    "/usr/share/",
+    "/usr/local/share/",
+    "/usr/share/tesseract-ocr/",
+    "/usr/share/tesseract-ocr/4.00/",
+    "/usr/share/tesseract-ocr/5/",
+    "/usr/share/tesseract/",
+];
+
+/// Language pack used when none of the configured ones is installed
+const FALLBACK_LANGUAGE: &str = "eng";
+
+/// Settings of the Tesseract instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OcrConfig {
+    /// Names of the `.traineddata` files separated by `+`, e.g. `eng+hin`
+    pub languages: String,
+    /// A `tessdata` directory or a directory containing one, searched for if `None`
+    pub tessdata_dir: Option<PathBuf>,
+    /// Engine mode, `None` for the best one of the installed Tesseract version
+    pub oem: Option<TessOcrEngineMode>,
+    /// Page segmentation mode, `None` for the Tesseract default
+    pub psm: Option<TessPageSegMode>,
+    /// The only characters to recognize, `None` for all of the language packs
+    pub whitelist: Option<String>,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        OcrConfig {
+            languages: FALLBACK_LANGUAGE.to_string(),
+            tessdata_dir: None,
+            oem: None,
+            psm: None,
+            whitelist: None,
+        }
+    }
+}
+
+impl OcrConfig {
+    /// Names of the language packs, in the order they are given
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.languages
+            .split('+')
+            .map(str::trim)
+            .filter(|lang| !lang.is_empty())
+    }
+
+    /// Directories which may hold the language packs, most preferred first
+    fn tessdata_candidates(&self) -> Vec<PathBuf> {
+        let prefixes: Vec<PathBuf> = match &self.tessdata_dir {
+            Some(dir) => vec![dir.clone()],
+            None => env::var_os("TESSDATA_PREFIX")
+                .map(PathBuf::from)
+                .into_iter()
+                .chain(TESSDATA_PREFIXES.iter().map(PathBuf::from))
+                .collect(),
+        };
+        // TESSDATA_PREFIX names the parent of tessdata for Tesseract 3, and tessdata itself later
+        prefixes
+            .into_iter()
+            .flat_map(|prefix| iter::once(prefix.join("tessdata")).chain(iter::once(prefix)))
+            .collect()
+    }
+
+    /// Returns the tessdata directory with the most of `wanted` and the languages found in it
+    ///
+    /// Missing languages are left out with a warning. If none is found, the English pack is
+    /// used instead, like the OCR of bitmap subtitles does.
+    fn resolve(&self) -> Result<(PathBuf, Vec<String>), HardsubxError> {
+        let wanted: Vec<&str> = self.languages().collect();
+        if wanted.is_empty() {
+            return Err(HardsubxError::OcrInit(format!(
+                "No OCR language given in \"{}\"",
+                self.languages
+            )));
+        }
+
+        let installed =
+            |dir: &Path, lang: &str| dir.join(format!("{}.traineddata", lang)).is_file();
+        let mut best: Option<(PathBuf, Vec<String>)> = None;
+        for dir in self.tessdata_candidates() {
+            let found: Vec<String> = wanted
+                .iter()
+                .filter(|lang| installed(&dir, lang))
+                .map(|lang| lang.to_string())
+                .collect();
+            if found.len() > best.as_ref().map_or(0, |(_, langs)| langs.len()) {
+                best = Some((dir, found));
+            }
+        }
+
+        match best {
+            Some((dir, found)) => {
+                for lang in wanted
+                    .iter()
+                    .filter(|lang| !found.iter().any(|f| f == *lang))
+                {
+                    warn!(
+                        "{}.traineddata not found in {}, recognizing without it",
+                        lang,
+                        dir.display()
+                    );
+                }
+                Ok((dir, found))
+            }
+            None if wanted != [FALLBACK_LANGUAGE] => {
+                warn!(
+                    "No .traineddata found for \"{}\", switching to English",
+                    self.languages
+                );
+                OcrConfig {
+                    languages: FALLBACK_LANGUAGE.to_string(),
+                    ..self.clone()
+                }
+                .resolve()
+            }
+            None => Err(HardsubxError::OcrInit(format!(
+                "{}.traineddata not found! No Switching Possible",
+                FALLBACK_LANGUAGE
+            ))),
+        }
+    }
+
+    /// Creates and initializes a Tesseract instance with these settings
+    ///
+    /// # Safety
+    /// Calls into Tesseract, which must be linked
+    pub unsafe fn create_api(&self) -> Result<TessApi, HardsubxError> {
+        let (tessdata, languages) = self.resolve()?;
+
+        let version = CStr::from_ptr(TessVersion()).to_string_lossy();
+        let legacy = version.starts_with("3.");
+        // Tesseract 3 appends tessdata to the path itself
+        let datapath = if legacy {
+            match tessdata.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        } else {
+            tessdata
+        };
+        let oem = self.oem.unwrap_or(if legacy {
+            TessOcrEngineMode_OEM_TESSERACT_ONLY
+        } else {
+            TessOcrEngineMode_OEM_LSTM_ONLY
+        });
+
+        let datapath = CString::new(datapath.to_string_lossy().into_owned())
+            .map_err(|_| HardsubxError::OcrInit("Invalid tessdata directory".to_string()))?;
+        let language = CString::new(languages.join("+")).map_err(|_| {
+            HardsubxError::OcrInit(format!("Invalid OCR language \"{}\"", self.languages))
+        })?;
+        let mut var_name = CString::new("debug_file").unwrap().into_raw();
+        let mut var_value = CString::new("/dev/null").unwrap().into_raw();
+
+        let api = TessApi {
+            handle: TessBaseAPICreate(),
+        };
+        let ret = TessBaseAPIInit4(
+            api.handle,
+            datapath.as_ptr(),
+            language.as_ptr(),
+            oem,
+            null_mut(),
+            0,
+            &mut var_name,
+            &mut var_value,
+            1,
+            0,
+        );
+        drop(CString::from_raw(var_name));
+        drop(CString::from_raw(var_value));
+        if ret != 0 {
+            return Err(HardsubxError::OcrInit(format!(
+                "Tesseract could not be initialized for \"{}\" in {}",
+                language.to_string_lossy(),
+                datapath.to_string_lossy()
+            )));
+        }
+
+        if let Some(psm) = self.psm {
+            TessBaseAPISetPageSegMode(api.handle, psm);
+        }
+        if let Some(whitelist) = &self.whitelist {
+            let whitelist = CString::new(whitelist.as_str()).map_err(|_| {
+                HardsubxError::OcrInit("Invalid OCR character whitelist".to_string())
+            })?;
+            TessBaseAPISetVariable(
+                api.handle,
+                "tessedit_char_whitelist\0".as_ptr() as *const c_char,
+                whitelist.as_ptr(),
+            );
+        }
+
+        Ok(api)
+    }
+}
+
+/// A Tesseract instance, ended and deleted when dropped
+#[derive(Debug)]
+pub struct TessApi {
+    handle: *mut TessBaseAPI,
+}
+
+impl TessApi {
+    pub fn handle(&self) -> *mut TessBaseAPI {
+        self.handle
+    }
+}
+
+impl Drop for TessApi {
+    fn drop(&mut self) {
+        unsafe {
+            TessBaseAPIEnd(self.handle);
+            TessBaseAPIDelete(self.handle);
+        }
+    }
+}
+
+unsafe fn optional_string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
+/// Creates the Tesseract instance of `ctx`, owned by Rust and freed with [hardsubx_free_ocr]
+///
+/// `languages`, `tessdata_dir` and `whitelist` may be null and `oem` and `psm` negative for the
+/// defaults. Returns 0 on success, otherwise prints why and returns -1.
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, and the strings null or nul terminated
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_init_ocr(
+    ctx: *mut lib_hardsubx_ctx,
+    languages: *const c_char,
+    tessdata_dir: *const c_char,
+    oem: c_int,
+    psm: c_int,
+    whitelist: *const c_char,
+) -> c_int {
+    if ctx.is_null() {
+        warn!("{}", HardsubxError::NullPointer("ctx"));
+        return -1;
+    }
+    let config = OcrConfig {
+        languages: optional_string(languages).unwrap_or_else(|| FALLBACK_LANGUAGE.to_string()),
+        tessdata_dir: optional_string(tessdata_dir).map(PathBuf::from),
+        oem: if oem < 0 { None } else { Some(oem as _) },
+        psm: if psm < 0 { None } else { Some(psm as _) },
+        whitelist: optional_string(whitelist),
+    };
+
+    match config.create_api() {
+        Ok(api) => {
+            (*ctx).tess_handle = api.handle();
+            (*ctx).tess_api = Box::into_raw(Box::new(api)) as *mut c_void;
+            0
+        }
+        Err(err) => {
+            let err = CString::new(err.to_string()).unwrap_or_default();
+            mprint("%s\n\0".as_ptr() as *const c_char, err.as_ptr());
+            -1
+        }
+    }
+}
+
+/// Ends and deletes the Tesseract instance of `ctx`
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer, whose `tess_api` is null or was created by Rust
+#[no_mangle]
+pub unsafe extern "C" fn hardsubx_free_ocr(ctx: *mut lib_hardsubx_ctx) {
+    if ctx.is_null() || (*ctx).tess_api.is_null() {
+        return;
+    }
+    drop(Box::from_raw((*ctx).tess_api as *mut TessApi));
+    (*ctx).tess_api = null_mut();
+    (*ctx).tess_handle = null_mut();
+}
@@ -0,0 +1,197 @@
+//! Region of the frame which is searched for burned-in subtitles
+//!
+//! The region is the union of the rectangles given with `-roi`, either as fractions of the frame
+//! size or in pixels. Without any rectangle each mode searches its own band of rows at the bottom
+//! of the frame, or of the picture if black bars were detected. Fractions are of the picture as
+//! well then, pixels are always of the frame.
+
+use std::ops::Range;
+
+use crate::bindings::hardsubx_roi;
+use crate::hardsubx::letterbox::active_area;
+use crate::hardsubx::lib_hardsubx_ctx;
+
+/// Rectangle of the frame in pixels, `right` and `bottom` are exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
+impl Rect {
+    /// Converts a rectangle of the options, clipped to the frame
+    ///
+    /// Fractions are of the `picture` area of the frame. Edges at 0 or 1 extend to the border of
+    /// the frame, so a rectangle at the bottom of the picture includes the bar below it. Returns
+    /// `None` if no pixel of the rectangle is inside the frame.
+    pub fn from_roi(
+        roi: &hardsubx_roi,
+        width: usize,
+        height: usize,
+        picture: &Rect,
+    ) -> Option<Rect> {
+        // Float to integer casts saturate, negative values become 0
+        let rect = if roi.in_pixels != 0 {
+            Rect {
+                left: roi.left.floor() as usize,
+                top: roi.top.floor() as usize,
+                right: ((roi.left + roi.width).ceil() as usize).min(width),
+                bottom: ((roi.top + roi.height).ceil() as usize).min(height),
+            }
+        } else {
+            let scale = |fraction: f32, start: usize, len: usize, frame_len: usize, ceil: bool| {
+                if fraction <= 0.0 {
+                    0
+                } else if fraction >= 1.0 {
+                    frame_len
+                } else {
+                    let offset = fraction * len as f32;
+                    let offset = if ceil { offset.ceil() } else { offset.floor() };
+                    (start + offset as usize).min(frame_len)
+                }
+            };
+            Rect {
+                left: scale(roi.left, picture.left, picture.width(), width, false),
+                top: scale(roi.top, picture.top, picture.height(), height, false),
+                right: scale(
+                    roi.left + roi.width,
+                    picture.left,
+                    picture.width(),
+                    width,
+                    true,
+                ),
+                bottom: scale(
+                    roi.top + roi.height,
+                    picture.top,
+                    picture.height(),
+                    height,
+                    true,
+                ),
+            }
+        };
+        if rect.left < rect.right && rect.top < rect.bottom {
+            Some(rect)
+        } else {
+            None
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> usize {
+        self.bottom - self.top
+    }
+
+    pub fn rows(&self) -> Range<usize> {
+        self.top..self.bottom
+    }
+
+    pub fn columns(&self) -> Range<usize> {
+        self.left..self.right
+    }
+
+    /// Smallest rectangle holding both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
+
+/// Union of rectangles of the frame, which may overlap
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Region {
+    rects: Vec<Rect>,
+}
+
+impl Region {
+    /// Region covering the whole `rows` of a frame
+    pub fn rows_of(width: usize, rows: Range<usize>) -> Region {
+        let rect = Rect {
+            left: 0,
+            top: rows.start,
+            right: width,
+            bottom: rows.end,
+        };
+        Region {
+            rects: if rect.left < rect.right && rect.top < rect.bottom {
+                vec![rect]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Region given in the options, or the default region of the mode if there is none
+    ///
+    /// The default region covers the rows from `default_top(height)` to the bottom of the frame.
+    /// Once black bars are detected, it starts at `default_top` of the active picture area instead,
+    /// so the bar below the picture is included. Fractional rectangles of the options are of the
+    /// active picture area then, see [Rect::from_roi].
+    ///
+    /// # Safety
+    /// `ctx` must be a valid pointer
+    pub unsafe fn from_ctx(
+        ctx: *const lib_hardsubx_ctx,
+        width: usize,
+        height: usize,
+        default_top: fn(usize) -> usize,
+    ) -> Region {
+        let rois = &(*ctx).rois;
+        let count = ((*ctx).roi_count.max(0) as usize).min(rois.len());
+        let active = active_area(ctx, width, height);
+        if count == 0 {
+            let top = match active {
+                Some(active) => active.top + default_top(active.height()),
+                None => default_top(height),
+            };
+            return Region::rows_of(width, top..height);
+        }
+        let picture = active.unwrap_or(Rect {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        });
+        Region {
+            rects: rois[..count]
+                .iter()
+                .filter_map(|roi| Rect::from_roi(roi, width, height, &picture))
+                .collect(),
+        }
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Smallest rectangle holding the whole region, `None` if the region is empty
+    pub fn bounds(&self) -> Option<Rect> {
+        let first = *self.rects.first()?;
+        Some(
+            self.rects
+                .iter()
+                .fold(first, |bounds, rect| bounds.union(rect)),
+        )
+    }
+
+    /// Rows with at least one pixel of the region
+    pub fn rows(&self) -> Range<usize> {
+        self.bounds().map_or(0..0, |bounds| bounds.rows())
+    }
+
+    /// Columns of the region in `row`, one range for each rectangle crossing it
+    pub fn spans(&self, row: usize) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.rects
+            .iter()
+            .filter(move |rect| rect.rows().contains(&row))
+            .map(|rect| rect.columns())
+    }
+}
@@ -2,7 +2,7 @@
 use rsmpeg::avutil::*;
 #[cfg(feature = "hardsubx_ocr")]
 use rsmpeg::ffi::AVRational;
-use std::os::raw::{c_char, c_int};
+use std::os::raw::{c_char, c_float, c_int};
 use std::{cmp, ffi};
 
 const AV_TIME_BASE: i32 = 1000000;
@@ -26,43 +26,76 @@ pub extern "C" fn convert_pts_to_s(pts: i64, time_base: AVRational) -> i64 {
     av_rescale_q(pts, time_base, AV_TIME_BASE_Q) / 1000000
 }
 
-fn _edit_distance_rec(
-    word1: &[u8],
-    word2: &[u8],
-    i: usize,
-    j: usize,
-    dp_array: &mut Vec<Vec<i32>>,
-) -> i32 {
-    // Recursive implementation with DP of Levenshtein distance
-
-    if dp_array.is_empty() || dp_array[0].is_empty() {
-        // in case word1  or word2 has length 0
-
-        cmp::max(i as i32, j as i32)
-    } else if dp_array[i][j] != -1 {
-        dp_array[i][j]
-    } else if cmp::min(i, j) == 0 {
-        dp_array[i][j] = cmp::max(i as i32, j as i32);
-        cmp::max(i as i32, j as i32)
-    } else {
-        let length_branch_one = _edit_distance_rec(word1, word2, i - 1, j, dp_array) + 1;
-        let length_branch_two = _edit_distance_rec(word1, word2, i, j - 1, dp_array) + 1;
-
-        let length_branch_three = _edit_distance_rec(word1, word2, i - 1, j - 1, dp_array)
-            + if word1[i - 1] == word2[j - 1] { 0 } else { 1 };
-        dp_array[i][j] = cmp::min(
-            length_branch_one,
-            cmp::min(length_branch_two, length_branch_three),
-        );
-        dp_array[i][j]
+/// Levenshtein distance between the characters of `word1` and `word2`, or `None`
+/// as soon as it is known to exceed `max_dist`.
+///
+/// Only two rows of the table are kept, so long OCR lines cost no stack and
+/// `O(min(len1, len2))` memory, and only the diagonal band that can stay within
+/// `max_dist` is computed.
+pub fn bounded_edit_distance(word1: &str, word2: &str, max_dist: usize) -> Option<usize> {
+    let mut chars1: Vec<char> = word1.chars().collect();
+    let mut chars2: Vec<char> = word2.chars().collect();
+    if chars1.len() < chars2.len() {
+        std::mem::swap(&mut chars1, &mut chars2);
+    }
+    // The length difference alone needs that many insertions
+    if chars1.len() - chars2.len() > max_dist {
+        return None;
+    }
+
+    // Cells further than max_dist from the diagonal can never come back within
+    // the bound, so only a band around it is filled and the rest counts as `out`
+    let out = max_dist.saturating_add(1);
+    let len2 = chars2.len();
+    let mut prev: Vec<usize> = (0..=len2).map(|j| cmp::min(j, out)).collect();
+    let mut cur = vec![out; len2 + 1];
+    for (i, c1) in chars1.iter().enumerate() {
+        let row = i + 1;
+        let first = cmp::max(row.saturating_sub(max_dist), 1);
+        let last = cmp::min(row.saturating_add(max_dist), len2);
+        cur[0] = cmp::min(row, out);
+        cur[first - 1] = if first == 1 { cur[0] } else { out };
+        let mut row_min = cur[first - 1];
+        for j in first..=last {
+            let substitute = prev[j - 1].saturating_add(usize::from(*c1 != chars2[j - 1]));
+            let indel = cmp::min(prev[j], cur[j - 1]).saturating_add(1);
+            cur[j] = cmp::min(cmp::min(substitute, indel), out);
+            row_min = cmp::min(row_min, cur[j]);
+        }
+        if last < len2 {
+            cur[last + 1] = out;
+        }
+        // Distances never decrease from one row to the next
+        if row_min > max_dist {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    Some(prev[chars2.len()]).filter(|&dist| dist <= max_dist)
+}
+
+/// Similarity of two OCR results between 0 and 1, i.e. one minus their edit
+/// distance relative to the longer text, or `None` if it is below `min_similarity`
+pub fn text_similarity_bounded(word1: &str, word2: &str, min_similarity: f32) -> Option<f32> {
+    let longest = cmp::max(word1.chars().count(), word2.chars().count());
+    if longest == 0 {
+        return Some(1.0);
     }
+    // The small margin keeps e.g. 1 of 5 characters within 80% despite rounding
+    let max_dist =
+        ((1.0 - min_similarity.clamp(0.0, 1.0)) * longest as f32 + 0.001).floor() as usize;
+    bounded_edit_distance(word1, word2, max_dist).map(|dist| 1.0 - dist as f32 / longest as f32)
+}
+
+unsafe fn lossy_prefix(word: *const c_char, len: usize) -> String {
+    let bytes = ffi::CStr::from_ptr(word).to_bytes();
+    String::from_utf8_lossy(&bytes[..cmp::min(len, bytes.len())]).into_owned()
 }
 
 /// # Safety
 ///
-/// Function deals with C string pointers
-/// which might be null
-
+/// `word1` and `word2` must be valid nul terminated strings
 #[no_mangle]
 pub unsafe extern "C" fn edit_distance(
     word1: *mut c_char,
@@ -70,21 +103,128 @@ pub unsafe extern "C" fn edit_distance(
     len1: c_int,
     len2: c_int,
 ) -> c_int {
-    // The actual edit_distance function
+    // len1 and len2 are byte lengths, the distance itself is counted in characters
+    let word1 = lossy_prefix(word1, cmp::max(len1, 0) as usize);
+    let word2 = lossy_prefix(word2, cmp::max(len2, 0) as usize);
+
+    bounded_edit_distance(&word1, &word2, usize::MAX).unwrap_or_default() as c_int
+}
+
+/// Returns the similarity of two subtitle texts between 0 and 1, or -1 if it is
+/// below `min_similarity` (in which case the comparison stops early)
+///
+/// # Safety
+///
+/// `word1` and `word2` must be null or valid nul terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn text_similarity(
+    word1: *const c_char,
+    word2: *const c_char,
+    min_similarity: c_float,
+) -> c_float {
+    if word1.is_null() || word2.is_null() {
+        return -1.0;
+    }
+    let word1 = ffi::CStr::from_ptr(word1).to_string_lossy();
+    let word2 = ffi::CStr::from_ptr(word2).to_string_lossy();
 
-    let word1_string: &ffi::CStr = ffi::CStr::from_ptr(word1);
-    let word2_string: &ffi::CStr = ffi::CStr::from_ptr(word2);
+    text_similarity_bounded(&word1, &word2, min_similarity).unwrap_or(-1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let len1 = len1 as usize;
-    let len2 = len2 as usize;
+    /// Levenshtein distance with the full table, to check the bounded one against
+    fn edit_distance_reference(word1: &str, word2: &str) -> usize {
+        let chars1: Vec<char> = word1.chars().collect();
+        let chars2: Vec<char> = word2.chars().collect();
+        let mut table = vec![vec![0; chars2.len() + 1]; chars1.len() + 1];
+        for (i, row) in table.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in table[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=chars1.len() {
+            for j in 1..=chars2.len() {
+                let substitute = table[i - 1][j - 1] + usize::from(chars1[i - 1] != chars2[j - 1]);
+                let indel = cmp::min(table[i - 1][j], table[i][j - 1]) + 1;
+                table[i][j] = cmp::min(substitute, indel);
+            }
+        }
+        table[chars1.len()][chars2.len()]
+    }
 
-    let mut dp_array = vec![vec![-1; len2 + 1]; len1 + 1];
+    #[test]
+    fn empty_strings() {
+        assert_eq!(bounded_edit_distance("", "", 0), Some(0));
+        assert_eq!(bounded_edit_distance("", "abc", 3), Some(3));
+        assert_eq!(bounded_edit_distance("abc", "", 3), Some(3));
+        assert_eq!(bounded_edit_distance("abc", "", 2), None);
+    }
 
-    _edit_distance_rec(
-        word1_string.to_bytes(),
-        word2_string.to_bytes(),
-        len1,
-        len2,
-        &mut dp_array,
-    ) as c_int
+    #[test]
+    fn length_difference_above_bound() {
+        assert_eq!(bounded_edit_distance("abcdef", "ab", 3), None);
+        assert_eq!(bounded_edit_distance("ab", "abcdef", 3), None);
+        assert_eq!(bounded_edit_distance("ab", "abcdef", 4), Some(4));
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        assert_eq!(bounded_edit_distance("größe", "grösse", 2), Some(2));
+        assert_eq!(bounded_edit_distance("日本語", "日本人", 1), Some(1));
+        assert_eq!(bounded_edit_distance("日本語", "日本語", 0), Some(0));
+        assert_eq!(bounded_edit_distance("é", "e", 0), None);
+    }
+
+    #[test]
+    fn matches_unbounded_distance() {
+        // xorshift, so the pairs are the same on every run
+        let mut state: u32 = 0x2545_f491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let alphabet = ['a', 'b', 'c', 'ä', '語'];
+        for _ in 0..500 {
+            let mut word = || -> String {
+                let len = next() % 12;
+                (0..len)
+                    .map(|_| alphabet[next() as usize % alphabet.len()])
+                    .collect()
+            };
+            let (word1, word2) = (word(), word());
+            let dist = edit_distance_reference(&word1, &word2);
+            assert_eq!(
+                bounded_edit_distance(&word1, &word2, usize::MAX),
+                Some(dist),
+                "{:?} {:?}",
+                word1,
+                word2
+            );
+            for max_dist in 0..=12 {
+                let expected = if dist <= max_dist { Some(dist) } else { None };
+                assert_eq!(
+                    bounded_edit_distance(&word1, &word2, max_dist),
+                    expected,
+                    "{:?} {:?} within {}",
+                    word1,
+                    word2,
+                    max_dist
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn similarity_rounding() {
+        // 1 of 5 characters differs, which is 80% although (1 - 0.8) * 5 is just below 1 in f32
+        assert_eq!(text_similarity_bounded("abcde", "abcdx", 0.8), Some(0.8));
+        assert_eq!(text_similarity_bounded("abcde", "abcxy", 0.8), None);
+        assert_eq!(text_similarity_bounded("", "", 0.8), Some(1.0));
+    }
 }
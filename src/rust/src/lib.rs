@@ -13,27 +13,26 @@ pub mod bindings {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 pub mod decoder;
+pub mod encoder;
 #[cfg(feature = "hardsubx_ocr")]
 pub mod hardsubx;
 pub mod utils;
 
 #[cfg(windows)]
 use std::os::windows::io::{FromRawHandle, RawHandle};
-use std::{io::Write, os::raw::c_int};
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    os::raw::{c_int, c_void},
+};
 
 use bindings::*;
-use decoder::Dtvcc;
+use decoder::{print_findings, Dtvcc};
 use utils::is_true;
 
 use env_logger::{builder, Target};
 use log::{warn, LevelFilter};
 
-extern "C" {
-    static mut cb_708: c_int;
-    static mut cb_field1: c_int;
-    static mut cb_field2: c_int;
-}
-
 /// Initialize env logger with custom format, using stdout as target
 #[no_mangle]
 pub extern "C" fn ccxr_init_logger() {
@@ -44,6 +43,114 @@ pub extern "C" fn ccxr_init_logger() {
         .init();
 }
 
+/// Create the rust 708 decoder context
+///
+/// The returned pointer is owned by the caller and has to be released with [ccxr_dtvcc_free]
+///
+/// # Safety
+/// opts should not be a null pointer, and its report and timing contexts should outlive the decoder
+#[no_mangle]
+extern "C" fn ccxr_dtvcc_init(opts: *const ccx_decoder_dtvcc_settings) -> *mut c_void {
+    let opts = unsafe { &*opts };
+    let dtvcc = Box::new(Dtvcc::new(opts));
+    Box::into_raw(dtvcc) as *mut c_void
+}
+
+/// Free the rust 708 decoder context created by [ccxr_dtvcc_init]
+#[no_mangle]
+extern "C" fn ccxr_dtvcc_free(dtvcc_rust: *mut c_void) {
+    if dtvcc_rust.is_null() {
+        return;
+    }
+    // Decoder is dropped once it goes out of scope
+    let _dtvcc = unsafe { Box::from_raw(dtvcc_rust as *mut Dtvcc) };
+}
+
+/// Print the statistics of the rust 708 decoder as JSON and the findings of the compliance
+/// mode, if enabled in the settings
+///
+/// The report goes to stderr, so it does not end up in captions written to stdout
+#[no_mangle]
+extern "C" fn ccxr_dtvcc_print_report(dtvcc_rust: *mut c_void) {
+    if dtvcc_rust.is_null() {
+        return;
+    }
+    let dtvcc = unsafe { &mut *(dtvcc_rust as *mut Dtvcc) };
+    if dtvcc.print_json_report {
+        eprintln!("{}", dtvcc.decoding_report().to_json());
+    }
+    if let Some(findings) = dtvcc.compliance_findings() {
+        print_findings(&findings);
+    }
+}
+
+/// Replay the capture given in the settings of the rust 708 decoder, writing the captions with
+/// `encoder`
+///
+/// The capture is only replayed by the first call with an encoder, later calls do nothing. The
+/// 708 data of the input is ignored while a capture is set, so the capture replaces the input.
+/// Returns -1 if the capture could not be read, 0 otherwise.
+///
+/// # Safety
+/// encoder should be null or point to the encoder of the decoder's outputs
+#[no_mangle]
+extern "C" fn ccxr_dtvcc_replay_capture(
+    dtvcc_rust: *mut c_void,
+    encoder: *mut encoder_ctx,
+) -> c_int {
+    let encoder = match unsafe { encoder.as_mut() } {
+        Some(encoder) if !dtvcc_rust.is_null() => encoder,
+        _ => return 0,
+    };
+    let dtvcc = unsafe { &mut *(dtvcc_rust as *mut Dtvcc) };
+    let filename = match &dtvcc.replay_filename {
+        Some(filename) if !dtvcc.replayed => filename.clone(),
+        _ => return 0,
+    };
+    dtvcc.replayed = true;
+    let result = File::open(&filename)
+        .map_err(|err| format!("Unable to open capture file {}: {}", filename, err))
+        .and_then(|file| dtvcc.replay_capture(BufReader::new(file), encoder));
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            warn!("ccxr_dtvcc_replay_capture: {}", err);
+            -1
+        }
+    }
+}
+
+/// Write cc_data to the MCC output of the encoder, for `-out=mcc`
+///
+/// Returns -1 if the output could not be written, 0 otherwise.
+///
+/// # Safety
+/// enc_ctx and dec_ctx should not be null pointers
+/// data should point to cc_data of length cc_count
+#[no_mangle]
+extern "C" fn ccxr_mcc_encode_cc_data(
+    enc_ctx: *mut encoder_ctx,
+    dec_ctx: *mut lib_cc_decode,
+    data: *const ::std::os::raw::c_uchar,
+    cc_count: c_int,
+) -> c_int {
+    if data.is_null() || cc_count <= 0 {
+        return 0;
+    }
+    let cc_data = unsafe { std::slice::from_raw_parts(data, cc_count as usize * 3) };
+    let enc_ctx = unsafe { &*enc_ctx };
+    let dec_ctx = unsafe { &mut *dec_ctx };
+    let dtvcc = unsafe { &mut *(dec_ctx.dtvcc_rust as *mut Dtvcc) };
+    dec_ctx.saw_caption_block = 1;
+    match dtvcc.write_mcc(enc_ctx, dec_ctx.current_frame_rate, cc_data) {
+        Ok(()) => 0,
+        Err(err) => {
+            warn!("ccxr_mcc_encode_cc_data: {}", err);
+            -1
+        }
+    }
+}
+
 /// Process cc_data
 ///
 /// # Safety
@@ -60,13 +167,20 @@ extern "C" fn ccxr_process_cc_data(
         .map(|x| unsafe { *data.add(x as usize) })
         .collect();
     let dec_ctx = unsafe { &mut *dec_ctx };
-    let dtvcc_ctx = unsafe { &mut *dec_ctx.dtvcc };
-    let mut dtvcc = Dtvcc::new(dtvcc_ctx);
+    let dtvcc = unsafe { &mut *(dec_ctx.dtvcc_rust as *mut Dtvcc) };
+    // The encoder can change between calls, so it is always taken from the C context
+    dtvcc.encoder = unsafe { ((*dec_ctx.dtvcc).encoder as *mut encoder_ctx).as_mut() };
+    if dtvcc.replay_filename.is_some() {
+        // The replayed capture replaces the 708 data of the input
+        return 0;
+    }
+    // Blocks keep counting while the frame timestamp does not advance
+    dtvcc.timing.sync_frame();
     for cc_block in cc_data.chunks_exact_mut(3) {
         if !validate_cc_pair(cc_block) {
             continue;
         }
-        let success = do_cb(dec_ctx, &mut dtvcc, cc_block);
+        let success = do_cb(dec_ctx, dtvcc, cc_block);
         if success {
             ret = 0;
         }
@@ -131,8 +245,8 @@ pub fn do_cb(ctx: &mut lib_cc_decode, dtvcc: &mut Dtvcc, cc_block: &[u8]) -> boo
             0 | 1 => {}
             // Type 2 and 3 are for CEA-708 data.
             2 | 3 => {
-                let current_time = unsafe { (*ctx.timing).get_fts(ctx.current_field as u8) };
                 ctx.current_field = 3;
+                let current_time = dtvcc.timing.get_fts();
 
                 // Check whether current time is within start and end bounds
                 if is_true(ctx.extraction_start.set)
@@ -145,10 +259,13 @@ pub fn do_cb(ctx: &mut lib_cc_decode, dtvcc: &mut Dtvcc, cc_block: &[u8]) -> boo
                     ctx.processed_enough = 1;
                 }
 
-                if timeok && ctx.write_format != ccx_output_format::CCX_OF_RAW {
+                // Raw output has no 708 captions, the packets are only assembled to record them
+                let raw = ctx.write_format == ccx_output_format::CCX_OF_RAW;
+                if timeok && (!raw || dtvcc.capture.is_some()) {
+                    dtvcc.decode_packets = !raw;
                     dtvcc.process_cc_data(cc_valid, cc_type, cc_block[1], cc_block[2]);
                 }
-                unsafe { cb_708 += 1 }
+                dtvcc.timing.count_block();
             }
             _ => warn!("Invalid cc_type"),
         }